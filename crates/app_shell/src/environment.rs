@@ -0,0 +1,83 @@
+//! Where the app runs. Containers and chroots usually lack polkit, so pkexec can't be
+//! used there, and inotify on the pacman database is unreliable across bind mounts.
+
+use domain::Privilege;
+use std::{fs, os::unix::fs::MetadataExt, path::Path, process::Command};
+
+pub struct Environment {
+    /// e.g. "docker", "podman", "a chroot"; `None` on a regular system.
+    pub container: Option<String>,
+    pub root: bool,
+    /// A system bus and pkexec are present, so a polkit prompt can work.
+    pub polkit: bool,
+}
+
+impl Environment {
+    pub fn detect() -> Self {
+        Self {
+            container: container(),
+            // /proc/self belongs to the effective user
+            root: fs::metadata("/proc/self").is_ok_and(|m| m.uid() == 0),
+            polkit: Path::new("/run/dbus/system_bus_socket").exists()
+                && Path::new("/usr/bin/pkexec").exists(),
+        }
+    }
+
+    pub fn privilege(&self) -> Privilege {
+        if self.root {
+            Privilege::Direct
+        } else if self.polkit {
+            Privilege::Pkexec
+        } else {
+            Privilege::Sudo
+        }
+    }
+
+    /// Whether to watch the local package database for outside changes.
+    pub fn watch_db(&self) -> bool {
+        self.container.is_none()
+    }
+
+    /// Banner text when running anywhere out of the ordinary.
+    pub fn notice(&self) -> Option<String> {
+        if self.container.is_none() && self.polkit {
+            return None;
+        }
+        let place = match &self.container {
+            Some(c) => format!("Running in {c}"),
+            None => "polkit isn't available".to_string(),
+        };
+        let how = match self.privilege() {
+            Privilege::Direct => "commands run directly as root",
+            Privilege::Sudo => "commands use sudo",
+            Privilege::Pkexec => "commands use pkexec",
+        };
+        let watch = if self.watch_db() {
+            ""
+        } else {
+            "; outside package changes aren't picked up automatically"
+        };
+        Some(format!("{place}: {how}{watch}."))
+    }
+}
+
+fn container() -> Option<String> {
+    if Path::new("/.dockerenv").exists() {
+        return Some("docker".into());
+    }
+    if Path::new("/run/.containerenv").exists() {
+        return Some("podman".into());
+    }
+    // systemd-detect-virt exits 0 when it detects one; --container prints its name
+    let detect = |flag: &str| {
+        Command::new("systemd-detect-virt")
+            .arg(flag)
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+    };
+    if let Some(out) = detect("--container") {
+        return Some(String::from_utf8_lossy(&out.stdout).trim().to_string());
+    }
+    detect("--chroot").map(|_| "a chroot".into())
+}
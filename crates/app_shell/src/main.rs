@@ -1,3 +1,5 @@
+mod environment;
+
 use crossbeam_channel as chan;
 use notify::{
     EventKind, RecursiveMode, Watcher,
@@ -12,36 +14,150 @@ use std::{
 };
 
 use app_ui::{
+    commands::CommandKind,
     root_view,
     state::{Action, Store},
 };
-use backend_aur::AurBackend;
+use backend_aur::{AurBackend, CloneConfig, CloneProtocol, HelperChoice, RpcConfig};
+use backend_flatpak::FlatpakBackend;
 use backend_pacman::PacmanCli;
-use domain::{Executor, PackageBackend};
+use domain::{BackendRegistry, Executor, JobQueue, Source, Verbosity};
 use repose_platform::run_desktop_app;
 
 fn main() -> anyhow::Result<()> {
     env_logger::init();
 
-    let (tx_jobs, rx_jobs) = chan::unbounded();
+    // SOREDOWE_VERBOSITY=quiet|normal|debug; debug also runs pacman with --debug
+    let verbosity = std::env::var("SOREDOWE_VERBOSITY")
+        .ok()
+        .and_then(|v| Verbosity::parse(&v))
+        .unwrap_or_default();
+
+    // AUR maintainers: SOREDOWE_AUR_PROTOCOL=ssh, SOREDOWE_AUR_DEPTH=0 for full history,
+    // SOREDOWE_AUR_PERSISTENT=0 to clone afresh for every install instead of keeping
+    // checkouts, which go under SOREDOWE_AUR_CACHE_DIR if set. Bad values keep the default.
+    let defaults = CloneConfig::default();
+    let clone = CloneConfig {
+        protocol: std::env::var("SOREDOWE_AUR_PROTOCOL")
+            .ok()
+            .and_then(|v| CloneProtocol::parse(&v))
+            .unwrap_or(defaults.protocol),
+        depth: match std::env::var("SOREDOWE_AUR_DEPTH").map(|v| v.trim().parse::<u32>()) {
+            Ok(Ok(d)) => Some(d),
+            _ => defaults.depth,
+        },
+        persistent: match std::env::var("SOREDOWE_AUR_PERSISTENT")
+            .as_deref()
+            .map(str::trim)
+        {
+            Ok("0" | "false" | "no") => false,
+            _ => defaults.persistent,
+        },
+        dir: std::env::var_os("SOREDOWE_AUR_CACHE_DIR")
+            .map(std::path::PathBuf::from)
+            .filter(|p| p.is_absolute())
+            .or(defaults.dir),
+    };
+    // SOREDOWE_AUR_RPC_URL for a mirror or local AUR, SOREDOWE_AUR_TIMEOUT=secs for slow
+    // networks (connect and read alike)
+    let defaults = RpcConfig::default();
+    let timeout = std::env::var("SOREDOWE_AUR_TIMEOUT")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|t| *t > 0)
+        .map(Duration::from_secs);
+    let rpc = RpcConfig {
+        base_url: std::env::var("SOREDOWE_AUR_RPC_URL").unwrap_or(defaults.base_url),
+        connect_timeout: timeout.unwrap_or(defaults.connect_timeout),
+        read_timeout: timeout.unwrap_or(defaults.read_timeout),
+        user_agent: format!("soredowe/{}", env!("CARGO_PKG_VERSION")),
+    };
+    // SOREDOWE_AUR_VCS_CHECK looks upstream for new commits to -git etc. packages when
+    // listing upgrades; it's a download and a git ls-remote per package, so off by default
+    let vcs_check = std::env::var_os("SOREDOWE_AUR_VCS_CHECK").is_some();
+    // SOREDOWE_AUR_HELPER=auto|paru|yay hands AUR installs to that helper when it's installed
+    let helper = std::env::var("SOREDOWE_AUR_HELPER")
+        .ok()
+        .and_then(|v| HelperChoice::parse(&v))
+        .unwrap_or_default();
+
+    let env = environment::Environment::detect();
+    let privilege = env.privilege();
+
+    let jobs = JobQueue::new();
     let (tx_prog, rx_prog) = chan::unbounded();
     let (tx_evt, rx_evt) = chan::unbounded();
     let (tx_watch, rx_watch) = chan::unbounded::<()>();
+    let (tx_tick, rx_tick) = chan::unbounded::<()>();
 
-    let repo_backend: Arc<dyn PackageBackend> = Arc::new(PacmanCli::new());
-    let aur_backend: Arc<dyn PackageBackend> = Arc::new(AurBackend::new());
-    Executor::new(
-        repo_backend,
-        aur_backend,
-        tx_prog.clone(),
-        tx_evt.clone(),
-        rx_jobs,
-    )
-    .run();
+    // Every package source is a registered backend; to add one, implement
+    // `PackageBackend` and register it here under its `Source`.
+    let backends = BackendRegistry::new()
+        .register(
+            Source::Repo,
+            Arc::new(
+                PacmanCli::new()
+                    .with_events(tx_evt.clone())
+                    .with_verbosity(verbosity)
+                    .with_privilege(privilege),
+            ),
+        )
+        .register(
+            Source::Aur,
+            Arc::new(
+                AurBackend::new()
+                    .with_events(tx_evt.clone())
+                    .with_clone(clone)
+                    .with_rpc(rpc)
+                    .with_helper(helper)
+                    .with_vcs_check(vcs_check)
+                    .with_verbosity(verbosity)
+                    .with_privilege(privilege),
+            ),
+        )
+        .register(
+            Source::Flatpak,
+            Arc::new(FlatpakBackend::new().with_verbosity(verbosity)),
+        );
+    // Kept to drop backend caches when the watcher sees the local db change.
+    let registry = backends.clone();
+    let executor = Executor::new(backends, tx_prog.clone(), tx_evt.clone(), jobs.clone()).run();
 
-    let store = Rc::new(Store::new(tx_jobs));
+    // e.g. SOREDOWE_INTERACTIVE=install,upgrade to answer pacman's prompts in a terminal
+    let interactive = std::env::var("SOREDOWE_INTERACTIVE")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|op| match op.trim() {
+            "install" => Some(CommandKind::Install),
+            "remove" => Some(CommandKind::Remove),
+            "upgrade" => Some(CommandKind::Upgrade),
+            _ => None,
+        })
+        .collect();
+    // SOREDOWE_CONFIRM_BUILD_DEPS lists an AUR package's build-only deps before building it;
+    // SOREDOWE_REVIEW_AUR_UPGRADES shows its PKGBUILD changes before upgrading it
+    let mut store = Store::new(jobs.clone())
+        .with_interactive(interactive)
+        .with_verbosity(verbosity)
+        .with_environment_notice(env.notice())
+        .with_build_dep_confirmation(std::env::var_os("SOREDOWE_CONFIRM_BUILD_DEPS").is_some())
+        .with_aur_upgrade_review(std::env::var_os("SOREDOWE_REVIEW_AUR_UPGRADES").is_some());
+    // SOREDOWE_NO_CACHE keeps results off disk
+    let cache =
+        app_ui::cache::default_path().filter(|_| std::env::var_os("SOREDOWE_NO_CACHE").is_none());
+    // Before the cache, whose results are shown through the saved filters
+    if let Some(path) = app_ui::prefs::default_path() {
+        store = store.with_prefs(path);
+    }
+    if let Some(path) = cache {
+        store = store.with_cache(path);
+    }
+    let store = Rc::new(store);
+    // Know up front whether a single install would be a partial upgrade.
+    store.dispatch(Action::CheckUpgrades);
 
-    {
+    // SOREDOWE_NO_WATCH turns the watcher off; it's off by default in containers
+    if env.watch_db() && std::env::var_os("SOREDOWE_NO_WATCH").is_none() {
         let tx_watch = tx_watch.clone();
         spawn(move || {
             // Callback-style watcher; coalesce by just sending a signal.
@@ -114,7 +230,25 @@ fn main() -> anyhow::Result<()> {
         });
     }
 
-    run_desktop_app(move |_sched| {
+    // SOREDOWE_UPGRADE_CHECK_MINUTES sets how often upgrades are re-listed in the
+    // background (default every 6 hours); 0 turns it off
+    let check_minutes = std::env::var("SOREDOWE_UPGRADE_CHECK_MINUTES")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(360);
+    if check_minutes > 0 {
+        let interval = Duration::from_secs(check_minutes * 60);
+        spawn(move || {
+            loop {
+                sleep(interval);
+                if tx_tick.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    let res = run_desktop_app(move |sched| {
         while let Ok(p) = rx_prog.try_recv() {
             store.dispatch(Action::Progress(p));
         }
@@ -126,8 +260,22 @@ fn main() -> anyhow::Result<()> {
             saw = true;
         }
         if saw {
+            for (_, b) in registry.iter() {
+                b.invalidate_installed_cache();
+            }
             store.dispatch(Action::Event(domain::Event::SystemChanged));
         }
-        root_view(store.clone())
-    })
+        let mut due = false;
+        while rx_tick.try_recv().is_ok() {
+            due = true;
+        }
+        if due {
+            store.dispatch(Action::CheckUpgrades);
+        }
+        root_view(store.clone(), sched.size.0)
+    });
+
+    // The window is gone: skip queued jobs but let a running transaction finish.
+    executor.shutdown();
+    res
 }
@@ -0,0 +1,99 @@
+use domain::{PackageDetails, PackageId, PackageSummary, Source};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+// Oldest entries are dropped first.
+const MAX_SEARCHES: usize = 20;
+const MAX_DETAILS: usize = 100;
+
+/// Last known search results and details, persisted so the app has something to show when
+/// offline.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Cache {
+    /// Most recent first.
+    searches: Vec<CachedSearch>,
+    /// Most recent first.
+    details: Vec<(SystemTime, PackageDetails)>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CachedSearch {
+    pub query: String,
+    pub at: SystemTime,
+    /// Unfiltered, as the backends returned them.
+    pub items: Vec<PackageSummary>,
+}
+
+/// `$XDG_CACHE_HOME/soredowe/results.json`, falling back to `~/.cache`.
+pub fn default_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|h| Path::new(&h).join(".cache")))?;
+    Some(base.join("soredowe").join("results.json"))
+}
+
+// Custom sources can't be serialized (see `Source::Custom`)
+fn cacheable(id: &PackageId) -> bool {
+    !matches!(id.source, Source::Custom(_))
+}
+
+impl Cache {
+    /// A missing or unreadable cache is just empty.
+    pub fn load(path: &Path) -> Self {
+        fs::read(path)
+            .ok()
+            .and_then(|b| serde_json::from_slice(&b).ok())
+            .unwrap_or_default()
+    }
+
+    /// Writes through a temp file so a crash mid-write can't leave a truncated cache.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_vec(self).map_err(|e| e.to_string())?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, path).map_err(|e| e.to_string())
+    }
+
+    pub fn latest_search(&self) -> Option<&CachedSearch> {
+        self.searches.first()
+    }
+
+    pub fn search(&self, query: &str) -> Option<&CachedSearch> {
+        self.searches.iter().find(|s| s.query == query)
+    }
+
+    pub fn details(&self, id: &PackageId) -> Option<&(SystemTime, PackageDetails)> {
+        self.details.iter().find(|(_, d)| d.summary.id == *id)
+    }
+
+    pub fn put_search(&mut self, query: &str, items: &[PackageSummary]) {
+        self.searches.retain(|s| s.query != query);
+        self.searches.insert(
+            0,
+            CachedSearch {
+                query: query.to_string(),
+                at: SystemTime::now(),
+                items: items.iter().filter(|p| cacheable(&p.id)).cloned().collect(),
+            },
+        );
+        self.searches.truncate(MAX_SEARCHES);
+    }
+
+    pub fn put_details(&mut self, details: &PackageDetails) {
+        if !cacheable(&details.summary.id) {
+            return;
+        }
+        self.details
+            .retain(|(_, d)| d.summary.id != details.summary.id);
+        self.details.insert(0, (SystemTime::now(), details.clone()));
+        self.details.truncate(MAX_DETAILS);
+    }
+}
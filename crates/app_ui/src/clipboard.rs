@@ -0,0 +1,21 @@
+use std::cell::RefCell;
+
+thread_local! {
+    // On X11 the selection is served by the owning process, so the handle must outlive the
+    // copy call or the text vanishes as soon as it's dropped.
+    static CLIPBOARD: RefCell<Option<arboard::Clipboard>> = const { RefCell::new(None) };
+}
+
+/// Puts `text` on the system clipboard.
+pub fn copy(text: &str) -> Result<(), String> {
+    CLIPBOARD.with(|cb| {
+        let mut cb = cb.borrow_mut();
+        if cb.is_none() {
+            *cb = Some(arboard::Clipboard::new().map_err(|e| e.to_string())?);
+        }
+        cb.as_mut()
+            .unwrap()
+            .set_text(text.to_string())
+            .map_err(|e| e.to_string())
+    })
+}
@@ -0,0 +1,92 @@
+use domain::{PackageId, Source};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommandKind {
+    Install,
+    Remove,
+    Upgrade,
+    /// A full system upgrade; its `ids` are what it leaves out.
+    UpgradeAll,
+}
+
+/// Terminal equivalent of running `kind` on `ids`, mirroring what the backends execute.
+/// Repo packages are batched into one pacman call; each AUR package gets its own clone and
+/// build; flatpak apps are batched per command. One command per line.
+pub fn shell_command(kind: CommandKind, ids: &[PackageId]) -> String {
+    let names = |source: Source| -> Vec<&str> {
+        ids.iter()
+            .filter(|id| id.source == source)
+            .map(|id| id.name.as_str())
+            .collect()
+    };
+    // A full upgrade is only pacman's side: the part with prompts worth a terminal
+    if kind == CommandKind::UpgradeAll {
+        let ignore = names(Source::Repo);
+        return if ignore.is_empty() {
+            "sudo pacman -Syu".to_string()
+        } else {
+            format!("sudo pacman -Syu --ignore {}", ignore.join(","))
+        };
+    }
+    let mut lines = Vec::new();
+
+    let repo = names(Source::Repo);
+    if !repo.is_empty() {
+        let args = match kind {
+            CommandKind::Remove => "-Rns",
+            _ => "-S --needed",
+        };
+        lines.push(format!("sudo pacman {args} {}", repo.join(" ")));
+    }
+
+    for name in names(Source::Aur) {
+        match kind {
+            CommandKind::Remove => lines.push(format!("sudo pacman -Rns {name}")),
+            _ => lines.push(format!(
+                "git clone https://aur.archlinux.org/{name}.git && (cd {name} && makepkg -si)"
+            )),
+        }
+    }
+
+    let flatpak = names(Source::Flatpak);
+    if !flatpak.is_empty() {
+        let cmd = match kind {
+            CommandKind::Install => "install --user flathub",
+            CommandKind::Remove => "uninstall",
+            _ => "update",
+        };
+        lines.push(format!("flatpak {cmd} {}", flatpak.join(" ")));
+    }
+
+    for id in ids {
+        if let Source::Custom(label) = id.source {
+            lines.push(format!("# {label}: no terminal equivalent for {}", id.name));
+        }
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repo(name: &str) -> PackageId {
+        PackageId {
+            name: name.into(),
+            source: Source::Repo,
+        }
+    }
+
+    #[test]
+    fn upgrade_all_ignores_what_it_leaves_out() {
+        assert_eq!(
+            shell_command(CommandKind::UpgradeAll, &[]),
+            "sudo pacman -Syu"
+        );
+        assert_eq!(
+            shell_command(CommandKind::UpgradeAll, &[repo("linux"), repo("mesa")]),
+            "sudo pacman -Syu --ignore linux,mesa"
+        );
+    }
+}
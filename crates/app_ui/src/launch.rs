@@ -0,0 +1,112 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+const APPLICATIONS_DIR: &str = "/usr/share/applications";
+
+/// Desktop entries among a package's files, i.e. the apps it can launch.
+pub fn desktop_entries(files: &[PathBuf]) -> Vec<&Path> {
+    files
+        .iter()
+        .map(PathBuf::as_path)
+        .filter(|f| {
+            f.starts_with(APPLICATIONS_DIR) && f.extension().is_some_and(|x| x == "desktop")
+        })
+        .collect()
+}
+
+/// Starts the app behind a desktop entry, detached from us. Prefers `gio launch`, which
+/// honours the full spec; without it, runs the entry's Exec line directly.
+pub fn launch(entry: &Path) -> Result<(), String> {
+    match spawn(Command::new("gio").arg("launch").arg(entry)) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let exec = exec_line(entry)?;
+            // Field codes (%f, %U, ...) stand for files/URLs we never pass. Quoted arguments
+            // aren't unescaped; gio covers those.
+            let mut argv = exec
+                .split_whitespace()
+                .filter(|a| !(a.len() == 2 && a.starts_with('%')));
+            let prog = argv.next().ok_or("empty Exec line")?;
+            spawn(Command::new(prog).args(argv)).map_err(|e| e.to_string())
+        }
+        r => r.map_err(|e| e.to_string()),
+    }
+}
+
+/// Opens `url` in the default browser, detached. Only http(s) URLs: homepages come from
+/// package metadata, and xdg-open would happily run a `file:` or `-`-prefixed one.
+pub fn open_url(url: &str) -> Result<(), String> {
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return Err(format!("not a web address: {url}"));
+    }
+    spawn(Command::new("xdg-open").arg(url)).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => "xdg-open not found".to_string(),
+        _ => e.to_string(),
+    })
+}
+
+/// Emulators tried in order when `$TERMINAL` isn't set, with the flag that precedes the
+/// command to run.
+const TERMINALS: &[(&str, &[&str])] = &[
+    ("kgx", &["-e"]),
+    ("gnome-terminal", &["--"]),
+    ("konsole", &["-e"]),
+    ("xfce4-terminal", &["-x"]),
+    ("alacritty", &["-e"]),
+    ("kitty", &[]),
+    ("foot", &[]),
+    ("wezterm", &["start", "--"]),
+    ("xterm", &["-e"]),
+];
+
+/// Runs the shell `script` interactively in `$TERMINAL` or the first emulator found, from the
+/// temp dir so AUR clones don't land in ours. The window stays open until Enter so the
+/// outcome can be read.
+pub fn run_in_terminal(script: &str) -> Result<(), String> {
+    let script = format!("{script}\nprintf '\\nPress Enter to close '; read _");
+    let configured = std::env::var("TERMINAL").ok().filter(|t| !t.is_empty());
+    let candidates: Vec<(&str, &[&str])> = match configured.as_deref() {
+        // Most emulators, and the convention $TERMINAL users expect, take -e
+        Some(term) => vec![(term, &["-e"])],
+        None => TERMINALS.to_vec(),
+    };
+    for (term, flags) in candidates {
+        let mut cmd = Command::new(term);
+        cmd.args(flags)
+            .args(["sh", "-c", &script])
+            .current_dir(std::env::temp_dir());
+        match spawn(&mut cmd) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            r => return r.map_err(|e| format!("{term}: {e}")),
+        }
+    }
+    Err("no terminal emulator found; set $TERMINAL".into())
+}
+
+fn spawn(cmd: &mut Command) -> std::io::Result<()> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    // Reap it whenever it exits so it doesn't linger as a zombie
+    std::thread::spawn(move || {
+        let _ = child.wait();
+    });
+    Ok(())
+}
+
+fn exec_line(entry: &Path) -> Result<String, String> {
+    let text = std::fs::read_to_string(entry).map_err(|e| e.to_string())?;
+    // Only the main group; desktop actions carry Exec keys of their own
+    let mut in_main = false;
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_main = line == "[Desktop Entry]";
+        } else if let Some(v) = line.strip_prefix("Exec=").filter(|_| in_main) {
+            return Ok(v.to_string());
+        }
+    }
+    Err(format!("no Exec line in {}", entry.display()))
+}
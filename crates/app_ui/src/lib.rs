@@ -1,5 +1,12 @@
-use crate::state::{Action, SortMode, Store};
-use domain::{PackageSummary, Source};
+use crate::commands::CommandKind;
+use crate::state::{
+    Action, ConfirmKind, JobRecord, SLOW_DOWNLOAD_RATE, Screen, SortMode, Store, change_highlight,
+};
+use domain::{
+    AurVariant, BuildFile, CacheCleanPlan, HookOutcome, HookRun, Job, JobKind, JobPayload,
+    LogEntry, PKGBUILD_DIFF, PackageDetails, PackageId, PackageSummary, RemovePreview, Source,
+    Stage, TransactionPlan,
+};
 use repose_core::*;
 use repose_ui::{
     lazy::{LazyColumn, LazyColumnState},
@@ -7,6 +14,12 @@ use repose_ui::{
 };
 use std::{cell::RefCell, rc::Rc};
 
+pub mod cache;
+pub mod clipboard;
+pub mod commands;
+pub mod launch;
+pub mod notification;
+pub mod prefs;
 pub mod state;
 
 // Simple badges
@@ -22,6 +35,32 @@ fn badge(text: &str, bg: Color) -> View {
         )
 }
 
+fn source_badge(source: Source) -> View {
+    match source {
+        Source::Repo => badge("Repo", Color::from_hex("#2D6A4F")),
+        Source::Aur => badge("AUR", Color::from_hex("#6B46C1")),
+        Source::Flatpak => badge("Flatpak", Color::from_hex("#3B6EA5")),
+        Source::Custom(name) => badge(name, Color::from_hex("#8A6D3B")),
+    }
+}
+
+/// Which sync repository a repo package is from; third-party ones stand out.
+fn repo_badge(repo: Option<&str>) -> View {
+    match repo {
+        Some(r @ ("core" | "extra" | "multilib")) => badge(r, Color::from_hex("#3F5F4F")),
+        Some(r) => badge(r, Color::from_hex("#8A6D3B")),
+        None => Box(Modifier::new()),
+    }
+}
+
+fn variant_badge(variant: AurVariant) -> View {
+    match variant {
+        AurVariant::Vcs => badge("VCS", Color::from_hex("#9A5B13")),
+        AurVariant::Prebuilt => badge("prebuilt", Color::from_hex("#4A5568")),
+        AurVariant::Regular => Box(Modifier::new()),
+    }
+}
+
 // Filter chip
 fn chip(label: &str, on: bool, on_toggle: impl Fn() + 'static) -> View {
     Button(label, on_toggle).modifier(
@@ -43,17 +82,59 @@ fn separator() -> View {
         .background(Color::from_hex("#2A2A2A")))
 }
 
+// Linear mix from `a` (t = 0) to `b` (t = 1)
+fn blend(a: Color, b: Color, t: f32) -> Color {
+    let mix = |x: u8, y: u8| (x as f32 + (y as f32 - x as f32) * t.clamp(0.0, 1.0)) as u8;
+    Color::from_rgba(mix(a.0, b.0), mix(a.1, b.1), mix(a.2, b.2), mix(a.3, b.3))
+}
+
+/// A row's toggles in the upgrades view.
+#[derive(Clone, Copy)]
+struct UpgradeToggles {
+    skip_once: bool,
+    /// On the persistent ignore list, so "Upgrade all" always leaves it out.
+    ignored: bool,
+}
+
 // Package row
-fn pkg_row(store: Rc<Store>, pkg: PackageSummary, selected: bool, upgrades_mode: bool) -> View {
+fn pkg_row(
+    store: Rc<Store>,
+    pkg: PackageSummary,
+    selected: bool,
+    // Set in the upgrades view
+    upgrade: Option<UpgradeToggles>,
+    special_variants: bool,
+    highlight: f32,
+    // The selection-mode checkbox; `None` hides it
+    checked: Option<bool>,
+) -> View {
+    let upgrades_mode = upgrade.is_some();
+    let ignored = upgrade.is_some_and(|u| u.ignored);
     let is_aur = pkg.id.source == Source::Aur;
+    // VCS packages always build the latest commit, so "upgrade" really means "rebuild"
+    let rebuildable = special_variants && pkg.variant == AurVariant::Vcs;
+    // Filled in lazily by the details prefetch when the search result had none
+    let description = Some(pkg.description.clone())
+        .filter(|d| !d.is_empty())
+        .or_else(|| store.prefetched_description(&pkg.id));
     Row(Modifier::new()
         .padding(10.0)
         .background(if selected {
             Color::from_hex("#244E74")
+        } else if ignored {
+            Color::from_hex("#2A2622")
         } else if is_aur {
-            Color::from_hex("#1A2030")
+            blend(
+                Color::from_hex("#1A2030"),
+                Color::from_hex("#2F5D3A"),
+                highlight,
+            )
         } else {
-            Color::from_hex("#1E1E1E")
+            blend(
+                Color::from_hex("#1E1E1E"),
+                Color::from_hex("#2F5D3A"),
+                highlight,
+            )
         })
         .border(1.0, Color::from_hex("#333333"), 8.0)
         .clip_rounded(8.0)
@@ -64,45 +145,162 @@ fn pkg_row(store: Rc<Store>, pkg: PackageSummary, selected: bool, upgrades_mode:
             move |_| store.dispatch(Action::Select(id.clone()))
         }))
     .child((
+        match checked {
+            Some(on) => Checkbox(on, "", {
+                let store = store.clone();
+                let id = pkg.id.clone();
+                move |_| store.dispatch(Action::ToggleChecked(id.clone()))
+            })
+            .modifier(Modifier::new().padding(4.0).semantics("Select package")),
+            _ => Box(Modifier::new()),
+        },
         Column(Modifier::new().flex_grow(1.0)).child((
             Row(Modifier::new()).child((
                 Text(pkg.id.name.clone()).modifier(Modifier::new().padding(2.0)),
-                if is_aur {
-                    badge("AUR", Color::from_hex("#6B46C1"))
+                Button("Copy", {
+                    let store = store.clone();
+                    let name = pkg.id.name.clone();
+                    move || store.dispatch(Action::CopyName(name.clone()))
+                })
+                .modifier(
+                    Modifier::new()
+                        .background(Color::TRANSPARENT)
+                        .padding(2.0)
+                        .semantics("Copy package name"),
+                ),
+                Text(match (&pkg.installed_version, pkg.group_members) {
+                    (Some(old), _) if upgrades_mode => format!("{old} → {}", pkg.version),
+                    (_, Some(n)) => format!("group · {n} packages"),
+                    _ => pkg.version.clone(),
+                })
+                .size(12.0)
+                .color(Color::from_hex("#888888"))
+                .max_lines(1)
+                .modifier(Modifier::new().padding(2.0)),
+                Row(Modifier::new()).child((
+                    source_badge(pkg.id.source),
+                    repo_badge(pkg.repo.as_deref()),
+                    match &pkg.provides_match {
+                        Some(name) => {
+                            badge(&format!("provides: {name}"), Color::from_hex("#5B4A8B"))
+                        }
+                        None => Box(Modifier::new()),
+                    },
+                )),
+                if special_variants {
+                    variant_badge(pkg.variant)
                 } else {
-                    badge("Repo", Color::from_hex("#2D6A4F"))
+                    Box(Modifier::new())
                 },
                 if pkg.installed {
                     badge("Installed", Color::from_hex("#4B5563"))
                 } else {
                     Box(Modifier::new())
                 },
+                if pkg.out_of_date.is_some() {
+                    badge("Out of date", Color::from_hex("#8B2C2C"))
+                } else {
+                    Box(Modifier::new())
+                },
+                if upgrades_mode && pkg.variant == AurVariant::Vcs {
+                    badge("New commits", Color::from_hex("#2C5F8B"))
+                } else {
+                    Box(Modifier::new())
+                },
             )),
-            Text(pkg.description.clone())
-                .size(12.0)
-                .color(Color::from_hex("#AAAAAA"))
-                .max_lines(1)
-                .overflow_ellipsize()
-                .modifier(Modifier::new().padding(2.0).flex_grow(1.0).max_width(500.0)),
+            Text(
+                description
+                    .clone()
+                    .unwrap_or_else(|| "No description".to_string()),
+            )
+            .size(12.0)
+            .color(if description.is_some() {
+                Color::from_hex("#AAAAAA")
+            } else {
+                Color::from_hex("#666666")
+            })
+            .max_lines(1)
+            .overflow_ellipsize()
+            .modifier(Modifier::new().padding(2.0).flex_grow(1.0).max_width(500.0)),
         )),
         if upgrades_mode {
-            Button("Upgrade", {
-                let store = store.clone();
-                let id = pkg.id.clone();
-                move || store.dispatch(Action::Upgrade(id.clone()))
-            })
+            Row(Modifier::new()).child((
+                chip("Skip once", upgrade.is_some_and(|u| u.skip_once), {
+                    let store = store.clone();
+                    let id = pkg.id.clone();
+                    move || store.dispatch(Action::ToggleSkipOnce(id.clone()))
+                }),
+                chip(if ignored { "Unignore" } else { "Ignore" }, ignored, {
+                    let store = store.clone();
+                    let name = pkg.id.name.clone();
+                    move || store.dispatch(Action::ToggleIgnore(name.clone()))
+                }),
+                Button(if rebuildable { "Rebuild" } else { "Upgrade" }, {
+                    let store = store.clone();
+                    let id = pkg.id.clone();
+                    move || store.dispatch(Action::Upgrade(id.clone()))
+                }),
+            ))
         } else {
-            Button(if pkg.installed { "Remove" } else { "Install" }, {
-                let store = store.clone();
-                let id = pkg.id.clone();
-                move || {
-                    if pkg.installed {
-                        store.dispatch(Action::Remove(id.clone()))
-                    } else {
-                        store.dispatch(Action::Install(id.clone()))
+            Row(Modifier::new()).child((
+                if rebuildable && pkg.installed {
+                    Button("Rebuild", {
+                        let store = store.clone();
+                        let id = pkg.id.clone();
+                        move || store.dispatch(Action::Upgrade(id.clone()))
+                    })
+                } else {
+                    Box(Modifier::new())
+                },
+                Button(install_label(&pkg), {
+                    let store = store.clone();
+                    let id = pkg.id.clone();
+                    move || {
+                        if pkg.installed {
+                            store.dispatch(Action::Confirm(id.clone(), ConfirmKind::Remove))
+                        } else {
+                            store.dispatch(Action::Install(id.clone()))
+                        }
                     }
-                }
-            })
+                }),
+            ))
+        },
+    ))
+}
+
+/// The install/remove button's label; groups say so, as they act on all their members.
+fn install_label(pkg: &PackageSummary) -> &'static str {
+    match (pkg.installed, pkg.group_members.is_some()) {
+        (true, false) => "Remove",
+        (true, true) => "Remove group",
+        (false, false) => "Install",
+        (false, true) => "Install group",
+    }
+}
+
+/// Header controls for selection mode: the toggle, then batch actions once something is
+/// checked.
+fn selection_controls(store: Rc<Store>, on: bool, count: usize) -> View {
+    Row(Modifier::new()).child((
+        chip("Select", on, {
+            let store = store.clone();
+            move || store.dispatch(Action::ToggleSelectionMode)
+        }),
+        if on && count > 0 {
+            Row(Modifier::new()).child((
+                Button(format!("Install selected ({count})"), {
+                    let store = store.clone();
+                    move || store.dispatch(Action::InstallChecked)
+                })
+                .modifier(Modifier::new().padding(4.0)),
+                Button(format!("Remove selected ({count})"), {
+                    let store = store.clone();
+                    move || store.dispatch(Action::RemoveChecked)
+                })
+                .modifier(Modifier::new().padding(4.0)),
+            ))
+        } else {
+            Box(Modifier::new())
         },
     ))
 }
@@ -110,15 +308,30 @@ fn pkg_row(store: Rc<Store>, pkg: PackageSummary, selected: bool, upgrades_mode:
 // Details card (right pane)
 fn details_card(store: Rc<Store>) -> View {
     let s = store.state.get();
-    let results = s.results.clone();
     let selected = s.selected.clone();
     let Some(id) = &s.selected else {
         return Column(Modifier::new().padding(16.0))
             .child(Text("Select a package to see details").color(Color::from_hex("#AAAAAA")));
     };
-    // Find summary in current results (lightweight until details endpoint is used)
-    let pkg = results.into_iter().find(|p| &p.id == id);
+    // The list row, or the fetched details for packages selected from elsewhere
+    let pkg = s.results.iter().find(|p| &p.id == id).cloned().or_else(|| {
+        s.details
+            .as_ref()
+            .filter(|d| d.summary.id == *id)
+            .map(|d| d.summary.clone())
+    });
+    let files = s
+        .files
+        .as_ref()
+        .filter(|(f, _)| f == id)
+        .map(|(_, f)| f.as_slice());
+    let history = s
+        .history
+        .as_ref()
+        .filter(|(h, _)| h == id)
+        .map(|(_, h)| h.as_slice());
     if let Some(pkg) = pkg {
+        let details = s.details.as_ref().filter(|d| d.summary.id == pkg.id);
         Column(
             Modifier::new()
                 .padding(16.0)
@@ -129,43 +342,115 @@ fn details_card(store: Rc<Store>) -> View {
         .child((
             Row(Modifier::new().align_self_center()).child((
                 Text(pkg.id.name.clone()).size(18.0),
-                if pkg.id.source == Source::Aur {
-                    badge("AUR", Color::from_hex("#6B46C1"))
+                Row(Modifier::new()).child((
+                    source_badge(pkg.id.source),
+                    repo_badge(
+                        pkg.repo
+                            .as_deref()
+                            .or(details.and_then(|d| d.summary.repo.as_deref())),
+                    ),
+                )),
+                if s.special_variants {
+                    variant_badge(pkg.variant)
                 } else {
-                    badge("Repo", Color::from_hex("#2D6A4F"))
+                    Box(Modifier::new())
                 },
                 if pkg.installed {
                     badge("Installed", Color::from_hex("#4B5563"))
                 } else {
                     Box(Modifier::new())
                 },
+                if pkg.out_of_date.is_some() {
+                    badge("Out of date", Color::from_hex("#8B2C2C"))
+                } else {
+                    Box(Modifier::new())
+                },
+            )),
+            // Fetched details win; upgrade listings, for one, carry no description
+            Text(
+                details
+                    .map(|d| d.summary.description.clone())
+                    .filter(|d| !d.is_empty())
+                    .unwrap_or_else(|| pkg.description.clone()),
+            )
+            .max_lines(10)
+            .overflow_clip()
+            .color(Color::from_hex("#BBBBBB"))
+            .modifier(Modifier::new().padding(6.0)),
+            match details {
+                Some(d) => details_info(store.clone(), d),
+                None if s.details_loading(id) => Text("Loading details…")
+                    .size(12.0)
+                    .color(Color::from_hex("#888888"))
+                    .modifier(Modifier::new().padding(6.0)),
+                None => Box(Modifier::new()),
+            },
+            Column(Modifier::new()).child((
+                match details.filter(|d| !d.conflicts.is_empty()) {
+                    Some(d) => Text(match d.installed_conflicts.as_slice() {
+                        [] => format!("Conflicts with: {}", d.conflicts.join(", ")),
+                        gone => format!(
+                            "Conflicts with: {} (installing removes {})",
+                            d.conflicts.join(", "),
+                            gone.join(", ")
+                        ),
+                    })
+                    .size(12.0)
+                    .color(Color::from_hex("#F87171"))
+                    .modifier(Modifier::new().padding(6.0)),
+                    None => Box(Modifier::new()),
+                },
+                match details.filter(|d| !d.replaces.is_empty()) {
+                    Some(d) => Text(format!("Replaces: {}", d.replaces.join(", ")))
+                        .size(12.0)
+                        .color(Color::from_hex("#AAAAAA"))
+                        .modifier(Modifier::new().padding(6.0)),
+                    None => Box(Modifier::new()),
+                },
+                match details.and_then(|d| d.architecture.as_deref()) {
+                    Some(arch) => Text(format!("Architecture: {}", arch_label(arch)))
+                        .size(12.0)
+                        .color(Color::from_hex("#AAAAAA"))
+                        .modifier(Modifier::new().padding(6.0)),
+                    None => Box(Modifier::new()),
+                },
+                match s.sibling.as_ref().filter(|(x, _)| x == id) {
+                    Some((_, Some(other))) => sibling_row(store.clone(), other),
+                    _ => Box(Modifier::new()),
+                },
             )),
-            Text(pkg.description.clone())
-                .max_lines(10)
-                .overflow_clip()
-                .color(Color::from_hex("#BBBBBB"))
-                .modifier(Modifier::new().padding(6.0)),
             Row(Modifier::new().padding(8.0)).child((
                 Spacer(),
                 if s.in_upgrades_view {
-                    Button("Upgrade", {
+                    let rebuild = s.special_variants && pkg.variant == AurVariant::Vcs;
+                    Button(if rebuild { "Rebuild" } else { "Upgrade" }, {
                         let store = store.clone();
                         let id = pkg.id.clone();
                         move || store.dispatch(Action::Upgrade(id.clone()))
                     })
                 } else {
-                    Button(if pkg.installed { "Remove" } else { "Install" }, {
+                    Button(install_label(&pkg), {
                         let store = store.clone();
                         let id = pkg.id.clone();
                         move || {
                             if pkg.installed {
-                                store.dispatch(Action::Remove(id.clone()))
+                                store.dispatch(Action::Confirm(id.clone(), ConfirmKind::Remove))
                             } else {
                                 store.dispatch(Action::Install(id.clone()))
                             }
                         }
                     })
                 },
+                if pkg.id.source == Source::Aur && !pkg.installed && !s.in_upgrades_view {
+                    Button("Review PKGBUILD", {
+                        let store = store.clone();
+                        let id = pkg.id.clone();
+                        move || store.dispatch(Action::ReviewBuildFiles(id.clone()))
+                    })
+                    .modifier(Modifier::new().padding(4.0))
+                } else {
+                    Box(Modifier::new())
+                },
                 Spacer(),
                 Button("Clear selection", {
                     let store = store.clone();
@@ -173,6 +458,198 @@ fn details_card(store: Rc<Store>) -> View {
                 }),
                 Spacer(),
             )),
+            // Secondary actions
+            Row(Modifier::new().padding(4.0)).child((
+                Spacer(),
+                match files
+                    .and_then(|f| launch::desktop_entries(f).first().map(|e| e.to_path_buf()))
+                {
+                    Some(entry) => Button("Launch", {
+                        let store = store.clone();
+                        move || store.dispatch(Action::Launch(entry.clone()))
+                    })
+                    .modifier(Modifier::new().padding(4.0)),
+                    None => Box(Modifier::new()),
+                },
+                // Repo packages can be listed before install, from the files database
+                if pkg.installed || (pkg.id.source == Source::Repo && pkg.group_members.is_none()) {
+                    Button(
+                        if s.show_files {
+                            "Hide files"
+                        } else {
+                            "Show files"
+                        },
+                        {
+                            let store = store.clone();
+                            let id = pkg.id.clone();
+                            move || store.dispatch(Action::ShowFiles(id.clone()))
+                        },
+                    )
+                    .modifier(Modifier::new().padding(4.0))
+                } else {
+                    Box(Modifier::new())
+                },
+                if pkg.id.source == Source::Aur {
+                    Button(
+                        if s.show_history {
+                            "Hide commit log"
+                        } else {
+                            "Commit log"
+                        },
+                        {
+                            let store = store.clone();
+                            let id = pkg.id.clone();
+                            move || store.dispatch(Action::ShowHistory(id.clone()))
+                        },
+                    )
+                    .modifier(Modifier::new().padding(4.0))
+                } else {
+                    Box(Modifier::new())
+                },
+                if pkg.installed && pkg.id.source == Source::Repo {
+                    Row(Modifier::new()).child((
+                        Button(
+                            if s.show_who_requires {
+                                "Hide required by"
+                            } else {
+                                "Required by…"
+                            },
+                            {
+                                let store = store.clone();
+                                let id = pkg.id.clone();
+                                move || store.dispatch(Action::ShowWhoRequires(id.clone()))
+                            },
+                        )
+                        .modifier(Modifier::new().padding(4.0)),
+                        Button(
+                            if s.show_cached_versions {
+                                "Hide older versions"
+                            } else {
+                                "Older versions"
+                            },
+                            {
+                                let store = store.clone();
+                                let id = pkg.id.clone();
+                                move || store.dispatch(Action::ShowCachedVersions(id.clone()))
+                            },
+                        )
+                        .modifier(Modifier::new().padding(4.0)),
+                    ))
+                } else {
+                    Box(Modifier::new())
+                },
+                Button("Refresh details", {
+                    let store = store.clone();
+                    let id = pkg.id.clone();
+                    move || store.dispatch(Action::RefreshDetails(id.clone()))
+                })
+                .modifier(Modifier::new().padding(4.0)),
+                {
+                    let kind = if s.in_upgrades_view {
+                        CommandKind::Upgrade
+                    } else if pkg.installed {
+                        CommandKind::Remove
+                    } else {
+                        CommandKind::Install
+                    };
+                    let ids = vec![pkg.id.clone()];
+                    Row(Modifier::new()).child((
+                        Button("Copy command", {
+                            let store = store.clone();
+                            let ids = ids.clone();
+                            move || store.dispatch(Action::CopyCommand(kind, ids.clone()))
+                        })
+                        .modifier(Modifier::new().padding(4.0)),
+                        // Escape hatch for provider choices and conflicts --noconfirm can't answer
+                        if matches!(pkg.id.source, Source::Repo | Source::Aur) {
+                            Button("Run in terminal", {
+                                let store = store.clone();
+                                move || store.dispatch(Action::RunInTerminal(kind, ids.clone()))
+                            })
+                            .modifier(Modifier::new().padding(4.0))
+                        } else {
+                            Box(Modifier::new())
+                        },
+                    ))
+                },
+                Spacer(),
+            )),
+            Column(Modifier::new()).child((
+                match files.filter(|_| s.show_files) {
+                    Some([]) => Text("No files listed")
+                        .size(11.0)
+                        .color(Color::from_hex("#888888"))
+                        .modifier(Modifier::new().padding(6.0)),
+                    Some(f) => LazyColumn(
+                        // Lossy for display only; actions keep the raw paths
+                        f.iter()
+                            .map(|p| p.to_string_lossy().into_owned())
+                            .collect::<Vec<_>>(),
+                        16.0,
+                        remember_with_key("files_scroll", LazyColumnState::new),
+                        Modifier::new().fill_max_width().height(240.0),
+                        |path: String, _| {
+                            Text(path)
+                                .size(11.0)
+                                .color(Color::from_hex("#B0B0B0"))
+                                .max_lines(1)
+                                .overflow_ellipsize()
+                                .modifier(Modifier::new().padding(1.0))
+                        },
+                    ),
+                    None => Box(Modifier::new()),
+                },
+                match history.filter(|_| s.show_history) {
+                    Some([]) => Text("No commits found")
+                        .size(11.0)
+                        .color(Color::from_hex("#888888"))
+                        .modifier(Modifier::new().padding(6.0)),
+                    Some(h) => LazyColumn(
+                        h.to_vec(),
+                        20.0,
+                        remember_with_key("history_scroll", LazyColumnState::new),
+                        Modifier::new().fill_max_width().height(240.0),
+                        |e: LogEntry, _| {
+                            Text(format!(
+                                "{}  {}  {} — {}",
+                                e.hash,
+                                e.at.map(ago).unwrap_or_default(),
+                                e.subject,
+                                e.author
+                            ))
+                            .size(11.0)
+                            .color(Color::from_hex("#B0B0B0"))
+                        },
+                    ),
+                    None => Box(Modifier::new()),
+                },
+                match s.build_files.as_ref().filter(|(x, _)| x == id) {
+                    Some((_, files)) => build_files_pane(store.clone(), id, files.as_deref()),
+                    None => Box(Modifier::new()),
+                },
+                if s.show_who_requires {
+                    who_requires_list(
+                        s.who_requires
+                            .as_ref()
+                            .filter(|(x, _)| x == id)
+                            .map(|(_, t)| t.as_slice()),
+                    )
+                } else {
+                    Box(Modifier::new())
+                },
+                if s.show_cached_versions {
+                    cached_versions_list(
+                        store.clone(),
+                        id,
+                        s.cached_versions
+                            .as_ref()
+                            .filter(|(x, _)| x == id)
+                            .map(|(_, v)| v.as_slice()),
+                    )
+                } else {
+                    Box(Modifier::new())
+                },
+            )),
         ))
     } else {
         Column(Modifier::new().padding(16.0))
@@ -180,12 +657,899 @@ fn details_card(store: Rc<Store>) -> View {
     }
 }
 
-pub fn root_view(store: Rc<Store>) -> View {
+/// PKGBUILD and install hooks of an AUR package, to read before building it. On a rebuild
+/// the PKGBUILD's changes since the last build come first, and accepting them builds.
+fn build_files_pane(store: Rc<Store>, id: &PackageId, files: Option<&[BuildFile]>) -> View {
+    let Some(files) = files else {
+        return Text("Fetching build files…")
+            .size(11.0)
+            .color(Color::from_hex("#888888"))
+            .modifier(Modifier::new().padding(6.0));
+    };
+    let has_diff = files.iter().any(|(name, _)| name == PKGBUILD_DIFF);
+    // (line, its color): file headers stand out, diff lines by what they do
+    let lines: Vec<(String, &'static str)> = files
+        .iter()
+        .flat_map(|(name, text)| {
+            let diff = name == PKGBUILD_DIFF;
+            std::iter::once((format!("==> {name}"), "#E0B050")).chain(text.lines().map(move |l| {
+                let color = match l.as_bytes().first() {
+                    Some(b'+') if diff => "#7CC87C",
+                    Some(b'-') if diff => "#E07070",
+                    Some(b'@') if diff => "#70A0E0",
+                    _ => "#C8C8C8",
+                };
+                (l.replace('\t', "    "), color)
+            }))
+        })
+        .collect();
+    Column(
+        Modifier::new()
+            .padding(6.0)
+            .background(Color::from_hex("#141414"))
+            .border(1.0, Color::from_hex("#333333"), 6.0)
+            .clip_rounded(6.0),
+    )
+    .child((
+        LazyColumn(
+            lines,
+            16.0,
+            remember_with_key("build_files_scroll", LazyColumnState::new),
+            Modifier::new().fill_max_width().height(320.0),
+            |(line, color): (String, &'static str), _| {
+                Text(line).size(11.0).color(Color::from_hex(color))
+            },
+        ),
+        Row(Modifier::new().padding(4.0)).child((
+            Spacer(),
+            Button(if has_diff { "Abort" } else { "Close" }, {
+                let store = store.clone();
+                move || store.dispatch(Action::CloseBuildFiles)
+            })
+            .modifier(Modifier::new().padding(4.0)),
+            Button(
+                if has_diff {
+                    "Accept and build"
+                } else {
+                    "Install anyway"
+                },
+                {
+                    let id = id.clone();
+                    move || {
+                        store.dispatch(Action::CloseBuildFiles);
+                        store.dispatch(Action::Install(id.clone()));
+                    }
+                },
+            )
+            .modifier(Modifier::new().padding(4.0)),
+        )),
+    ))
+}
+
+/// What depends on an installed package, as the tree the backend drew.
+fn who_requires_list(tree: Option<&[String]>) -> View {
+    let dim = |text: &str| {
+        Text(text)
+            .size(11.0)
+            .color(Color::from_hex("#888888"))
+            .modifier(Modifier::new().padding(6.0))
+    };
+    match tree {
+        None => dim("Looking for dependent packages…"),
+        Some([]) => dim("Nothing installed depends on this package"),
+        Some(tree) => LazyColumn(
+            tree.to_vec(),
+            16.0,
+            remember_with_key("who_requires_scroll", LazyColumnState::new),
+            Modifier::new().fill_max_width().height(200.0),
+            |line: String, _| {
+                Text(line)
+                    .size(11.0)
+                    .color(Color::from_hex("#B0B0B0"))
+                    .max_lines(1)
+                    .modifier(Modifier::new().padding(1.0))
+            },
+        ),
+    }
+}
+
+/// Older versions of `id` in the package cache, each with a button to go back to it.
+fn cached_versions_list(store: Rc<Store>, id: &PackageId, versions: Option<&[String]>) -> View {
+    let dim = |text: &str| {
+        Text(text)
+            .size(11.0)
+            .color(Color::from_hex("#888888"))
+            .modifier(Modifier::new().padding(6.0))
+    };
+    match versions {
+        None => dim("Looking in the package cache…"),
+        Some([]) => dim("No older versions in the package cache"),
+        Some(versions) => Column(Modifier::new().padding(2.0)).child(
+            versions
+                .iter()
+                .map(|v| {
+                    Row(Modifier::new()).child((
+                        Text(v.clone())
+                            .size(12.0)
+                            .color(Color::from_hex("#B0B0B0"))
+                            .modifier(Modifier::new().padding(6.0)),
+                        Spacer(),
+                        Button("Downgrade", {
+                            let store = store.clone();
+                            let (id, v) = (id.clone(), v.clone());
+                            move || store.dispatch(Action::Downgrade(id.clone(), v.clone()))
+                        })
+                        .modifier(Modifier::new().padding(2.0)),
+                    ))
+                })
+                .collect::<Vec<_>>(),
+        ),
+    }
+}
+
+// Maintainer, homepage, sizes and dependencies; lines the backend didn't fill are left out
+fn details_info(store: Rc<Store>, d: &PackageDetails) -> View {
+    let line = |text: String| {
+        Text(text)
+            .size(12.0)
+            .color(Color::from_hex("#AAAAAA"))
+            .max_lines(4)
+            .overflow_clip()
+            .modifier(Modifier::new().padding(2.0))
+    };
+    let mut lines = Vec::new();
+    if let Some(m) = &d.maintainer {
+        lines.push(line(format!("Maintainer: {m}")));
+    }
+    if let Some(url) = &d.homepage {
+        lines.push(
+            Row(Modifier::new()).child((
+                Text("Homepage:")
+                    .size(12.0)
+                    .color(Color::from_hex("#AAAAAA"))
+                    .modifier(Modifier::new().padding(2.0)),
+                link(store, url),
+            )),
+        );
+    }
+    let sizes: Vec<String> = [
+        d.size_download
+            .map(|b| format!("download {}", human_size(b))),
+        d.size_install
+            .map(|b| format!("installed {}", human_size(b))),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if !sizes.is_empty() {
+        lines.push(line(format!("Size: {}", sizes.join(", "))));
+    }
+    if !d.depends.is_empty() {
+        lines.push(line(format!("Depends on: {}", d.depends.join(", "))));
+    }
+    if !d.opt_depends.is_empty() {
+        lines.push(line(format!("Optional: {}", d.opt_depends.join(", "))));
+    }
+    if !d.provides.is_empty() {
+        lines.push(line(format!("Provides: {}", d.provides.join(", "))));
+    }
+    if !d.required_by.is_empty() {
+        lines.push(line(format!("Required by: {}", d.required_by.join(", "))));
+    }
+    Column(Modifier::new().padding(4.0)).child(lines)
+}
+
+/// A borderless button that opens `url` in the browser.
+fn link(store: Rc<Store>, url: &str) -> View {
+    let url = url.to_string();
+    Button(url.clone(), move || {
+        store.dispatch(Action::OpenUrl(url.clone()))
+    })
+    .modifier(
+        Modifier::new()
+            .background(Color::TRANSPARENT)
+            .padding(2.0)
+            .semantics("Open homepage"),
+    )
+}
+
+// Offer the other build of an AUR package, with what choosing it trades away
+fn sibling_row(store: Rc<Store>, other: &PackageSummary) -> View {
+    let tradeoff = if other.variant == AurVariant::Prebuilt {
+        "prebuilt upstream binary: installs without compiling, but you trust the packager's build"
+    } else {
+        "builds from source: slower to install, but what gets compiled can be audited"
+    };
+    Row(Modifier::new().padding(6.0)).child((
+        Text(format!("Also as {} ({tradeoff})", other.id.name))
+            .size(12.0)
+            .color(Color::from_hex("#AAAAAA"))
+            .max_lines(2)
+            .overflow_clip(),
+        Spacer(),
+        Button(format!("Switch to {}", other.id.name), move || {
+            store.dispatch(Action::SwitchVariant)
+        }),
+    ))
+}
+
+pub(crate) fn human_size(bytes: u64) -> String {
+    const MIB: f64 = 1024.0 * 1024.0;
+    let b = bytes as f64;
+    if b >= 1024.0 * MIB {
+        format!("{:.1} GiB", b / (1024.0 * MIB))
+    } else {
+        format!("{:.1} MiB", b / MIB)
+    }
+}
+
+// Coarse age for banners, e.g. "3 h ago"
+fn ago(t: std::time::SystemTime) -> String {
+    let secs = t.elapsed().map(|d| d.as_secs()).unwrap_or(0);
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", secs / 60),
+        3600..86400 => format!("{} h ago", secs / 3600),
+        _ => format!("{} days ago", secs / 86400),
+    }
+}
+
+// "any", native, or foreign relative to the running system
+fn arch_label(arch: &str) -> String {
+    let native = std::env::consts::ARCH;
+    if arch == "any" {
+        "any (architecture-independent)".to_string()
+    } else if arch.split_whitespace().any(|a| a == native) {
+        format!("{arch} (native)")
+    } else {
+        format!("{arch} (foreign, this system is {native})")
+    }
+}
+
+// Post-transaction hook summary; failures first since they can leave the system unbootable
+fn hooks_panel(store: Rc<Store>, hooks: &[HookRun]) -> View {
+    let failed = hooks
+        .iter()
+        .filter(|h| h.outcome == HookOutcome::Failed)
+        .count();
+    let warned = hooks
+        .iter()
+        .filter(|h| h.outcome == HookOutcome::Warning)
+        .count();
+    let mut notable: Vec<&HookRun> = hooks
+        .iter()
+        .filter(|h| h.outcome != HookOutcome::Ok)
+        .collect();
+    notable.sort_by_key(|h| h.outcome != HookOutcome::Failed);
+
+    let mut lines: Vec<View> = vec![
+        Row(Modifier::new()).child((
+            Text(format!(
+                "Transaction hooks: {} ran, {warned} with warnings, {failed} failed",
+                hooks.len()
+            ))
+            .color(if failed > 0 {
+                Color::from_hex("#F87171")
+            } else if warned > 0 {
+                Color::from_hex("#E0B050")
+            } else {
+                Color::from_hex("#A0A0A0")
+            }),
+            Spacer(),
+            Button("Dismiss", {
+                let store = store.clone();
+                move || store.dispatch(Action::DismissHooks)
+            }),
+        )),
+    ];
+    for h in notable {
+        let (label, color) = if h.outcome == HookOutcome::Failed {
+            ("FAILED", "#F87171")
+        } else {
+            ("warning", "#E0B050")
+        };
+        lines.push(
+            Text(format!(
+                "{label}: {}  {}",
+                h.name,
+                h.messages.last().map(String::as_str).unwrap_or("")
+            ))
+            .size(12.0)
+            .color(Color::from_hex(color))
+            .max_lines(1)
+            .overflow_ellipsize()
+            .modifier(Modifier::new().padding(2.0)),
+        );
+    }
+
+    Column(
+        Modifier::new()
+            .padding(8.0)
+            .background(Color::from_hex("#1B1B1B"))
+            .border(
+                1.0,
+                if failed > 0 {
+                    Color::from_hex("#7F1D1D")
+                } else {
+                    Color::from_hex("#333333")
+                },
+                8.0,
+            )
+            .clip_rounded(8.0),
+    )
+    .child(lines)
+}
+
+/// Shown instead of queuing a repo install while upgrades are pending.
+fn partial_upgrade_panel(store: Rc<Store>, id: &PackageId, upgrades: usize) -> View {
+    Column(
+        Modifier::new()
+            .padding(8.0)
+            .background(Color::from_hex("#1B1B1B"))
+            .border(1.0, Color::from_hex("#A16207"), 8.0)
+            .clip_rounded(8.0),
+    )
+    .child((
+        Text(format!(
+            "{upgrades} repo package(s) have upgrades pending. Installing {} on its own is a \
+             partial upgrade, which Arch does not support and can break the system.",
+            id.name
+        ))
+        .color(Color::from_hex("#E0B050"))
+        .modifier(Modifier::new().padding(4.0)),
+        Row(Modifier::new()).child((
+            Button("Upgrade system, then install", {
+                let store = store.clone();
+                let id = id.clone();
+                move || store.dispatch(Action::UpgradeThenInstall(id.clone()))
+            })
+            .modifier(Modifier::new().padding(4.0)),
+            Button("Install anyway", {
+                let store = store.clone();
+                let id = id.clone();
+                move || store.dispatch(Action::ConfirmInstall(id.clone()))
+            })
+            .modifier(Modifier::new().padding(4.0)),
+            Spacer(),
+            Button("Cancel", {
+                let store = store.clone();
+                move || store.dispatch(Action::DismissPartialUpgrade)
+            })
+            .modifier(Modifier::new().padding(4.0)),
+        )),
+    ))
+}
+
+// AUR packages linking against libraries that are gone
+fn rebuilds_panel(store: Rc<Store>, items: &[PackageSummary]) -> View {
+    let mut lines: Vec<View> = vec![
+        Row(Modifier::new()).child((
+            Text(if items.is_empty() {
+                "No AUR packages need a rebuild.".to_string()
+            } else {
+                format!(
+                    "{} AUR package(s) link against libraries that no longer exist",
+                    items.len()
+                )
+            })
+            .color(if items.is_empty() {
+                Color::from_hex("#A0A0A0")
+            } else {
+                Color::from_hex("#E0B050")
+            }),
+            Spacer(),
+            if items.is_empty() {
+                Box(Modifier::new())
+            } else {
+                Button("Rebuild all", {
+                    let store = store.clone();
+                    move || store.dispatch(Action::RebuildAll)
+                })
+                .modifier(Modifier::new().padding(4.0))
+            },
+            Button("Dismiss", {
+                let store = store.clone();
+                move || store.dispatch(Action::DismissRebuilds)
+            })
+            .modifier(Modifier::new().padding(4.0)),
+        )),
+    ];
+    for p in items {
+        lines.push(
+            Row(Modifier::new().padding(2.0)).child((
+                Text(format!("{} {}", p.id.name, p.version))
+                    .size(12.0)
+                    .color(Color::from_hex("#C8C8C8")),
+                Spacer(),
+                Button("Rebuild", {
+                    let store = store.clone();
+                    let id = p.id.clone();
+                    move || store.dispatch(Action::Upgrade(id.clone()))
+                }),
+            )),
+        );
+    }
+
+    Column(
+        Modifier::new()
+            .padding(8.0)
+            .background(Color::from_hex("#1B1B1B"))
+            .border(1.0, Color::from_hex("#333333"), 8.0)
+            .clip_rounded(8.0),
+    )
+    .child(lines)
+}
+
+/// A yes/no notice about a list of packages.
+fn package_list_panel(
+    message: String,
+    names: &[String],
+    yes: (&str, Action),
+    no: (&str, Action),
+    store: Rc<Store>,
+) -> View {
+    Column(
+        Modifier::new()
+            .padding(8.0)
+            .background(Color::from_hex("#1B1B1B"))
+            .border(1.0, Color::from_hex("#333333"), 8.0)
+            .clip_rounded(8.0),
+    )
+    .child((
+        Row(Modifier::new()).child((
+            Text(message).color(Color::from_hex("#E0B050")),
+            Spacer(),
+            Button(yes.0, {
+                let store = store.clone();
+                let action = yes.1;
+                move || store.dispatch(action.clone())
+            })
+            .modifier(Modifier::new().padding(4.0)),
+            Button(no.0, {
+                let store = store.clone();
+                let action = no.1;
+                move || store.dispatch(action.clone())
+            })
+            .modifier(Modifier::new().padding(4.0)),
+        )),
+        Text(names.join(", "))
+            .size(12.0)
+            .color(Color::from_hex("#C8C8C8"))
+            .modifier(Modifier::new().padding(4.0)),
+    ))
+}
+
+/// Modal question over the whole window; the dimmed backdrop swallows clicks meant for
+/// the rows beneath. A removal lists what else goes once its dry run is back, and can't be
+/// confirmed when installed packages still need it.
+fn confirm_dialog(
+    store: Rc<Store>,
+    id: &PackageId,
+    kind: ConfirmKind,
+    preview: Option<&RemovePreview>,
+) -> View {
+    let dim = Color::from_hex("#A0A0A0");
+    let blocked = preview.is_some_and(|p| !p.breaks.is_empty());
+    let detail = match preview {
+        _ if kind != ConfirmKind::Remove => Box(Modifier::new()),
+        None => Text("Checking what else it takes…").size(12.0).color(dim),
+        Some(p) if blocked => Column(Modifier::new()).child(
+            p.breaks
+                .iter()
+                .map(|b| Text(b.clone()).size(12.0).color(Color::from_hex("#E0B050")))
+                .collect::<Vec<_>>(),
+        ),
+        Some(p) => {
+            let others: Vec<&str> = p
+                .names
+                .iter()
+                .map(String::as_str)
+                .filter(|n| *n != id.name)
+                .collect();
+            if others.is_empty() {
+                Text("Nothing else is removed.").size(12.0).color(dim)
+            } else {
+                Text(format!("Also removes: {}", others.join(", ")))
+                    .size(12.0)
+                    .color(dim)
+            }
+        }
+    };
+    Column(
+        Modifier::new()
+            .absolute()
+            .offset(Some(0.0), Some(0.0), Some(0.0), Some(0.0))
+            .background(Color::from_hex("#000000A0"))
+            .clickable()
+            .z_index(10.0),
+    )
+    .child((
+        Spacer(),
+        Column(
+            Modifier::new()
+                .align_self_center()
+                .padding(16.0)
+                .background(Color::from_hex("#1B1B1B"))
+                .border(1.0, Color::from_hex("#333333"), 8.0)
+                .clip_rounded(8.0)
+                .semantics("Confirmation"),
+        )
+        .child((
+            Text(kind.question(id)).modifier(Modifier::new().padding(4.0)),
+            Box(Modifier::new().padding(4.0)).child(detail),
+            Row(Modifier::new().padding(4.0)).child((
+                Spacer(),
+                Button("Cancel", {
+                    let store = store.clone();
+                    move || store.dispatch(Action::CancelConfirm)
+                })
+                .modifier(Modifier::new().padding(4.0)),
+                if blocked {
+                    Box(Modifier::new())
+                } else {
+                    Button(kind.verb(), {
+                        let id = id.clone();
+                        move || store.dispatch(kind.action(id.clone()))
+                    })
+                    .modifier(Modifier::new().padding(4.0))
+                },
+            )),
+        )),
+        Spacer(),
+    ))
+}
+
+fn upgrade_plan_panel(store: Rc<Store>, plan: &TransactionPlan) -> View {
+    let header = Color::from_hex("#E0E0E0");
+    let mut lines: Vec<(String, Color)> = Vec::new();
+    if !plan.upgrades.is_empty() {
+        lines.push((format!("Upgrade ({})", plan.upgrades.len()), header));
+        for (name, old, new) in &plan.upgrades {
+            lines.push((
+                format!("  {name} {old} → {new}"),
+                Color::from_hex("#C8C8C8"),
+            ));
+        }
+    }
+    if !plan.installs.is_empty() {
+        lines.push((
+            format!("New dependencies ({})", plan.installs.len()),
+            header,
+        ));
+        for (name, version) in &plan.installs {
+            lines.push((format!("  {name} {version}"), Color::from_hex("#7FB3FF")));
+        }
+    }
+    if !plan.removals.is_empty() {
+        lines.push((
+            format!("Removed or replaced ({})", plan.removals.len()),
+            header,
+        ));
+        for name in &plan.removals {
+            lines.push((format!("  {name}"), Color::from_hex("#E06060")));
+        }
+    }
+    let empty = lines.is_empty();
+    let delta = if plan.install_size_delta < 0 {
+        format!("-{}", human_size(plan.install_size_delta.unsigned_abs()))
+    } else {
+        format!("+{}", human_size(plan.install_size_delta as u64))
+    };
+    let height = (lines.len() as f32 * 20.0).min(240.0);
+
+    Column(
+        Modifier::new()
+            .padding(8.0)
+            .background(Color::from_hex("#1B1B1B"))
+            .border(1.0, Color::from_hex("#333333"), 8.0)
+            .clip_rounded(8.0),
+    )
+    .child((
+        Row(Modifier::new()).child((
+            Text(if empty {
+                "Nothing to upgrade.".to_string()
+            } else {
+                format!(
+                    "Upgrade all: {} to download, {delta} installed",
+                    human_size(plan.download_size)
+                )
+            })
+            .color(Color::from_hex("#E0B050")),
+            Spacer(),
+            if empty {
+                Box(Modifier::new())
+            } else {
+                Button("Proceed", {
+                    let store = store.clone();
+                    move || store.dispatch(Action::ConfirmUpgradeAll)
+                })
+                .modifier(Modifier::new().padding(4.0))
+            },
+            Button(if empty { "Dismiss" } else { "Cancel" }, {
+                let store = store.clone();
+                move || store.dispatch(Action::CancelUpgradePlan)
+            })
+            .modifier(Modifier::new().padding(4.0)),
+        )),
+        LazyColumn(
+            lines,
+            20.0,
+            remember_with_key("plan_scroll", LazyColumnState::new),
+            Modifier::new().fill_max_width().height(height),
+            |(line, color): (String, Color), _| Text(line).size(12.0).color(color),
+        ),
+    ))
+}
+
+fn job_label(job: &Job) -> String {
+    let target = match &job.payload {
+        JobPayload::None => String::new(),
+        JobPayload::Skip(ids) if ids.is_empty() => String::new(),
+        JobPayload::Skip(ids) => format!(" (skipping {})", ids.len()),
+        JobPayload::Packages(ids) => format!(" ({} packages)", ids.len()),
+        JobPayload::Query(q) => format!(" \"{q}\""),
+        JobPayload::Package(id) | JobPayload::Details { id, .. } => format!(" {}", id.name),
+        JobPayload::Version { id, version } => format!(" {} {version}", id.name),
+    };
+    format!("{:?}{target}", job.kind)
+}
+
+/// Operations this session, newest first, with how they ended.
+fn activity_panel(records: &[JobRecord]) -> View {
+    let mut lines: Vec<View> = vec![
+        Text(format!("Activity ({})", records.len()))
+            .size(12.0)
+            .color(Color::from_hex("#888888"))
+            .modifier(Modifier::new().padding(2.0)),
+    ];
+    if records.is_empty() {
+        lines.push(
+            Text("Nothing installed, removed or upgraded yet.")
+                .size(12.0)
+                .color(Color::from_hex("#666666"))
+                .modifier(Modifier::new().padding(2.0)),
+        );
+    }
+    for r in records.iter().rev() {
+        let (outcome, color) = match r.outcome {
+            None => ("running", "#7FB3FF"),
+            Some(Stage::Finished) => ("done", "#7FBF7F"),
+            Some(Stage::Cancelled) => ("cancelled", "#A0A0A0"),
+            Some(_) => ("failed", "#E06C6C"),
+        };
+        let took = r
+            .ended
+            .and_then(|e| e.duration_since(r.started).ok())
+            .map(|d| format!(" in {}s", d.as_secs()))
+            .unwrap_or_default();
+        lines.push(
+            Row(Modifier::new().padding(2.0)).child((
+                Text(format!("{:?} {}", r.kind, r.target))
+                    .size(12.0)
+                    .color(Color::from_hex("#C8C8C8"))
+                    .max_lines(1)
+                    .overflow_ellipsize()
+                    .modifier(Modifier::new().flex_grow(1.0)),
+                Text(format!("{outcome}{took}, {}", ago(r.started)))
+                    .size(12.0)
+                    .color(Color::from_hex(color))
+                    .modifier(Modifier::new().padding(2.0)),
+            )),
+        );
+    }
+    Column(
+        Modifier::new()
+            .padding(8.0)
+            .background(Color::from_hex("#1B1B1B"))
+            .border(1.0, Color::from_hex("#333333"), 8.0)
+            .clip_rounded(8.0),
+    )
+    .child(lines)
+}
+
+/// Red, dismissible banner for the last error.
+fn error_banner(store: Rc<Store>, message: &str) -> View {
+    Row(Modifier::new()
+        .padding(8.0)
+        .background(Color::from_hex("#5A1F1F"))
+        .border(1.0, Color::from_hex("#8B2C2C"), 6.0)
+        .clip_rounded(6.0))
+    .child((
+        Text(message.to_string())
+            .color(Color::from_hex("#F2C4C4"))
+            .max_lines(3)
+            .overflow_ellipsize()
+            .modifier(Modifier::new().padding(4.0).flex_grow(1.0)),
+        Button("✕", move || store.dispatch(Action::ClearError))
+            .modifier(Modifier::new().padding(4.0).semantics("Dismiss error")),
+    ))
+}
+
+/// Spinner and job count in the header while anything is queued or running. Frames are
+/// picked from the clock, so it turns as long as the app keeps redrawing.
+fn busy_indicator(jobs: usize, authorizing: bool) -> View {
+    const FRAMES: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+    if authorizing {
+        return Text("🔒 Waiting for authorization…")
+            .size(12.0)
+            .color(Color::from_hex("#E0B050"))
+            .modifier(
+                Modifier::new()
+                    .padding(8.0)
+                    .semantics("Waiting for authorization"),
+            );
+    }
+    if jobs == 0 {
+        return Box(Modifier::new());
+    }
+    let ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+    let frame = FRAMES[(ms / 100) as usize % FRAMES.len()];
+    Text(match jobs {
+        1 => format!("{frame} Working…"),
+        n => format!("{frame} {n} jobs…"),
+    })
+    .size(12.0)
+    .color(Color::from_hex("#A0A0A0"))
+    .modifier(Modifier::new().padding(8.0).semantics("Busy"))
+}
+
+/// Actions that change the system without touching packages, kept out of the header so
+/// they aren't hit by accident. Each one asks again before it runs.
+fn maintenance_panel(
+    store: Rc<Store>,
+    plan: Option<&CacheCleanPlan>,
+    confirm_clear_build_cache: bool,
+) -> View {
+    let confirm = match plan {
+        Some(plan) => package_list_panel(
+            match plan.reclaim {
+                Some(bytes) => format!(
+                    "{} removes {} cached package files, freeing {}",
+                    plan.command,
+                    plan.files.len(),
+                    human_size(bytes)
+                ),
+                None => format!(
+                    "{} removes cached packages no longer installed",
+                    plan.command
+                ),
+            },
+            &plan.files,
+            ("Clean", Action::ConfirmCleanCache),
+            ("Cancel", Action::CancelCleanCache),
+            store.clone(),
+        ),
+        None => Box(Modifier::new()),
+    };
+    let confirm_clear = if confirm_clear_build_cache {
+        package_list_panel(
+            "Delete the AUR repos kept between builds? They're cloned again when next built."
+                .into(),
+            &[],
+            ("Clear", Action::ConfirmClearBuildCache),
+            ("Cancel", Action::CancelClearBuildCache),
+            store.clone(),
+        )
+    } else {
+        Box(Modifier::new())
+    };
+    Column(Modifier::new().padding(4.0)).child((
+        Row(Modifier::new()).child((
+            Text("Maintenance")
+                .size(12.0)
+                .color(Color::from_hex("#888888"))
+                .modifier(Modifier::new().padding(6.0)),
+            Button("Clean package cache…", {
+                let store = store.clone();
+                move || store.dispatch(Action::PlanCleanCache)
+            })
+            .modifier(Modifier::new().padding(4.0)),
+            Button("Clear AUR build cache…", move || {
+                store.dispatch(Action::ClearBuildCache)
+            })
+            .modifier(Modifier::new().padding(4.0)),
+        )),
+        confirm,
+        confirm_clear,
+    ))
+}
+
+/// Footer bar for the running job: determinate when the backend reports a percentage,
+/// otherwise a sweep driven by the clock.
+fn progress_bar(percent: Option<f32>, bytes: Option<(u64, u64)>) -> View {
+    let (value, label) = match percent {
+        Some(p) => (
+            p,
+            match bytes {
+                Some((done, total)) => format!("{} / {}", human_size(done), human_size(total)),
+                None => format!("{:.0}%", p * 100.0),
+            },
+        ),
+        None => {
+            let ms = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis());
+            ((ms % 1500) as f32 / 1500.0, String::new())
+        }
+    };
+    ProgressBar(value, (0.0, 1.0), label).modifier(
+        Modifier::new()
+            .width(220.0)
+            .padding(4.0)
+            .semantics("Progress"),
+    )
+}
+
+/// Jobs waiting behind the running one, with controls to change their order.
+fn jobs_panel(store: Rc<Store>, pending: &[Job]) -> View {
+    let mut lines: Vec<View> = vec![
+        Text(format!("Queued ({})", pending.len()))
+            .size(12.0)
+            .color(Color::from_hex("#888888"))
+            .modifier(Modifier::new().padding(2.0)),
+    ];
+    for (i, job) in pending.iter().enumerate() {
+        let jid = job.id;
+        lines.push(
+            Row(Modifier::new().padding(2.0)).child((
+                Text(job_label(job))
+                    .size(12.0)
+                    .color(Color::from_hex("#C8C8C8"))
+                    .max_lines(1)
+                    .overflow_ellipsize(),
+                Spacer(),
+                if i > 0 {
+                    Button("↑", {
+                        let store = store.clone();
+                        move || store.dispatch(Action::Prioritize(jid))
+                    })
+                } else {
+                    Box(Modifier::new())
+                },
+                if i + 1 < pending.len() {
+                    Button("↓", {
+                        let store = store.clone();
+                        move || store.dispatch(Action::Deprioritize(jid))
+                    })
+                } else {
+                    Box(Modifier::new())
+                },
+                Button("✕", {
+                    let store = store.clone();
+                    move || store.dispatch(Action::CancelQueued(jid))
+                }),
+            )),
+        );
+    }
+
+    Column(
+        Modifier::new()
+            .padding(8.0)
+            .background(Color::from_hex("#1B1B1B"))
+            .border(1.0, Color::from_hex("#333333"), 8.0)
+            .clip_rounded(8.0),
+    )
+    .child(lines)
+}
+
+/// Below this width (dp) details get their own screen instead of a side column.
+const NARROW_WIDTH: f32 = 900.0;
+
+/// `window_width` is in physical pixels, as the scheduler reports it.
+pub fn root_view(store: Rc<Store>, window_width: u32) -> View {
     let s = store.state.get();
+    let wide = window_width as f32 / density().scale >= NARROW_WIDTH;
 
     let current_query = s.query.clone();
+    let toast = s.active_toast().map(str::to_string);
+    let pending = s.pending.clone();
+    let partial_upgrade_risk = s.partial_upgrade_risk.clone();
+    // Filled in as the result list builds its on-screen rows
+    let visible: Rc<RefCell<Vec<PackageId>>> = Rc::default();
 
-    Surface(
+    let view = Surface(
         Modifier::new()
             .fill_max_size()
             .background(Color::from_hex("#0F1012")),
@@ -197,30 +1561,91 @@ pub fn root_view(store: Rc<Store>) -> View {
                     .modifier(Modifier::new().padding(8.0)),
                 Spacer(),
                 if s.in_upgrades_view && !s.results.is_empty() {
-                    Button("Upgrade all", {
+                    let skips = s.upgrade_skips();
+                    // Only what's listed; the rest of the ignore list isn't pending anyway
+                    let listed = s.results.iter().filter(|p| skips.contains(&p.id)).count();
+                    let label = match listed {
+                        0 => "Upgrade all".to_string(),
+                        n => format!("Upgrade all (skipping {n})"),
+                    };
+                    Button(label, {
                         let store = store.clone();
                         move || store.dispatch(Action::UpgradeAll)
                     })
                     .modifier(Modifier::new().padding(4.0))
-                } else {
+                } else if s.in_upgrades_view {
                     Box(Modifier::new())
+                } else {
+                    selection_controls(store.clone(), s.selection_mode, s.checked.len())
                 },
-                Button("🔃 Refresh", {
-                    let store = store.clone();
-                    move || store.dispatch(Action::Search)
-                })
-                .modifier(Modifier::new().padding(4.0)),
-                Button("Upgrades", {
-                    let store = store.clone();
-                    move || store.dispatch(Action::Upgrades)
-                })
+                Row(Modifier::new()).child((
+                    Button("🔍 Re-search", {
+                        let store = store.clone();
+                        move || store.dispatch(Action::Search)
+                    })
+                    .modifier(Modifier::new().padding(4.0)),
+                    Button(
+                        if s.busy.contains(&JobKind::Refresh) {
+                            "Syncing…"
+                        } else {
+                            "🔃 Sync databases"
+                        },
+                        {
+                            let store = store.clone();
+                            move || store.dispatch(Action::Refresh)
+                        },
+                    )
+                    .modifier(
+                        Modifier::new()
+                            .padding(4.0)
+                            .semantics("Sync package databases"),
+                    ),
+                )),
+                Button(
+                    match s.available_upgrade_count {
+                        _ if s.busy.contains(&JobKind::Upgrades) => "Upgrades…".to_string(),
+                        None | Some(0) => "Upgrades".to_string(),
+                        Some(n) => format!("Upgrades ({n})"),
+                    },
+                    {
+                        let store = store.clone();
+                        move || store.dispatch(Action::Upgrades)
+                    },
+                )
                 .modifier(Modifier::new().padding(4.0)),
+                Row(Modifier::new()).child((
+                    Button("Orphans", {
+                        let store = store.clone();
+                        move || store.dispatch(Action::ShowOrphans)
+                    })
+                    .modifier(Modifier::new().padding(4.0)),
+                    Button("AUR rebuilds", {
+                        let store = store.clone();
+                        move || store.dispatch(Action::CheckRebuilds)
+                    })
+                    .modifier(Modifier::new().padding(4.0)),
+                    chip("Maintenance", s.show_maintenance, {
+                        let store = store.clone();
+                        move || store.dispatch(Action::ToggleMaintenance)
+                    }),
+                )),
+                Row(Modifier::new()).child((
+                    busy_indicator(s.pending_jobs, s.authorizing),
+                    match s.running {
+                        Some((jid, _)) => Button("Cancel", {
+                            let store = store.clone();
+                            move || store.dispatch(Action::Cancel(jid))
+                        })
+                        .modifier(Modifier::new().padding(4.0)),
+                        None => Box(Modifier::new()),
+                    },
+                )),
             )),
             separator(),
             // Search row
             Row(Modifier::new().padding(8.0)).child((
                 repose_ui::textfield::TextField(
-                    "Search packages…",
+                    "Search packages, or /path for its owner…",
                     Modifier::new()
                         .size(420.0, 36.0)
                         .background(Color::from_hex("#171717"))
@@ -244,28 +1669,66 @@ pub fn root_view(store: Rc<Store>) -> View {
                     }),
                 ),
                 // Search button - uses query from store
-                Button("Search", {
-                    let store = store.clone();
-                    move || {
-                        store.dispatch(Action::Search);
-                    }
-                })
+                Button(
+                    if s.busy.iter().any(|k| {
+                        matches!(
+                            k,
+                            JobKind::Search | JobKind::SearchInstalled | JobKind::SearchFile
+                        )
+                    }) {
+                        "Searching…"
+                    } else {
+                        "Search"
+                    },
+                    {
+                        let store = store.clone();
+                        move || {
+                            store.dispatch(Action::Search);
+                        }
+                    },
+                )
                 .modifier(Modifier::new().padding(4.0)),
                 // Debug
                 // Text(format!("Query: '{}'", current_query)).modifier(Modifier::new().padding(4.0)),
                 // Filters
-                chip("Repo", s.filter_repo, {
-                    let store = store.clone();
-                    move || store.dispatch(Action::ToggleFilterRepo)
-                }),
-                chip("AUR", s.filter_aur, {
-                    let store = store.clone();
-                    move || store.dispatch(Action::ToggleFilterAur)
-                }),
-                chip("Installed", s.filter_installed, {
-                    let store = store.clone();
-                    move || store.dispatch(Action::ToggleFilterInstalled)
-                }),
+                Row(Modifier::new()).child((
+                    chip("Repo", s.filter_repo, {
+                        let store = store.clone();
+                        move || store.dispatch(Action::ToggleFilterRepo)
+                    }),
+                    chip("AUR", s.filter_aur, {
+                        let store = store.clone();
+                        move || store.dispatch(Action::ToggleFilterAur)
+                    }),
+                    chip("Flatpak", s.filter_flatpak, {
+                        let store = store.clone();
+                        move || store.dispatch(Action::ToggleFilterFlatpak)
+                    }),
+                    chip("Installed", s.filter_installed, {
+                        let store = store.clone();
+                        move || store.dispatch(Action::ToggleFilterInstalled)
+                    }),
+                    chip("Out of date", s.filter_out_of_date, {
+                        let store = store.clone();
+                        move || store.dispatch(Action::ToggleFilterOutOfDate)
+                    }),
+                    chip("VCS/bin", s.special_variants, {
+                        let store = store.clone();
+                        move || store.dispatch(Action::ToggleSpecialVariants)
+                    }),
+                    if s.shadowed.is_empty() || s.in_upgrades_view || s.in_orphans_view {
+                        Box(Modifier::new())
+                    } else {
+                        chip(
+                            &format!("AUR duplicates ({})", s.shadowed.len()),
+                            s.show_shadowed,
+                            {
+                                let store = store.clone();
+                                move || store.dispatch(Action::ToggleShadowed)
+                            },
+                        )
+                    },
+                )),
                 Spacer(),
                 // Sort
                 Row(Modifier::new().padding(6.0)).child((
@@ -281,50 +1744,246 @@ pub fn root_view(store: Rc<Store>) -> View {
                         let store = store.clone();
                         move || store.dispatch(Action::SetSort(SortMode::Popularity))
                     }),
+                    Button("Recent", {
+                        let store = store.clone();
+                        move || store.dispatch(Action::SetSort(SortMode::RecentlyUpdated))
+                    }),
                 )),
             )),
-            {
-                let wide = true;
+            if !wide && s.screen == Screen::Details && s.selected.is_some() {
+                Column(Modifier::new().fill_max_size().padding(6.0)).child((
+                    Button("← Back", {
+                        let store = store.clone();
+                        move || store.dispatch(Action::Back)
+                    })
+                    .modifier(Modifier::new().padding(4.0)),
+                    details_card(store.clone()),
+                ))
+            } else {
                 let left_span = if wide { 4 } else { 6 };
-                let right_span = if wide { 2 } else { 6 };
-
-                Grid(
-                    6,
-                    Modifier::new().fill_max_size().padding(6.0),
-                    vec![
-                        // Left: result list
-                        Column(Modifier::new().grid_span(left_span, 1)).child(
-                            if s.results.is_empty() {
-                                Column(Modifier::new().padding(16.0)).child(
-                                    Text("No results. Try searching.")
-                                        .color(Color::from_hex("#888888")),
-                                )
-                            } else {
+                let mut panes = vec![
+                    // Left: result list
+                    Column(Modifier::new().grid_span(left_span, 1)).child(
+                        if s.results.is_empty() && s.sync_db_missing {
+                            Column(Modifier::new().padding(16.0)).child((
+                                Text("Package databases have never been synced.")
+                                    .size(16.0)
+                                    .color(Color::from_hex("#E0B050")),
+                                Text("Repository search needs a first refresh to download them.")
+                                    .color(Color::from_hex("#AAAAAA"))
+                                    .modifier(Modifier::new().padding(4.0)),
+                                Button("Refresh package databases", {
+                                    let store = store.clone();
+                                    move || store.dispatch(Action::Refresh)
+                                })
+                                .modifier(Modifier::new().padding(4.0)),
+                            ))
+                        } else if s.results.is_empty() {
+                            Column(Modifier::new().padding(16.0)).child(
+                                Text("No results. Try searching.")
+                                    .color(Color::from_hex("#888888")),
+                            )
+                        } else {
+                            Column(Modifier::new()).child((
+                                if s.in_upgrades_view {
+                                    Text(match s.upgrades_download {
+                                        Some(b) => format!(
+                                            "{} upgrades, {} to download",
+                                            s.results.len(),
+                                            human_size(b)
+                                        ),
+                                        None => format!("{} upgrades", s.results.len()),
+                                    })
+                                    .color(Color::from_hex("#AAAAAA"))
+                                    .modifier(Modifier::new().padding(6.0))
+                                } else {
+                                    Box(Modifier::new())
+                                },
+                                // Rows are built from indices so the (possibly huge)
+                                // list isn't copied every frame
                                 LazyColumn(
-                                    s.results.clone(),
+                                    (0..s.results.len()).collect::<Vec<usize>>(),
                                     56.0,
                                     remember_with_key("scroll", || LazyColumnState::new()),
                                     Modifier::new().fill_max_width().height(700.0),
                                     {
                                         let store = store.clone();
                                         let upgrades_mode = s.in_upgrades_view;
-                                        move |pkg: PackageSummary, _| {
+                                        let special_variants = s.special_variants;
+                                        let results = s.results.clone();
+                                        let visible = visible.clone();
+                                        move |i: usize, _| {
+                                            let pkg = results[i].clone();
+                                            visible.borrow_mut().push(pkg.id.clone());
                                             let selected = s
                                                 .selected
                                                 .as_ref()
                                                 .map_or(false, |id| *id == pkg.id);
-                                            pkg_row(store.clone(), pkg, selected, upgrades_mode)
+                                            let highlight = change_highlight(&s.changed, &pkg.id);
+                                            let upgrade =
+                                                upgrades_mode.then(|| UpgradeToggles {
+                                                    skip_once: s.skip_once.contains(&pkg.id),
+                                                    ignored: s.ignore.contains(&pkg.id.name),
+                                                });
+                                            let checked = (s.selection_mode && !upgrades_mode)
+                                                .then(|| s.checked.contains(&pkg.id));
+                                            pkg_row(
+                                                store.clone(),
+                                                pkg,
+                                                selected,
+                                                upgrade,
+                                                special_variants,
+                                                highlight,
+                                                checked,
+                                            )
                                         }
                                     },
-                                )
-                            },
-                        ),
-                        // Right: details
-                        Column(Modifier::new().grid_span(right_span, 1))
-                            .child(details_card(store.clone())),
-                    ],
-                )
+                                ),
+                            ))
+                        },
+                    ),
+                ];
+                // Right: details, on wide windows only
+                if wide {
+                    panes.push(
+                        Column(Modifier::new().grid_span(2, 1)).child(details_card(store.clone())),
+                    );
+                }
+                Grid(6, Modifier::new().fill_max_size().padding(6.0), panes)
             },
+            // Notices between the results and the footer
+            Column(Modifier::new()).child((
+                Column(Modifier::new()).child((
+                    match &s.error {
+                        Some(e) => error_banner(store.clone(), e),
+                        None => Box(Modifier::new()),
+                    },
+                    match &s.environment_notice {
+                        Some(notice) => Text(notice.clone())
+                            .size(12.0)
+                            .color(Color::from_hex("#7FB3FF"))
+                            .modifier(Modifier::new().padding(6.0)),
+                        None => Box(Modifier::new()),
+                    },
+                    match s.cached_at {
+                        Some(at) => {
+                            Text(format!("Cached results from {}, possibly stale", ago(at)))
+                                .size(12.0)
+                                .color(Color::from_hex("#E0B050"))
+                                .modifier(Modifier::new().padding(6.0))
+                        }
+                        None => Box(Modifier::new()),
+                    },
+                    if s.db_locked.is_some() {
+                        Row(Modifier::new().padding(8.0)).child((
+                            Text("Another package operation is in progress — retry?")
+                                .color(Color::from_hex("#E0B050"))
+                                .modifier(Modifier::new().padding(4.0)),
+                            Spacer(),
+                            Button("Retry", {
+                                let store = store.clone();
+                                move || store.dispatch(Action::RetryLocked)
+                            })
+                            .modifier(Modifier::new().padding(4.0)),
+                            Button("Dismiss", {
+                                let store = store.clone();
+                                move || store.dispatch(Action::DismissLocked)
+                            })
+                            .modifier(Modifier::new().padding(4.0)),
+                        ))
+                    } else {
+                        Box(Modifier::new())
+                    },
+                )),
+                if s.files_db_needs_sync {
+                    Row(Modifier::new().padding(8.0)).child((
+                        Text("The files database for path searches and file lists is missing or out of date.")
+                            .color(Color::from_hex("#E0B050"))
+                            .modifier(Modifier::new().padding(4.0)),
+                        Spacer(),
+                        Button("Sync files database", {
+                            let store = store.clone();
+                            move || store.dispatch(Action::RefreshFilesDb)
+                        })
+                        .modifier(Modifier::new().padding(4.0)),
+                    ))
+                } else {
+                    Box(Modifier::new())
+                },
+                match &partial_upgrade_risk {
+                    Some(id) => partial_upgrade_panel(store.clone(), id, s.repo_upgrades),
+                    None => Box(Modifier::new()),
+                },
+                if pending.is_empty() {
+                    Box(Modifier::new())
+                } else {
+                    jobs_panel(store.clone(), &pending)
+                },
+                // AUR build dependencies (before the build, then leftovers after it), batch
+                // removes and maintenance
+                Column(Modifier::new()).child((
+                    match &s.build_deps {
+                        Some((id, deps)) => package_list_panel(
+                            format!(
+                                "Building {} first installs {} build-only dependencies",
+                                id.name,
+                                deps.len()
+                            ),
+                            deps,
+                            ("Build", Action::ConfirmBuild),
+                            ("Cancel", Action::CancelBuild),
+                            store.clone(),
+                        ),
+                        None => Box(Modifier::new()),
+                    },
+                    if s.orphaned_build_deps.is_empty() {
+                        Box(Modifier::new())
+                    } else {
+                        package_list_panel(
+                            "These build dependencies are no longer needed".to_string(),
+                            &s.orphaned_build_deps,
+                            ("Remove", Action::RemoveOrphanedBuildDeps),
+                            ("Keep", Action::KeepOrphanedBuildDeps),
+                            store.clone(),
+                        )
+                    },
+                    match &s.batch_remove {
+                        Some(ids) => package_list_panel(
+                            format!(
+                                "Remove {} packages and their unneeded dependencies?",
+                                ids.len()
+                            ),
+                            &ids.iter().map(|id| id.name.clone()).collect::<Vec<_>>(),
+                            ("Remove", Action::ConfirmRemoveChecked),
+                            ("Cancel", Action::CancelRemoveChecked),
+                            store.clone(),
+                        ),
+                        None => Box(Modifier::new()),
+                    },
+                    if s.show_maintenance || s.cache_clean_plan.is_some() {
+                        maintenance_panel(
+                            store.clone(),
+                            s.cache_clean_plan.as_ref(),
+                            s.confirm_clear_build_cache,
+                        )
+                    } else {
+                        Box(Modifier::new())
+                    },
+                )),
+                match &s.upgrade_plan {
+                    Some(plan) => upgrade_plan_panel(store.clone(), plan),
+                    None => Box(Modifier::new()),
+                },
+                match &s.rebuilds {
+                    Some(items) => rebuilds_panel(store.clone(), items),
+                    None => Box(Modifier::new()),
+                },
+                if s.last_hooks.is_empty() {
+                    Box(Modifier::new())
+                } else {
+                    hooks_panel(store.clone(), &s.last_hooks)
+                },
+            )),
             // Footer / status
             Row(Modifier::new().padding(8.0)).child((
                 Text("Status").size(12.0).color(Color::from_hex("#888888")),
@@ -335,34 +1994,93 @@ pub fn root_view(store: Rc<Store>) -> View {
                 .color(Color::from_hex("#A0A0A0"))
                 .modifier(Modifier::new().padding(4.0)),
                 Spacer(),
-                Button(
-                    if s.log_expanded {
-                        "Hide log"
-                    } else {
-                        "Show log"
-                    },
-                    {
-                        let store = store.clone();
-                        move || store.dispatch(Action::ToggleLog)
-                    },
-                ),
-            )),
-            if s.log_expanded {
-                Box(Modifier::new()
-                    .fill_max_size()
-                    .size(0.0, 180.0)
-                    .background(Color::TRANSPARENT) //Color::from_hex("#101010"))
-                    // .border(1.0, Color::from_hex("#2A2A2A"), 6.0)
-                    .clip_rounded(6.0))
-                .child(
-                    Text(s.progress_log.clone())
+                match &s.download_mirror {
+                    Some(host) => Text(format!("via {host}"))
                         .size(12.0)
-                        .color(Color::from_hex("#B0B0B0"))
-                        .modifier(Modifier::new().padding(8.0)),
-                )
-            } else {
-                Box(Modifier::new())
+                        .color(Color::from_hex("#888888"))
+                        .modifier(Modifier::new().padding(4.0)),
+                    None => Box(Modifier::new()),
+                },
+                match s.download_rate.filter(|r| *r < SLOW_DOWNLOAD_RATE) {
+                    Some(rate) => Text(format!(
+                        "Slow download ({:.0} KiB/s). Re-ranking mirrors with reflector may help.",
+                        rate / 1024.0
+                    ))
+                    .size(12.0)
+                    .color(Color::from_hex("#E0B050"))
+                    .modifier(Modifier::new().padding(4.0)),
+                    None => Box(Modifier::new()),
+                },
+                match &toast {
+                    Some(t) => badge(t, Color::from_hex("#4B5563")),
+                    None => Box(Modifier::new()),
+                },
+                match s.progress {
+                    Some((_, percent, bytes)) => progress_bar(percent, bytes),
+                    None => Box(Modifier::new()),
+                },
+                Row(Modifier::new()).child((
+                    Button(
+                        if s.show_activity {
+                            "Hide activity"
+                        } else {
+                            "Activity"
+                        },
+                        {
+                            let store = store.clone();
+                            move || store.dispatch(Action::ToggleActivity)
+                        },
+                    ),
+                    Button(
+                        if s.log_expanded {
+                            "Hide log"
+                        } else {
+                            "Show log"
+                        },
+                        {
+                            let store = store.clone();
+                            move || store.dispatch(Action::ToggleLog)
+                        },
+                    ),
+                )),
+            )),
+            Column(Modifier::new()).child((
+                if s.show_activity {
+                    activity_panel(&s.activity)
+                } else {
+                    Box(Modifier::new())
+                },
+                if s.log_expanded {
+                    Box(Modifier::new()
+                        .fill_max_size()
+                        .size(0.0, 180.0)
+                        .background(Color::TRANSPARENT) //Color::from_hex("#101010"))
+                        // .border(1.0, Color::from_hex("#2A2A2A"), 6.0)
+                        .clip_rounded(6.0))
+                    .child(
+                        Text(s.progress_log.clone())
+                            .size(12.0)
+                            .color(Color::from_hex("#B0B0B0"))
+                            .modifier(Modifier::new().padding(8.0)),
+                    )
+                } else {
+                    Box(Modifier::new())
+                },
+            )),
+            match &s.pending_confirm {
+                Some((id, kind)) => {
+                    let preview = s
+                        .remove_preview
+                        .as_ref()
+                        .filter(|(p, _)| p == id && *kind == ConfirmKind::Remove)
+                        .map(|(_, p)| p);
+                    confirm_dialog(store.clone(), id, *kind, preview)
+                }
+                None => Box(Modifier::new()),
             },
         )),
-    )
+    );
+    store.prefetch_visible(visible.take());
+    store.search_when_idle();
+    view
 }
@@ -0,0 +1,18 @@
+use std::thread::spawn;
+
+/// Posts a freedesktop "N updates available" notification.
+///
+/// Runs on its own thread: the D-Bus round trip can stall for a while when no
+/// notification daemon is running, and a missing daemon is not worth reporting.
+pub fn upgrades_available(count: usize) {
+    spawn(move || {
+        let _ = notify_rust::Notification::new()
+            .appname("soredowe")
+            .summary(&match count {
+                1 => "1 update available".to_string(),
+                n => format!("{n} updates available"),
+            })
+            .icon("system-software-update")
+            .show();
+    });
+}
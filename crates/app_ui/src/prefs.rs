@@ -0,0 +1,94 @@
+use crate::state::{AppState, SortMode};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Filter, sort and notification choices, kept across restarts.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Prefs {
+    pub filter_repo: bool,
+    pub filter_aur: bool,
+    pub filter_flatpak: bool,
+    pub filter_installed: bool,
+    pub filter_out_of_date: bool,
+    pub special_variants: bool,
+    pub sort: SortMode,
+    pub notify_upgrades: bool,
+    /// Package names "Upgrade all" leaves out.
+    pub ignore: Vec<String>,
+}
+
+impl Default for Prefs {
+    fn default() -> Self {
+        Self {
+            filter_repo: true,
+            filter_aur: true,
+            filter_flatpak: true,
+            filter_installed: false,
+            filter_out_of_date: false,
+            special_variants: true,
+            sort: SortMode::default(),
+            notify_upgrades: true,
+            ignore: Vec::new(),
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/soredowe/prefs.json`, falling back to `~/.config`.
+pub fn default_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|h| Path::new(&h).join(".config")))?;
+    Some(base.join("soredowe").join("prefs.json"))
+}
+
+impl Prefs {
+    /// A missing or malformed file gives the defaults.
+    pub fn load(path: &Path) -> Self {
+        fs::read(path)
+            .ok()
+            .and_then(|b| serde_json::from_slice(&b).ok())
+            .unwrap_or_default()
+    }
+
+    /// Writes through a temp file, like `Cache::save`.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_vec_pretty(self).map_err(|e| e.to_string())?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, path).map_err(|e| e.to_string())
+    }
+
+    pub fn of(s: &AppState) -> Self {
+        Self {
+            filter_repo: s.filter_repo,
+            filter_aur: s.filter_aur,
+            filter_flatpak: s.filter_flatpak,
+            filter_installed: s.filter_installed,
+            filter_out_of_date: s.filter_out_of_date,
+            special_variants: s.special_variants,
+            sort: s.sort,
+            notify_upgrades: s.notify_upgrades,
+            ignore: s.ignore.clone(),
+        }
+    }
+
+    pub fn apply(&self, s: &mut AppState) {
+        s.filter_repo = self.filter_repo;
+        s.filter_aur = self.filter_aur;
+        s.filter_flatpak = self.filter_flatpak;
+        s.filter_installed = self.filter_installed;
+        s.filter_out_of_date = self.filter_out_of_date;
+        s.special_variants = self.special_variants;
+        s.sort = self.sort;
+        s.notify_upgrades = self.notify_upgrades;
+        s.ignore = self.ignore.clone();
+    }
+}
@@ -1,14 +1,56 @@
-use crossbeam_channel as chan;
+use crate::cache::Cache;
+use crate::commands::{CommandKind, shell_command};
+use crate::prefs::Prefs;
 use domain::*;
 use repose_core::signal::signal;
 
 const MAX_LOG: usize = 256 * 1024;
+/// Finished and running operations kept in `AppState::activity`; older ones drop off.
+const MAX_ACTIVITY: usize = 200;
+/// Sustained download rate (bytes/sec) below which the footer suggests re-ranking mirrors.
+pub const SLOW_DOWNLOAD_RATE: f64 = 256.0 * 1024.0;
+/// How long a toast stays in the footer.
+pub const TOAST_TTL: std::time::Duration = std::time::Duration::from_secs(4);
+/// How long a row changed by a re-search stays highlighted, fading out.
+pub const CHANGE_HIGHLIGHT: std::time::Duration = std::time::Duration::from_secs(3);
+/// Rows must stay on screen this long (i.e. scrolling settled) before their details are
+/// prefetched.
+const PREFETCH_DELAY: std::time::Duration = std::time::Duration::from_millis(150);
+/// Typing must pause this long before the query is searched on its own.
+const SEARCH_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(300);
+/// Shorter queries wait for Enter; the AUR refuses them anyway.
+const MIN_TYPEAHEAD: usize = 2;
+/// Prefetched details kept at most; the cache starts over beyond this.
+const MAX_PREFETCHED: usize = 500;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum SortMode {
     NameAsc,
     NameDesc,
     Popularity,
+    /// Newest `last_updated` first; packages without one (repo, Flatpak) go last.
+    RecentlyUpdated,
+}
+
+/// One operation in the activity panel, from the moment the executor starts it.
+#[derive(Clone, Debug)]
+pub struct JobRecord {
+    pub job_id: u64,
+    pub kind: JobKind,
+    /// Package name(s) the job was about; empty for jobs without a package.
+    pub target: String,
+    pub started: std::time::SystemTime,
+    pub ended: Option<std::time::SystemTime>,
+    /// `Finished`, `Failed` or `Cancelled` once ended.
+    pub outcome: Option<Stage>,
+}
+
+/// Which screen a narrow window shows; wide windows show both side by side.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Screen {
+    #[default]
+    List,
+    Details,
 }
 
 impl Default for SortMode {
@@ -20,54 +62,644 @@ impl Default for SortMode {
 #[derive(Clone, Debug, Default)]
 pub struct AppState {
     pub query: String,
-    pub results: Vec<PackageSummary>,
+    /// Shared so cloning the state (every dispatch) and rendering don't copy long lists.
+    pub results: std::rc::Rc<[PackageSummary]>,
+    /// AUR search results named like a repo result (shadowed names, same-named forks),
+    /// left out of `results` unless `show_shadowed`.
+    pub shadowed: Vec<PackageSummary>,
+    pub show_shadowed: bool,
     pub selected: Option<PackageId>,
     pub filter_repo: bool,
     pub filter_aur: bool,
+    pub filter_flatpak: bool,
     pub filter_installed: bool,
+    /// Only packages flagged out of date upstream.
+    pub filter_out_of_date: bool,
     pub sort: SortMode,
     pub progress_log: String,
     pub error: Option<String>,
     pub log_expanded: bool,
     pub in_upgrades_view: bool,
+    /// The list shows orphaned dependencies rather than search results.
+    pub in_orphans_view: bool,
+    /// Badge AUR -git/-bin style variants and list them after regular packages.
+    pub special_variants: bool,
+    /// Sync databases were never downloaded; the empty results area prompts for a refresh.
+    pub sync_db_missing: bool,
+    /// The files database behind path searches is missing or stale; a notice offers to sync it.
+    pub files_db_needs_sync: bool,
+    /// Result of the last AUR rebuild check, until dismissed; empty when nothing is broken.
+    pub rebuilds: Option<Vec<PackageSummary>>,
+    /// Hooks from the last transaction that ran any; cleared when dismissed.
+    pub last_hooks: Vec<HookRun>,
+    /// Selecting a package pushes its details; `Back` pops to the list.
+    pub screen: Screen,
+    /// Latest fetched details; only meaningful while it matches `selected`.
+    pub details: Option<PackageDetails>,
+    /// Short neutral notice (e.g. "cancelled") and when it was raised; expires after `TOAST_TTL`.
+    pub toast: Option<(String, std::time::Instant)>,
+    /// Mirror host of the transaction in progress.
+    pub download_mirror: Option<String>,
+    /// Bytes/sec, measured from successive `Progress.bytes` samples.
+    pub download_rate: Option<f64>,
+    rate_sample: Option<(std::time::Instant, u64)>,
+    /// The job the executor is on and how far it got, for the footer progress bar.
+    pub progress: Option<JobProgress>,
+    /// Upgrades the next "Upgrade all" leaves out; never persisted, and cleared once that
+    /// runs or the view changes.
+    pub skip_once: Vec<PackageId>,
+    /// Names "Upgrade all" always leaves out, like pacman's `IgnorePkg`; persisted.
+    pub ignore: Vec<String>,
+    /// Show a checkbox on each result for batch install/remove.
+    pub selection_mode: bool,
+    /// Packages ticked in selection mode; kept across searches so a set can be gathered
+    /// from several queries.
+    pub checked: std::collections::HashSet<PackageId>,
+    /// Checked packages waiting on confirmation before a batch remove.
+    pub batch_remove: Option<Vec<PackageId>>,
+    /// An AUR package waiting on confirmation of the build-only dependencies it pulls in.
+    pub build_deps: Option<(PackageId, Vec<String>)>,
+    /// Build-only dependencies left orphaned by the last AUR install, offered for removal.
+    pub orphaned_build_deps: Vec<String>,
+    /// A job that failed because another pacman held the database lock, to retry.
+    pub db_locked: Option<(JobKind, JobPayload)>,
+    /// An operation waiting on the user in a modal dialog before it's queued.
+    pub pending_confirm: Option<(PackageId, ConfirmKind)>,
+    /// Dry run of the removal being confirmed, once it's back.
+    pub remove_preview: Option<(PackageId, RemovePreview)>,
+    /// What "Upgrade all" would change, shown for confirmation before it runs.
+    pub upgrade_plan: Option<TransactionPlan>,
+    /// The maintenance actions row under the header.
+    pub show_maintenance: bool,
+    /// What cleaning the package cache would remove, shown for confirmation before it runs.
+    pub cache_clean_plan: Option<CacheCleanPlan>,
+    /// Asking before the kept AUR checkouts are deleted.
+    pub confirm_clear_build_cache: bool,
+    /// Total download of the listed upgrades, if any backend reported sizes.
+    pub upgrades_download: Option<u64>,
+    /// Repo packages with an upgrade pending, from the last upgrades listing.
+    pub repo_upgrades: usize,
+    /// Upgrades from every source in the last listing, counted on the Upgrades button;
+    /// `None` until the first listing.
+    pub available_upgrade_count: Option<usize>,
+    /// Post a desktop notification when a background check finds more upgrades than the
+    /// listing before it.
+    pub notify_upgrades: bool,
+    /// A `CheckUpgrades` listing is on its way; only those can notify.
+    pub upgrade_check_pending: bool,
+    /// A repo install held back because upgrades are pending; installing it alone would be
+    /// a partial upgrade.
+    pub partial_upgrade_risk: Option<PackageId>,
+    /// An install waiting on the full upgrade with this job id; it's queued only once that
+    /// finishes, so a failed or cancelled upgrade can't leave a partial one behind.
+    pub install_after_upgrade: Option<(u64, PackageId)>,
+    /// File list of the last installed package it was fetched for.
+    pub files: Option<(PackageId, Vec<std::path::PathBuf>)>,
+    /// Expand the file list in the details card.
+    pub show_files: bool,
+    /// Packaging commit log of a package, fetched on request.
+    pub history: Option<(PackageId, Vec<LogEntry>)>,
+    pub show_history: bool,
+    /// Build scripts of an AUR package under review, as (file name, contents); `None` inside
+    /// while they're fetched.
+    pub build_files: Option<(PackageId, Option<Vec<BuildFile>>)>,
+    /// Older versions of a package left in the package cache, to downgrade to.
+    pub cached_versions: Option<(PackageId, Vec<String>)>,
+    pub show_cached_versions: bool,
+    /// Reverse dependency tree of an installed package, fetched on request.
+    pub who_requires: Option<(PackageId, Vec<String>)>,
+    pub show_who_requires: bool,
+    /// The `-bin` or from-source counterpart of an AUR package, looked up on selection;
+    /// `None` inside once it's known there is none.
+    pub sibling: Option<(PackageId, Option<PackageSummary>)>,
+    /// Unfiltered results of the last search and whether it was installed-only, kept to diff
+    /// a re-run of the same query.
+    last_search: Option<(String, bool, Vec<PackageSummary>)>,
+    /// Rows a re-search changed (installed, removed, new version) and when.
+    pub changed: Vec<(PackageId, std::time::Instant)>,
+    /// Informational banner about an unusual environment (container, chroot, no polkit).
+    pub environment_notice: Option<String>,
+    /// Set while the results come from the disk cache: when they were saved.
+    pub cached_at: Option<std::time::SystemTime>,
+    /// Latest search and details jobs, to fall back to the cache if they fail.
+    search_job: Option<(u64, String)>,
+    /// Results gathered so far from a search still running, by its job id.
+    partial: Option<(u64, Vec<PackageSummary>)>,
+    /// When the query was last edited without being searched, for type-ahead.
+    typed_at: Option<std::time::Instant>,
+    details_job: Option<(u64, PackageId)>,
+    /// Jobs waiting behind the running one, in run order; mirrors `Store::jobs`.
+    pub pending: Vec<Job>,
+    /// The install, remove or upgrade the executor is running, which the header offers to
+    /// cancel.
+    pub running: Option<(u64, JobKind)>,
+    /// Jobs queued or running, background detail prefetches aside; the header shows a
+    /// spinner while nonzero.
+    pub pending_jobs: usize,
+    /// What those jobs are, for buttons to show their own job as busy.
+    pub busy: Vec<JobKind>,
+    /// The running job waits on a polkit dialog.
+    pub authorizing: bool,
+    /// Installs, removes, upgrades and syncs this session, oldest first.
+    pub activity: Vec<JobRecord>,
+    pub show_activity: bool,
+}
+
+impl AppState {
+    /// Whether the source filter chips let packages from `source` through. Sources without a
+    /// chip (custom backends) are always shown.
+    pub fn source_enabled(&self, source: Source) -> bool {
+        match source {
+            Source::Repo => self.filter_repo,
+            Source::Aur => self.filter_aur,
+            Source::Flatpak => self.filter_flatpak,
+            Source::Custom(_) => true,
+        }
+    }
+
+    /// The toast text, if it hasn't expired yet.
+    pub fn active_toast(&self) -> Option<&str> {
+        self.toast
+            .as_ref()
+            .filter(|(_, at)| at.elapsed() < TOAST_TTL)
+            .map(|(t, _)| t.as_str())
+    }
+
+    /// What "Upgrade all" leaves out: upgrades skipped once, and every name on the ignore
+    /// list, listed or not. The ignore list mirrors pacman's IgnorePkg, so its names are
+    /// always repo packages, whatever else happens to be listed under them.
+    pub fn upgrade_skips(&self) -> Vec<PackageId> {
+        let ignored = self
+            .ignore
+            .iter()
+            .map(|name| PackageId {
+                name: name.clone(),
+                source: Source::Repo,
+            })
+            .filter(|id| !self.skip_once.contains(id));
+        self.skip_once.iter().cloned().chain(ignored).collect()
+    }
+
+    /// Records a listing of `found` upgrades, giving the count to announce if it's a
+    /// background check that found more than the listing before. The first listing is
+    /// only a baseline, so launching the app doesn't announce what was already pending.
+    fn count_upgrades(&mut self, found: usize) -> Option<usize> {
+        let background = std::mem::take(&mut self.upgrade_check_pending);
+        let before = self.available_upgrade_count.replace(found);
+        (background && before.is_some_and(|n| found > n)).then_some(found)
+    }
+
+    /// Whether details for `id` are being fetched.
+    pub fn details_loading(&self, id: &PackageId) -> bool {
+        self.details_job.as_ref().is_some_and(|(_, d)| d == id)
+    }
+
+    /// Empties `checked`, in name order so batch jobs read predictably.
+    fn take_checked(&mut self) -> Vec<PackageId> {
+        let mut ids: Vec<PackageId> = self.checked.drain().collect();
+        ids.sort_by(|a, b| a.name.cmp(&b.name));
+        ids
+    }
+
+    /// Opens a record for `job` if it's an operation worth listing in the activity panel.
+    fn record_start(&mut self, job: &Job) {
+        let target = match &job.payload {
+            JobPayload::Package(id) => id.name.clone(),
+            JobPayload::Packages(ids) => ids
+                .iter()
+                .map(|id| id.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            JobPayload::Version { id, version } => format!("{} {version}", id.name),
+            _ => String::new(),
+        };
+        if !matches!(
+            job.kind,
+            JobKind::Refresh
+                | JobKind::RefreshFiles
+                | JobKind::Install
+                | JobKind::InstallMany
+                | JobKind::Remove
+                | JobKind::RemoveMany
+                | JobKind::Upgrade
+                | JobKind::UpgradeAll
+                | JobKind::Downgrade
+                | JobKind::CleanCache
+                | JobKind::ClearBuildCache
+        ) {
+            return;
+        }
+        self.activity.push(JobRecord {
+            job_id: job.id,
+            kind: job.kind,
+            target,
+            started: std::time::SystemTime::now(),
+            ended: None,
+            outcome: None,
+        });
+        if self.activity.len() > MAX_ACTIVITY {
+            let cut = self.activity.len() - MAX_ACTIVITY;
+            self.activity.drain(..cut);
+        }
+    }
+
+    fn record_end(&mut self, job_id: u64, outcome: Stage) {
+        if let Some(r) = self
+            .activity
+            .iter_mut()
+            .rev()
+            .find(|r| r.job_id == job_id && r.ended.is_none())
+        {
+            r.ended = Some(std::time::SystemTime::now());
+            r.outcome = Some(outcome);
+        }
+    }
+
+    fn push_log(&mut self, line: &str) {
+        self.progress_log.push_str(line);
+        self.progress_log.push('\n');
+        if self.progress_log.len() > MAX_LOG {
+            // Whole lines only: a raw byte offset could split a multibyte character (and
+            // panic) or leave half a line at the top. A '\n' byte is always a char boundary.
+            let over = self.progress_log.len() - MAX_LOG;
+            let cut = self.progress_log.as_bytes()[over..]
+                .iter()
+                .position(|b| *b == b'\n')
+                .map_or(self.progress_log.len(), |i| over + i + 1);
+            self.progress_log.drain(..cut);
+        }
+    }
+
+    /// Shows search results, applying the query text and filter chips.
+    fn show_search_results(&mut self, items: Vec<PackageSummary>) {
+        let q = self.query.to_lowercase();
+        let mut v = items
+            .into_iter()
+            .filter(|x| {
+                if q.is_empty() {
+                    true
+                } else {
+                    let name = x.id.name.to_lowercase();
+                    let desc = x.description.to_lowercase();
+                    name.contains(&q) || desc.contains(&q)
+                }
+            })
+            // Existing filters
+            .filter(|x| self.source_enabled(x.id.source))
+            .filter(|x| {
+                if self.filter_installed {
+                    x.installed
+                } else {
+                    true
+                }
+            })
+            .filter(|x| !self.filter_out_of_date || x.out_of_date.is_some())
+            .collect::<Vec<_>>();
+        self.sort_results(&mut v);
+        self.results = self.fold_shadowed(v).into();
+        if let Some(sel) = &self.selected {
+            if !self.results.iter().any(|r| r.id == *sel) {
+                self.selected = None;
+            }
+        }
+    }
+
+    /// Moves AUR rows that share a name with a repo row into `shadowed`. With
+    /// `show_shadowed` they stay in, each right after its repo row.
+    fn fold_shadowed(&mut self, v: Vec<PackageSummary>) -> Vec<PackageSummary> {
+        let repo: std::collections::HashSet<String> = v
+            .iter()
+            .filter(|p| p.id.source == Source::Repo)
+            .map(|p| p.id.name.clone())
+            .collect();
+        let (twins, mut rest): (Vec<_>, Vec<_>) = v
+            .into_iter()
+            .partition(|p| p.id.source == Source::Aur && repo.contains(&p.id.name));
+        self.shadowed = twins;
+        if self.show_shadowed {
+            for twin in &self.shadowed {
+                let at = rest
+                    .iter()
+                    .position(|p| p.id.source == Source::Repo && p.id.name == twin.id.name)
+                    .map_or(rest.len(), |i| i + 1);
+                rest.insert(at, twin.clone());
+            }
+        }
+        rest
+    }
+
+    fn sort_results(&self, v: &mut [PackageSummary]) {
+        match self.sort {
+            SortMode::NameAsc => v.sort_by(|a, b| a.id.name.cmp(&b.id.name)),
+            SortMode::NameDesc => v.sort_by(|a, b| b.id.name.cmp(&a.id.name)),
+            SortMode::Popularity => {
+                v.sort_by(|a, b| b.popular.unwrap_or(0).cmp(&a.popular.unwrap_or(0)))
+            }
+            // `None` orders before any `Some`, so descending puts it last
+            SortMode::RecentlyUpdated => v.sort_by_key(|p| std::cmp::Reverse(p.last_updated)),
+        }
+        if self.special_variants {
+            // Stable, so the chosen order holds within each group
+            v.sort_by_key(|p| p.variant != AurVariant::Regular);
+        }
+    }
+}
+
+/// A job id with its latest percent and (bytes done, total), as far as reported.
+pub type JobProgress = (u64, Option<f32>, Option<(u64, u64)>);
+
+/// Operations that ask before running.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfirmKind {
+    /// `pacman -Rns` also takes dependencies nothing else needs, more than the click suggests.
+    Remove,
+}
+
+impl ConfirmKind {
+    pub fn question(self, id: &PackageId) -> String {
+        match self {
+            ConfirmKind::Remove if id.source == Source::Flatpak => format!("Remove {}?", id.name),
+            ConfirmKind::Remove => format!("Remove {} and its unneeded dependencies?", id.name),
+        }
+    }
+
+    /// Label of the button that goes ahead.
+    pub fn verb(self) -> &'static str {
+        match self {
+            ConfirmKind::Remove => "Remove",
+        }
+    }
+
+    /// What confirming dispatches.
+    pub fn action(self, id: PackageId) -> Action {
+        match self {
+            ConfirmKind::Remove => Action::Remove(id),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub enum Action {
     SetQuery(String),
     Search,
+    Refresh,
     Upgrades,
+    /// List dependencies nothing requires any more.
+    ShowOrphans,
+    /// Plan "Upgrade all" and show what it would change.
     UpgradeAll,
+    /// Run the upgrade the shown plan describes.
+    ConfirmUpgradeAll,
+    CancelUpgradePlan,
+    ToggleMaintenance,
+    /// Dry-run cleaning the package cache and show what it would free.
+    PlanCleanCache,
+    /// Run the cache clean the shown plan describes.
+    ConfirmCleanCache,
+    CancelCleanCache,
+    /// Ask before deleting the AUR checkouts kept between builds.
+    ClearBuildCache,
+    ConfirmClearBuildCache,
+    CancelClearBuildCache,
+    /// Build the AUR package whose build dependencies are shown.
+    ConfirmBuild,
+    CancelBuild,
+    RemoveOrphanedBuildDeps,
+    KeepOrphanedBuildDeps,
     Upgrade(PackageId),
     Install(PackageId),
     Remove(PackageId),
+    /// Ask before running `kind` on a package; confirming dispatches `ConfirmKind::action`.
+    Confirm(PackageId, ConfirmKind),
+    CancelConfirm,
+    /// Queue again the job that found the package database locked.
+    RetryLocked,
+    DismissLocked,
     Progress(Progress),
     Event(Event),
     ClearError,
     Select(PackageId),
     ClearSelection,
+    /// Leave the details screen of a narrow window.
+    Back,
     ToggleFilterRepo,
     ToggleFilterAur,
+    ToggleFilterFlatpak,
     ToggleFilterInstalled,
+    ToggleFilterOutOfDate,
+    ToggleSpecialVariants,
+    /// Show or hide AUR results named like a repo result.
+    ToggleShadowed,
     SetSort(SortMode),
     ToggleLog,
+    ToggleActivity,
+    DismissHooks,
+    CopyCommand(CommandKind, Vec<PackageId>),
+    /// Run the terminal equivalent interactively, for prompts --noconfirm can't answer. The
+    /// db watcher's SystemChanged picks up whatever it changes.
+    RunInTerminal(CommandKind, Vec<PackageId>),
+    /// Refetch details for a package, bypassing backend caches.
+    RefreshDetails(PackageId),
+    /// Look for AUR packages broken by library updates.
+    CheckRebuilds,
+    /// Rebuild every package the last check reported.
+    RebuildAll,
+    DismissRebuilds,
+    /// Toggle leaving a package out of the next "Upgrade all".
+    ToggleSkipOnce(PackageId),
+    /// Toggle a package name on the persistent ignore list.
+    ToggleIgnore(String),
+    /// Show or hide the result checkboxes; leaving selection mode unchecks everything.
+    ToggleSelectionMode,
+    ToggleChecked(PackageId),
+    /// Install every checked package: repo packages in one transaction, AUR ones in turn.
+    InstallChecked,
+    /// Ask before removing every checked package.
+    RemoveChecked,
+    ConfirmRemoveChecked,
+    CancelRemoveChecked,
+    /// Sync the files database, then redo a pending path search.
+    RefreshFilesDb,
+    /// Re-list upgrades in the background to update `repo_upgrades` and
+    /// `available_upgrade_count`.
+    CheckUpgrades,
+    /// Install despite the partial-upgrade warning.
+    ConfirmInstall(PackageId),
+    /// Run a full upgrade, then the install that was held back.
+    UpgradeThenInstall(PackageId),
+    DismissPartialUpgrade,
+    /// Toggle the file list, fetching it if needed.
+    ShowFiles(PackageId),
+    /// Toggle the packaging commit log, fetching it if needed.
+    ShowHistory(PackageId),
+    /// Fetch an AUR package's PKGBUILD and install hooks to read before building it.
+    ReviewBuildFiles(PackageId),
+    CloseBuildFiles,
+    /// Toggle the tree of installed packages depending on an installed package.
+    ShowWhoRequires(PackageId),
+    /// Toggle the cached older versions of an installed package.
+    ShowCachedVersions(PackageId),
+    /// Reinstall an older version from the package cache.
+    Downgrade(PackageId, String),
+    /// Select the counterpart of the selected AUR package, listing it if it isn't.
+    SwitchVariant,
+    /// Launch the app behind a desktop entry.
+    Launch(std::path::PathBuf),
+    /// Put a package name on the clipboard.
+    CopyName(String),
+    /// Open a package homepage in the browser.
+    OpenUrl(String),
+    /// Move a pending job one place earlier in the queue.
+    Prioritize(u64),
+    /// Move a pending job one place later in the queue.
+    Deprioritize(u64),
+    /// Drop a job before it starts.
+    CancelQueued(u64),
+    /// Stop a queued or running job through its `CancelToken`.
+    Cancel(u64),
+}
+
+/// Details fetched ahead for the rows on screen, so selecting one shows them at once.
+#[derive(Default)]
+struct Prefetch {
+    /// Rows last seen on screen, and since when.
+    rows: Vec<PackageId>,
+    since: Option<std::time::Instant>,
+    requested: bool,
+    /// Cancels the batch once its rows scroll away.
+    cancel: Option<CancelToken>,
+    /// Running prefetch jobs; their progress stays out of the log and toasts.
+    jobs: std::collections::HashSet<u64>,
+    details: std::collections::HashMap<PackageId, PackageDetails>,
 }
 
 pub struct Store {
     pub state: repose_core::signal::Signal<AppState>,
-    pub tx_jobs: chan::Sender<domain::Job>,
+    pub jobs: JobQueue,
     next_id: std::sync::atomic::AtomicU64,
+    /// Operations that go to a terminal instead of running pacman with --noconfirm, so its
+    /// prompts (provider choice, replacements) reach the user.
+    interactive: Vec<CommandKind>,
+    /// Where results are persisted for offline browsing; `None` keeps them in memory only.
+    cache: Option<(std::path::PathBuf, std::cell::RefCell<Cache>)>,
+    /// List an AUR package's build-only dependencies for confirmation before building it.
+    confirm_build_deps: bool,
+    /// Show an AUR package's build files, PKGBUILD diff first, before upgrading it.
+    review_aur_upgrades: bool,
+    /// `Quiet` keeps only warnings and errors in the log.
+    verbosity: Verbosity,
+    prefetch: std::cell::RefCell<Prefetch>,
+    /// Cancel tokens of queued and running jobs, until they finish.
+    in_flight: std::cell::RefCell<std::collections::HashMap<u64, Job>>,
+    /// Where filter and sort choices are saved; `None` forgets them on exit.
+    prefs: Option<std::path::PathBuf>,
 }
 impl Store {
-    pub fn new(tx_jobs: chan::Sender<domain::Job>) -> Self {
+    pub fn new(jobs: JobQueue) -> Self {
         let mut s = AppState::default();
-        s.filter_repo = true;
-        s.filter_aur = true;
-        s.sort = SortMode::default();
+        Prefs::default().apply(&mut s);
         Self {
             state: signal(s),
-            tx_jobs,
+            jobs,
             next_id: std::sync::atomic::AtomicU64::new(1),
+            interactive: Vec::new(),
+            cache: None,
+            confirm_build_deps: false,
+            review_aur_upgrades: false,
+            verbosity: Verbosity::default(),
+            prefetch: Default::default(),
+            in_flight: Default::default(),
+            prefs: None,
+        }
+    }
+
+    /// Start from the filters and sort saved at `path`, and save them there on each change.
+    pub fn with_prefs(mut self, path: std::path::PathBuf) -> Self {
+        let mut s = self.state.get();
+        Prefs::load(&path).apply(&mut s);
+        self.state.set(s);
+        self.prefs = Some(path);
+        self
+    }
+
+    /// Persist results and details at `path`, and start from the last cached search.
+    pub fn with_cache(mut self, path: std::path::PathBuf) -> Self {
+        let cache = Cache::load(&path);
+        if let Some(last) = cache.latest_search() {
+            let mut s = self.state.get();
+            s.query = last.query.clone();
+            s.cached_at = Some(last.at);
+            s.show_search_results(last.items.clone());
+            self.state.set(s);
+        }
+        self.cache = Some((path, std::cell::RefCell::new(cache)));
+        self
+    }
+
+    /// Hand `kinds` on repo and AUR packages to the terminal: their command is copied to the
+    /// clipboard rather than queued.
+    pub fn with_interactive(mut self, kinds: Vec<CommandKind>) -> Self {
+        self.interactive = kinds;
+        self
+    }
+
+    pub fn with_environment_notice(self, notice: Option<String>) -> Self {
+        self.state.update(|s| s.environment_notice = notice);
+        self
+    }
+
+    /// Which log lines to keep; pass the same level to the backends.
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Ask before an AUR build installs dependencies it only needs to build.
+    pub fn with_build_dep_confirmation(mut self, confirm: bool) -> Self {
+        self.confirm_build_deps = confirm;
+        self
+    }
+
+    /// Hold single AUR upgrades for a review of what changed in the PKGBUILD since the last
+    /// build; the upgrade goes ahead once it's accepted.
+    pub fn with_aur_upgrade_review(mut self, review: bool) -> Self {
+        self.review_aur_upgrades = review;
+        self
+    }
+
+    /// When a search or details job fails (e.g. offline), shows its cached result instead.
+    fn fall_back_to_cache(&self, s: &mut AppState, job_id: u64) {
+        let Some((_, cache)) = &self.cache else {
+            return;
+        };
+        let cache = cache.borrow();
+        let search = s.search_job.as_ref().filter(|(j, _)| *j == job_id);
+        let details = s.details_job.as_ref().filter(|(j, _)| *j == job_id);
+        if let Some(hit) = search.and_then(|(_, q)| cache.search(q)) {
+            s.push_log(&format!("showing cached results for \"{}\"", hit.query));
+            s.cached_at = Some(hit.at);
+            s.show_search_results(hit.items.clone());
+        } else if let Some((_, d)) = details.and_then(|(_, id)| cache.details(id)) {
+            s.push_log(&format!("showing cached details for {}", d.summary.id.name));
+            s.details = Some(d.clone());
+        }
+    }
+
+    fn wants_terminal(&self, kind: CommandKind, id: Option<&PackageId>) -> bool {
+        self.interactive.contains(&kind)
+            && id.is_none_or(|id| matches!(id.source, Source::Repo | Source::Aur))
+    }
+
+    fn copy_for_terminal(&self, s: &mut AppState, cmd: String) {
+        match crate::clipboard::copy(&cmd) {
+            Ok(()) => {
+                s.push_log(&format!("copied to clipboard:\n{cmd}"));
+                s.toast = Some((
+                    "Command copied; run it in a terminal".into(),
+                    std::time::Instant::now(),
+                ));
+            }
+            Err(e) => s.error = Some(format!("clipboard unavailable: {e}")),
         }
     }
     fn jid(&self) -> u64 {
@@ -75,18 +707,163 @@ impl Store {
             .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
     }
 
+    /// Queues `job`, keeping its token so `Action::Cancel` can reach it.
+    fn push(&self, job: Job) {
+        self.in_flight.borrow_mut().insert(job.id, job.clone());
+        self.jobs.push(job);
+    }
+
+    /// Stops the search a new one is about to replace, queued or running.
+    fn cancel_search(&self, s: &AppState) {
+        if let Some(job) = s
+            .search_job
+            .as_ref()
+            .and_then(|(j, _)| self.in_flight.borrow().get(j).cloned())
+        {
+            job.cancel.cancel();
+        }
+    }
+
+    fn queue(&self, kind: JobKind, payload: JobPayload) {
+        self.push(Job {
+            id: self.jid(),
+            kind,
+            payload,
+            created_at: std::time::SystemTime::now(),
+            cancel: CancelToken::new(),
+        });
+    }
+
+    fn select(&self, s: &mut AppState, id: PackageId) {
+        // Launch needs the file list, so fetch it up front for installed packages
+        let installed = s.results.iter().any(|p| p.id == id && p.installed);
+        if installed && s.files.as_ref().is_none_or(|(f, _)| *f != id) {
+            self.queue(JobKind::Files, JobPayload::Package(id.clone()));
+        }
+        if id.source == Source::Aur && s.sibling.as_ref().is_none_or(|(x, _)| *x != id) {
+            s.sibling = None;
+            self.queue(JobKind::Sibling, JobPayload::Package(id.clone()));
+        }
+        if s.selected.as_ref() != Some(&id) {
+            s.show_history = false;
+            s.show_cached_versions = false;
+            s.show_who_requires = false;
+        }
+        // Always fetched afresh for a new selection, so the pane doesn't depend on what the
+        // list row happened to carry; prefetched details stand in until it's back.
+        let have = s.details.as_ref().is_some_and(|d| d.summary.id == id);
+        if let Some(d) = self.prefetch.borrow().details.get(&id) {
+            s.details = Some(d.clone());
+        }
+        if !have || s.selected.as_ref() != Some(&id) {
+            let jid = self.jid();
+            s.details_job = Some((jid, id.clone()));
+            self.push(Job {
+                id: jid,
+                kind: JobKind::Details,
+                payload: JobPayload::Details {
+                    id: id.clone(),
+                    force: false,
+                },
+                created_at: std::time::SystemTime::now(),
+                cancel: CancelToken::new(),
+            });
+        }
+        s.selected = Some(id);
+        s.screen = Screen::Details;
+    }
+
+    /// Called each frame; searches the query once typing has paused for `SEARCH_DEBOUNCE`.
+    /// Paths wait for Enter, as each file search scans the whole files database.
+    pub fn search_when_idle(&self) {
+        let s = self.state.get();
+        let Some(at) = s.typed_at else { return };
+        if at.elapsed() < SEARCH_DEBOUNCE {
+            return;
+        }
+        let q = s.query.trim();
+        if q.chars().count() < MIN_TYPEAHEAD || q.starts_with('/') {
+            let mut s = s;
+            s.typed_at = None;
+            self.state.set(s);
+            return;
+        }
+        self.dispatch(Action::Search);
+    }
+
+    /// Called each frame with the rows on screen; once they stop changing, fetches the
+    /// details still missing in one batch.
+    pub fn prefetch_visible(&self, rows: Vec<PackageId>) {
+        let mut p = self.prefetch.borrow_mut();
+        if p.rows != rows {
+            if let Some(cancel) = p.cancel.take() {
+                cancel.cancel();
+            }
+            p.rows = rows;
+            p.since = Some(std::time::Instant::now());
+            p.requested = false;
+            return;
+        }
+        if p.requested || p.since.is_none_or(|t| t.elapsed() < PREFETCH_DELAY) {
+            return;
+        }
+        p.requested = true;
+        let missing: Vec<PackageId> = p
+            .rows
+            .iter()
+            .filter(|id| !p.details.contains_key(*id))
+            .cloned()
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+        let id = self.jid();
+        let cancel = CancelToken::new();
+        p.jobs.insert(id);
+        p.cancel = Some(cancel.clone());
+        self.jobs.push(Job {
+            id,
+            kind: JobKind::PrefetchDetails,
+            payload: JobPayload::Packages(missing),
+            created_at: std::time::SystemTime::now(),
+            cancel,
+        });
+    }
+
+    /// Description from prefetched details, for rows whose search result had none (repo
+    /// search often leaves it empty).
+    pub fn prefetched_description(&self, id: &PackageId) -> Option<String> {
+        self.prefetch
+            .borrow()
+            .details
+            .get(id)
+            .map(|d| d.summary.description.clone())
+            .filter(|d| !d.is_empty())
+    }
+
     pub fn dispatch(&self, a: Action) {
         let mut s = self.state.get();
+        let prefs = Prefs::of(&s);
         match a {
-            Action::SetQuery(q) => s.query = q,
+            Action::SetQuery(q) => {
+                if q != s.query {
+                    s.query = q;
+                    s.typed_at = Some(std::time::Instant::now());
+                }
+            }
             Action::Search => {
+                s.typed_at = None;
                 s.in_upgrades_view = false;
+                s.in_orphans_view = false;
+                s.skip_once.clear();
                 let q = s.query.trim().to_string();
 
+                self.cancel_search(&s);
                 let id = self.jid();
-                let _ = self.tx_jobs.send(Job {
+                s.search_job = Some((id, q.clone()));
+                self.push(Job {
                     id,
-                    kind: JobKind::Search,
+                    kind: search_kind(&q, s.filter_installed),
                     payload: JobPayload::Query(q.clone()),
                     created_at: std::time::SystemTime::now(),
                     cancel: CancelToken::new(),
@@ -94,34 +871,103 @@ impl Store {
 
                 // Clear previous results if query is empty
                 if q.is_empty() {
-                    s.results.clear();
+                    s.results = Default::default();
                     s.selected = None;
                 }
             }
-            Action::Upgrades => {
-                s.in_upgrades_view = true;
+            Action::Refresh => {
+                s.sync_db_missing = false;
                 let id = self.jid();
-                let _ = self.tx_jobs.send(Job {
+                self.push(Job {
                     id,
-                    kind: JobKind::Upgrades,
+                    kind: JobKind::Refresh,
                     payload: JobPayload::None,
                     created_at: std::time::SystemTime::now(),
                     cancel: CancelToken::new(),
                 });
+                // Fresh sync dbs are exactly when a lone `-S` turns into a partial upgrade.
+                self.queue(JobKind::Upgrades, JobPayload::None);
             }
-            Action::UpgradeAll => {
+            Action::ShowOrphans => {
+                s.in_upgrades_view = false;
+                s.in_orphans_view = true;
+                self.queue(JobKind::Orphans, JobPayload::None);
+            }
+            Action::Upgrades => {
+                // Whatever lists next is this one, or shows up in it
+                s.upgrade_check_pending = false;
+                s.in_upgrades_view = true;
+                s.in_orphans_view = false;
                 let id = self.jid();
-                let _ = self.tx_jobs.send(Job {
+                self.push(Job {
                     id,
-                    kind: JobKind::UpgradeAll,
+                    kind: JobKind::Upgrades,
                     payload: JobPayload::None,
                     created_at: std::time::SystemTime::now(),
                     cancel: CancelToken::new(),
                 });
             }
+            Action::UpgradeAll if self.wants_terminal(CommandKind::Upgrade, None) => {
+                let cmd = shell_command(CommandKind::UpgradeAll, &s.upgrade_skips());
+                s.skip_once.clear();
+                self.copy_for_terminal(&mut s, cmd)
+            }
+            Action::UpgradeAll => {
+                // Runs once the plan is confirmed
+                self.queue(JobKind::PlanUpgradeAll, JobPayload::Skip(s.upgrade_skips()));
+            }
+            Action::ConfirmUpgradeAll => {
+                s.upgrade_plan = None;
+                self.queue(JobKind::UpgradeAll, JobPayload::Skip(s.upgrade_skips()));
+                s.skip_once.clear();
+                self.queue(JobKind::Upgrades, JobPayload::None);
+            }
+            Action::CancelUpgradePlan => s.upgrade_plan = None,
+            Action::ToggleMaintenance => s.show_maintenance = !s.show_maintenance,
+            Action::PlanCleanCache => self.queue(JobKind::PlanCleanCache, JobPayload::None),
+            Action::ConfirmCleanCache => {
+                s.cache_clean_plan = None;
+                self.queue(JobKind::CleanCache, JobPayload::None);
+            }
+            Action::CancelCleanCache => s.cache_clean_plan = None,
+            Action::ClearBuildCache => s.confirm_clear_build_cache = true,
+            Action::ConfirmClearBuildCache => {
+                s.confirm_clear_build_cache = false;
+                self.queue(JobKind::ClearBuildCache, JobPayload::None);
+            }
+            Action::CancelClearBuildCache => s.confirm_clear_build_cache = false,
+            Action::ConfirmBuild => {
+                if let Some((id, _)) = s.build_deps.take() {
+                    self.queue(JobKind::Install, JobPayload::Package(id));
+                }
+            }
+            Action::CancelBuild => s.build_deps = None,
+            Action::RemoveOrphanedBuildDeps => {
+                // One transaction, so deps that need each other go together
+                let ids: Vec<PackageId> = std::mem::take(&mut s.orphaned_build_deps)
+                    .into_iter()
+                    .map(|name| PackageId {
+                        name,
+                        source: Source::Repo,
+                    })
+                    .collect();
+                if !ids.is_empty() {
+                    self.queue(JobKind::RemoveMany, JobPayload::Packages(ids));
+                }
+            }
+            Action::KeepOrphanedBuildDeps => s.orphaned_build_deps.clear(),
+            Action::Upgrade(id) if self.wants_terminal(CommandKind::Upgrade, Some(&id)) => {
+                self.copy_for_terminal(&mut s, shell_command(CommandKind::Upgrade, &[id]))
+            }
+            Action::Upgrade(id) if id.source == Source::Aur && self.review_aur_upgrades => {
+                // Shown in the details pane, which the review needs to be on
+                self.select(&mut s, id.clone());
+                self.queue(JobKind::ShowBuildFiles, JobPayload::Package(id.clone()));
+                s.build_files = Some((id, None));
+            }
             Action::Upgrade(id) => {
                 let jid = self.jid();
-                let _ = self.tx_jobs.send(Job {
+                self.push(Job {
                     id: jid,
                     kind: JobKind::Upgrade,
                     payload: JobPayload::Package(id),
@@ -130,9 +976,18 @@ impl Store {
                 });
             }
 
+            Action::Install(id) if id.source == Source::Repo && s.repo_upgrades > 0 => {
+                s.partial_upgrade_risk = Some(id);
+            }
+            Action::Install(id) if self.wants_terminal(CommandKind::Install, Some(&id)) => {
+                self.copy_for_terminal(&mut s, shell_command(CommandKind::Install, &[id]))
+            }
+            Action::Install(id) if id.source == Source::Aur && self.confirm_build_deps => {
+                self.queue(JobKind::BuildDeps, JobPayload::Package(id));
+            }
             Action::Install(id) => {
                 let jid = self.jid();
-                let _ = self.tx_jobs.send(Job {
+                self.push(Job {
                     id: jid,
                     kind: JobKind::Install,
                     payload: JobPayload::Package(id),
@@ -140,9 +995,64 @@ impl Store {
                     cancel: CancelToken::new(),
                 });
             }
+            Action::InstallChecked if self.wants_terminal(CommandKind::Install, None) => {
+                let ids = s.take_checked();
+                self.copy_for_terminal(&mut s, shell_command(CommandKind::Install, &ids))
+            }
+            Action::InstallChecked
+                if s.repo_upgrades > 0 && s.checked.iter().any(|id| id.source == Source::Repo) =>
+            {
+                // Same partial-upgrade hazard as a single install; keep the set for after
+                s.error = Some(format!(
+                    "{} upgrades pending; upgrade first, installing repo packages now would be a partial upgrade",
+                    s.repo_upgrades
+                ));
+            }
+            Action::InstallChecked => {
+                let ids = s.take_checked();
+                if !ids.is_empty() {
+                    self.queue(JobKind::InstallMany, JobPayload::Packages(ids));
+                }
+            }
+            Action::RemoveChecked => {
+                if !s.checked.is_empty() {
+                    s.batch_remove = Some(s.take_checked());
+                }
+            }
+            Action::ConfirmRemoveChecked => {
+                let ids = s.batch_remove.take().unwrap_or_default();
+                if self.wants_terminal(CommandKind::Remove, None) {
+                    self.copy_for_terminal(&mut s, shell_command(CommandKind::Remove, &ids));
+                } else if !ids.is_empty() {
+                    self.queue(JobKind::RemoveMany, JobPayload::Packages(ids));
+                }
+            }
+            Action::CancelRemoveChecked => {
+                // Back to the checked set, so the selection isn't lost
+                s.checked.extend(s.batch_remove.take().unwrap_or_default());
+            }
+            Action::Confirm(id, kind) => {
+                if kind == ConfirmKind::Remove {
+                    s.remove_preview = None;
+                    self.queue(JobKind::RemovePreview, JobPayload::Package(id.clone()));
+                }
+                s.pending_confirm = Some((id, kind));
+            }
+            Action::CancelConfirm => s.pending_confirm = None,
+            Action::RetryLocked => {
+                if let Some((kind, payload)) = s.db_locked.take() {
+                    self.queue(kind, payload);
+                }
+            }
+            Action::DismissLocked => s.db_locked = None,
+            Action::Remove(id) if self.wants_terminal(CommandKind::Remove, Some(&id)) => {
+                s.pending_confirm = None;
+                self.copy_for_terminal(&mut s, shell_command(CommandKind::Remove, &[id]))
+            }
             Action::Remove(id) => {
+                s.pending_confirm = None;
                 let jid = self.jid();
-                let _ = self.tx_jobs.send(Job {
+                self.push(Job {
                     id: jid,
                     kind: JobKind::Remove,
                     payload: JobPayload::Package(id),
@@ -150,107 +1060,351 @@ impl Store {
                     cancel: CancelToken::new(),
                 });
             }
+            Action::Progress(p) if self.prefetch.borrow().jobs.contains(&p.job_id) => {
+                if matches!(p.stage, Stage::Finished | Stage::Failed | Stage::Cancelled) {
+                    self.prefetch.borrow_mut().jobs.remove(&p.job_id);
+                }
+            }
             Action::Progress(p) => {
-                if let Some(mut l) = p.log {
-                    l.push('\n');
-                    s.progress_log.push_str(&l);
-                    if s.progress_log.len() > MAX_LOG {
-                        let cut = s.progress_log.len() - MAX_LOG;
-                        s.progress_log.drain(..cut);
+                s.authorizing = matches!(p.stage, Stage::Authorizing);
+                if matches!(p.stage, Stage::Queued) {
+                    if let Some(job) = self.in_flight.borrow().get(&p.job_id) {
+                        s.record_start(job);
+                    }
+                    let kind = self.in_flight.borrow().get(&p.job_id).map(|j| j.kind);
+                    s.running = kind
+                        .filter(|k| {
+                            matches!(
+                                k,
+                                JobKind::Install
+                                    | JobKind::InstallMany
+                                    | JobKind::Remove
+                                    | JobKind::RemoveMany
+                                    | JobKind::Upgrade
+                                    | JobKind::UpgradeAll
+                                    | JobKind::Downgrade
+                            )
+                        })
+                        .map(|k| (p.job_id, k));
+                    s.progress = Some((p.job_id, None, None));
+                } else if let Some((_, percent, bytes)) = s
+                    .progress
+                    .as_mut()
+                    // Backends report with job id 0, meaning the job being run
+                    .filter(|(j, ..)| p.job_id == 0 || p.job_id == *j)
+                {
+                    *percent = p.percent.or(*percent);
+                    *bytes = p.bytes.or(*bytes);
+                }
+                // A search cancelled because a newer one replaced it isn't worth a toast
+                let mut superseded = false;
+                if matches!(p.stage, Stage::Finished | Stage::Failed | Stage::Cancelled) {
+                    let job = self.in_flight.borrow_mut().remove(&p.job_id);
+                    superseded = job.as_ref().is_some_and(|j| {
+                        matches!(
+                            j.kind,
+                            JobKind::Search | JobKind::SearchInstalled | JobKind::SearchFile
+                        )
+                    }) && s.search_job.as_ref().is_none_or(|(j, _)| *j != p.job_id);
+                    s.record_end(p.job_id, p.stage.clone());
+                    if matches!(p.stage, Stage::Finished)
+                        && job.as_ref().is_some_and(|j| j.kind == JobKind::Refresh)
+                    {
+                        s.toast =
+                            Some(("Package databases synced".into(), std::time::Instant::now()));
+                    }
+                    if matches!(p.stage, Stage::Finished)
+                        && job
+                            .as_ref()
+                            .is_some_and(|j| j.kind == JobKind::ClearBuildCache)
+                    {
+                        s.toast =
+                            Some(("AUR build cache cleared".into(), std::time::Instant::now()));
+                    }
+                    if let Some((_, id)) = s.install_after_upgrade.take_if(|(j, _)| *j == p.job_id)
+                    {
+                        if matches!(p.stage, Stage::Finished) {
+                            self.queue(JobKind::Install, JobPayload::Package(id));
+                        } else {
+                            s.push_log(&format!(
+                                "{} not installed: the upgrade before it didn't finish",
+                                id.name
+                            ));
+                        }
+                    }
+                    let locked = p.log.as_deref() == Some(Error::DbLocked.to_string().as_str());
+                    if let Some(job) = job.filter(|_| locked) {
+                        s.db_locked = Some((job.kind, job.payload));
+                    }
+                    if s.running.is_some_and(|(j, _)| j == p.job_id) {
+                        s.running = None;
+                    }
+                    if s.progress.is_some_and(|(j, ..)| j == p.job_id) {
+                        s.progress = None;
                     }
                 }
-                if matches!(p.stage, Stage::Failed) && s.error.is_none() {
+                let auth_cancelled =
+                    p.log.as_deref() == Some(Error::AuthCancelled.to_string().as_str());
+                let keep = p.warning || self.verbosity > Verbosity::Quiet;
+                if let Some(l) = p.log.filter(|_| keep) {
+                    s.push_log(&l);
+                }
+                if let Some((done, _)) = p.bytes {
+                    let now = std::time::Instant::now();
+                    match s.rate_sample {
+                        Some((_, prev)) if done < prev => s.rate_sample = Some((now, done)),
+                        Some((at, prev)) => {
+                            let secs = now.duration_since(at).as_secs_f64();
+                            // Sample over at least a second so bursts don't flap the hint
+                            if secs >= 1.0 {
+                                s.download_rate = Some((done - prev) as f64 / secs);
+                                s.rate_sample = Some((now, done));
+                            }
+                        }
+                        None => s.rate_sample = Some((now, done)),
+                    }
+                }
+                if matches!(p.stage, Stage::Finished | Stage::Failed | Stage::Cancelled) {
+                    s.download_mirror = None;
+                    s.download_rate = None;
+                    s.rate_sample = None;
+                }
+                // A user cancel is a neutral outcome, not an error.
+                if matches!(p.stage, Stage::Cancelled) && !superseded {
+                    let note = if auth_cancelled {
+                        "Authentication cancelled"
+                    } else {
+                        "Operation cancelled"
+                    };
+                    s.toast = Some((note.into(), std::time::Instant::now()));
+                } else if matches!(p.stage, Stage::Failed) && s.error.is_none() {
                     s.error = Some("operation failed".into());
                 }
+                if matches!(p.stage, Stage::Failed) {
+                    self.fall_back_to_cache(&mut s, p.job_id);
+                }
+                if matches!(p.stage, Stage::Finished | Stage::Failed | Stage::Cancelled)
+                    && s.details_job.as_ref().is_some_and(|(j, _)| *j == p.job_id)
+                {
+                    s.details_job = None;
+                }
             }
             Action::Event(e) => match e {
-                Event::SearchResults { items, .. } => {
+                // A newer search superseded this one, or typing moved on since.
+                Event::SearchResults {
+                    query, generation, ..
+                } if s.search_job.as_ref().is_some_and(|(j, _)| *j != generation)
+                    || query != s.query.trim() => {}
+                Event::SearchResultsPartial {
+                    query,
+                    items,
+                    generation,
+                    ..
+                } => {
+                    let current = s.search_job.as_ref().is_some_and(|(j, _)| *j == generation);
+                    // A re-run of the shown query keeps its rows until the full results are in.
+                    let shown = s
+                        .last_search
+                        .as_ref()
+                        .is_some_and(|(q, i, _)| *q == query && *i == s.filter_installed);
+                    if current && query == s.query.trim() && !shown {
+                        let mut seen = match s.partial.take() {
+                            Some((j, seen)) if j == generation => seen,
+                            _ => Vec::new(),
+                        };
+                        seen.extend(items);
+                        s.in_upgrades_view = false;
+                        s.in_orphans_view = false;
+                        s.cached_at = None;
+                        s.show_search_results(seen.clone());
+                        s.partial = Some((generation, seen));
+                    }
+                }
+                Event::SearchResults {
+                    query,
+                    items,
+                    installed_only,
+                    ..
+                } => {
                     s.in_upgrades_view = false;
-                    let q = s.query.to_lowercase();
-                    let mut v = items
-                        .into_iter()
-                        .filter(|x| {
-                            if q.is_empty() {
-                                true
-                            } else {
-                                let name = x.id.name.to_lowercase();
-                                let desc = x.description.to_lowercase();
-                                name.contains(&q) || desc.contains(&q)
-                            }
-                        })
-                        // Existing filters
-                        .filter(|x| {
-                            (s.filter_repo && x.id.source == Source::Repo)
-                                || (s.filter_aur && x.id.source == Source::Aur)
-                        })
-                        .filter(|x| {
-                            if s.filter_installed {
-                                x.installed
-                            } else {
-                                true
-                            }
-                        })
-                        .collect::<Vec<_>>();
-                    // Sorting as before
-                    match s.sort {
-                        SortMode::NameAsc => v.sort_by(|a, b| a.id.name.cmp(&b.id.name)),
-                        SortMode::NameDesc => v.sort_by(|a, b| b.id.name.cmp(&a.id.name)),
-                        SortMode::Popularity => {
-                            v.sort_by(|a, b| b.popular.unwrap_or(0).cmp(&a.popular.unwrap_or(0)))
+                    s.in_orphans_view = false;
+                    s.partial = None;
+                    let same = |(q, i, _): &(String, bool, _)| *q == query && *i == installed_only;
+                    if let Some((_, _, old)) = s.last_search.take().filter(same) {
+                        let (changed, notes) = diff_results(&old, &items);
+                        if let Some(first) = notes.first() {
+                            let more = match notes.len() - 1 {
+                                0 => String::new(),
+                                n => format!(" (+{n} more)"),
+                            };
+                            let now = std::time::Instant::now();
+                            s.toast = Some((format!("{first}{more}"), now));
+                            s.changed = changed.into_iter().map(|id| (id, now)).collect();
+                        }
+                        for n in &notes {
+                            s.push_log(n);
                         }
                     }
-                    s.results = v;
-                    if let Some(sel) = &s.selected {
-                        if !s.results.iter().any(|r| r.id == *sel) {
-                            s.selected = None;
+                    s.last_search = Some((query.clone(), installed_only, items.clone()));
+                    s.cached_at = None;
+                    // The cache keeps full results, for browsing everything offline
+                    if let Some((path, cache)) = self.cache.as_ref().filter(|_| !installed_only) {
+                        let mut cache = cache.borrow_mut();
+                        cache.put_search(&query, &items);
+                        if let Err(e) = cache.save(path) {
+                            s.push_log(&format!("result cache: {e}"));
                         }
                     }
+                    s.show_search_results(items);
                 }
                 Event::Upgrades { items } => {
-                    s.in_upgrades_view = true;
-                    // Show upgrades in the same left pane, honoring filters/sort
+                    s.repo_upgrades = items.iter().filter(|x| x.id.source == Source::Repo).count();
+                    if let Some(n) = s.count_upgrades(items.len()).filter(|_| s.notify_upgrades) {
+                        crate::notification::upgrades_available(n);
+                    }
+                    // Background checks (`CheckUpgrades`) only update the count.
+                    if s.in_upgrades_view {
+                        // Show upgrades in the same left pane, honoring filters/sort
+                        let mut v = items
+                            .into_iter()
+                            .filter(|x| s.source_enabled(x.id.source))
+                            .filter(|x| {
+                                if s.filter_installed {
+                                    x.installed
+                                } else {
+                                    true
+                                }
+                            })
+                            .filter(|x| !s.filter_out_of_date || x.out_of_date.is_some())
+                            .collect::<Vec<_>>();
+                        s.sort_results(&mut v);
+                        s.upgrades_download = v
+                            .iter()
+                            .filter_map(|p| p.download_size)
+                            .reduce(|a, b| a + b);
+                        s.results = v.into();
+                        s.selected = None;
+                    }
+                }
+                Event::Orphans { items } if s.in_orphans_view => {
+                    if items.is_empty() {
+                        s.toast = Some(("No orphaned packages".into(), std::time::Instant::now()));
+                    }
                     let mut v = items
                         .into_iter()
-                        .filter(|x| {
-                            (s.filter_repo && x.id.source == Source::Repo)
-                                || (s.filter_aur && x.id.source == Source::Aur)
-                        })
-                        .filter(|x| {
-                            if s.filter_installed {
-                                x.installed
-                            } else {
-                                true
-                            }
-                        })
+                        .filter(|x| s.source_enabled(x.id.source))
+                        .collect::<Vec<_>>();
+                    s.sort_results(&mut v);
+                    s.results = v.into();
+                    s.selected = None;
+                }
+                // The user moved on before the listing came back
+                Event::Orphans { .. } => {}
+                Event::Details { item } => {
+                    if let Some((path, cache)) = &self.cache {
+                        let mut cache = cache.borrow_mut();
+                        cache.put_details(&item);
+                        if let Err(e) = cache.save(path) {
+                            s.push_log(&format!("result cache: {e}"));
+                        }
+                    }
+                    s.details = Some(*item);
+                }
+                Event::SyncDbMissing => s.sync_db_missing = true,
+                Event::RebuildsNeeded { items } => s.rebuilds = Some(items),
+                Event::UpgradePlan { plan } => s.upgrade_plan = Some(*plan),
+                Event::CacheCleanPlan { plan } => s.cache_clean_plan = Some(plan),
+                // Nothing extra to install, so nothing to confirm
+                Event::BuildDeps { id, deps } if deps.is_empty() => {
+                    self.queue(JobKind::Install, JobPayload::Package(id));
+                }
+                Event::BuildDeps { id, deps } => s.build_deps = Some((id, deps)),
+                Event::OrphanedBuildDeps { names } => s.orphaned_build_deps = names,
+                Event::FilesDbNeedsSync => s.files_db_needs_sync = true,
+                Event::FileSearchResults { items, .. } => {
+                    s.in_upgrades_view = false;
+                    s.in_orphans_view = false;
+                    // No text filter: the query is a path, not part of name or description
+                    let mut v = items
+                        .into_iter()
+                        .filter(|x| s.source_enabled(x.id.source))
+                        .filter(|x| !s.filter_installed || x.installed)
+                        .filter(|x| !s.filter_out_of_date || x.out_of_date.is_some())
                         .collect::<Vec<_>>();
-                    match s.sort {
-                        SortMode::NameAsc => v.sort_by(|a, b| a.id.name.cmp(&b.id.name)),
-                        SortMode::NameDesc => v.sort_by(|a, b| b.id.name.cmp(&a.id.name)),
-                        SortMode::Popularity => {
-                            v.sort_by(|a, b| b.popular.unwrap_or(0).cmp(&a.popular.unwrap_or(0)))
+                    s.sort_results(&mut v);
+                    s.results = v.into();
+                    let gone = |sel: &PackageId| !s.results.iter().any(|r| r.id == *sel);
+                    if s.selected.as_ref().is_some_and(gone) {
+                        s.selected = None;
+                    }
+                }
+                Event::JobFailed { kind, message, .. } => {
+                    s.error = Some(format!("{} failed: {message}", failure_label(kind)));
+                }
+                Event::HookSummary { hooks } => s.last_hooks = hooks,
+                Event::DownloadMirror { host } => s.download_mirror = Some(host),
+                Event::Files { id, files } => s.files = Some((id, files)),
+                Event::History { id, entries } => s.history = Some((id, entries)),
+                Event::Sibling { id, sibling } => s.sibling = Some((id, sibling.map(|b| *b))),
+                Event::BuildFiles { id, files } => {
+                    if s.build_files.as_ref().is_some_and(|(x, _)| *x == id) {
+                        s.build_files = Some((id, Some(files)));
+                    }
+                }
+                Event::CachedVersions { id, versions } => s.cached_versions = Some((id, versions)),
+                Event::WhoRequires { id, tree } => s.who_requires = Some((id, tree)),
+                Event::RemovePreview { id, preview } => s.remove_preview = Some((id, preview)),
+                Event::TransactionSummary { summary } => {
+                    for (name, version) in &summary.installed {
+                        s.push_log(&format!("installed {name} {version}"));
+                    }
+                    for (name, old, new) in &summary.upgraded {
+                        s.push_log(&format!("upgraded {name} {old} -> {new}"));
+                    }
+                    for (name, version) in &summary.removed {
+                        s.push_log(&format!("removed {name} {version}"));
+                    }
+                    s.toast = Some((summary_headline(&summary), std::time::Instant::now()));
+                }
+                Event::DetailsBatch { items } => {
+                    let mut p = self.prefetch.borrow_mut();
+                    if p.details.len() + items.len() > MAX_PREFETCHED {
+                        p.details.clear();
+                    }
+                    for d in items {
+                        // The selection may have been waiting on this batch
+                        if s.selected.as_ref() == Some(&d.summary.id)
+                            && s.details
+                                .as_ref()
+                                .is_none_or(|x| x.summary.id != d.summary.id)
+                        {
+                            s.details = Some(d.clone());
                         }
+                        p.details.insert(d.summary.id.clone(), d);
                     }
-                    s.results = v;
-                    s.selected = None;
                 }
-                Event::Details { .. } => { /* not shown in v1 */ }
                 Event::SystemChanged => {
+                    self.prefetch.borrow_mut().details.clear();
                     // Decide what to refresh based on current UI mode.
                     if s.in_upgrades_view {
                         let id = self.jid();
-                        let _ = self.tx_jobs.send(Job {
+                        self.push(Job {
                             id,
                             kind: JobKind::Upgrades,
                             payload: JobPayload::None,
                             created_at: std::time::SystemTime::now(),
                             cancel: CancelToken::new(),
                         });
+                    } else if s.in_orphans_view {
+                        self.queue(JobKind::Orphans, JobPayload::None);
                     } else if !s.query.trim().is_empty() {
+                        self.cancel_search(&s);
                         let id = self.jid();
                         let q = s.query.clone();
-                        let _ = self.tx_jobs.send(Job {
+                        s.search_job = Some((id, q.trim().to_string()));
+                        self.push(Job {
                             id,
-                            kind: JobKind::Search,
+                            kind: search_kind(q.trim(), s.filter_installed),
                             payload: JobPayload::Query(q),
                             created_at: std::time::SystemTime::now(),
                             cancel: CancelToken::new(),
@@ -259,14 +1413,460 @@ impl Store {
                 }
             },
             Action::ClearError => s.error = None,
-            Action::Select(id) => s.selected = Some(id),
-            Action::ClearSelection => s.selected = None,
+            Action::Select(id) => self.select(&mut s, id),
+            Action::SwitchVariant => {
+                if let Some((_, Some(sibling))) = s.sibling.clone() {
+                    if !s.results.iter().any(|p| p.id == sibling.id) {
+                        // Next to the package it stands in for
+                        let mut v = s.results.to_vec();
+                        let at = v
+                            .iter()
+                            .position(|p| s.selected.as_ref() == Some(&p.id))
+                            .map_or(v.len(), |i| i + 1);
+                        v.insert(at, sibling.clone());
+                        s.results = v.into();
+                    }
+                    self.select(&mut s, sibling.id);
+                }
+            }
+            Action::ClearSelection => {
+                s.selected = None;
+                s.details = None;
+                s.screen = Screen::List;
+            }
+            Action::Back => s.screen = Screen::List,
             Action::ToggleFilterRepo => s.filter_repo = !s.filter_repo,
             Action::ToggleFilterAur => s.filter_aur = !s.filter_aur,
+            Action::ToggleFilterFlatpak => s.filter_flatpak = !s.filter_flatpak,
             Action::ToggleFilterInstalled => s.filter_installed = !s.filter_installed,
+            Action::ToggleFilterOutOfDate => s.filter_out_of_date = !s.filter_out_of_date,
+            Action::ToggleSpecialVariants => s.special_variants = !s.special_variants,
+            Action::ToggleShadowed => {
+                let mut all = s.results.to_vec();
+                if !s.show_shadowed {
+                    all.append(&mut s.shadowed);
+                }
+                s.show_shadowed = !s.show_shadowed;
+                s.sort_results(&mut all);
+                s.results = s.fold_shadowed(all).into();
+            }
             Action::SetSort(m) => s.sort = m,
             Action::ToggleLog => s.log_expanded = !s.log_expanded,
+            Action::ToggleActivity => s.show_activity = !s.show_activity,
+            Action::DismissHooks => s.last_hooks.clear(),
+            Action::RefreshDetails(id) => {
+                let jid = self.jid();
+                s.details_job = Some((jid, id.clone()));
+                self.push(Job {
+                    id: jid,
+                    kind: JobKind::Details,
+                    payload: JobPayload::Details { id, force: true },
+                    created_at: std::time::SystemTime::now(),
+                    cancel: CancelToken::new(),
+                });
+            }
+            Action::ShowFiles(id) => {
+                if s.files.as_ref().is_none_or(|(f, _)| *f != id) {
+                    self.queue(JobKind::Files, JobPayload::Package(id));
+                }
+                s.show_files = !s.show_files;
+            }
+            Action::ShowHistory(id) => {
+                // Fetched once per selection; the backend refreshes its clone on each fetch
+                if s.history.as_ref().is_none_or(|(h, _)| *h != id) {
+                    self.queue(JobKind::History, JobPayload::Package(id));
+                }
+                s.show_history = !s.show_history;
+            }
+            Action::ReviewBuildFiles(id) => {
+                self.queue(JobKind::ShowBuildFiles, JobPayload::Package(id.clone()));
+                s.build_files = Some((id, None));
+            }
+            Action::CloseBuildFiles => s.build_files = None,
+            Action::ShowWhoRequires(id) => {
+                // Listed afresh each time, as installs and removes change it
+                if !s.show_who_requires {
+                    s.who_requires = None;
+                    self.queue(JobKind::WhoRequires, JobPayload::Package(id));
+                }
+                s.show_who_requires = !s.show_who_requires;
+            }
+            Action::ShowCachedVersions(id) => {
+                // Listed afresh each time, as the cache changes with every transaction
+                if !s.show_cached_versions {
+                    s.cached_versions = None;
+                    self.queue(JobKind::CachedVersions, JobPayload::Package(id));
+                }
+                s.show_cached_versions = !s.show_cached_versions;
+            }
+            Action::Downgrade(id, version) => {
+                s.show_cached_versions = false;
+                self.queue(JobKind::Downgrade, JobPayload::Version { id, version });
+            }
+            Action::Launch(entry) => match crate::launch::launch(&entry) {
+                Ok(()) => s.toast = Some(("Launching…".into(), std::time::Instant::now())),
+                Err(e) => s.error = Some(format!("launch failed: {e}")),
+            },
+            Action::CopyName(name) => match crate::clipboard::copy(&name) {
+                Ok(()) => s.toast = Some((format!("Copied {name}"), std::time::Instant::now())),
+                Err(e) => s.error = Some(format!("clipboard unavailable: {e}")),
+            },
+            Action::OpenUrl(url) => {
+                if let Err(e) = crate::launch::open_url(&url) {
+                    s.push_log(&format!("can't open {url}: {e}"));
+                }
+            }
+            Action::CheckRebuilds => self.queue(JobKind::CheckRebuilds, JobPayload::None),
+            Action::RebuildAll => {
+                // AUR "upgrade" rebuilds from the latest PKGBUILD
+                for p in s.rebuilds.take().unwrap_or_default() {
+                    self.queue(JobKind::Upgrade, JobPayload::Package(p.id));
+                }
+            }
+            Action::DismissRebuilds => s.rebuilds = None,
+            Action::ToggleSelectionMode => {
+                s.selection_mode = !s.selection_mode;
+                if !s.selection_mode {
+                    s.checked.clear();
+                }
+            }
+            Action::ToggleChecked(id) => {
+                if !s.checked.remove(&id) {
+                    s.checked.insert(id);
+                }
+            }
+            Action::ToggleSkipOnce(id) => {
+                if let Some(i) = s.skip_once.iter().position(|x| *x == id) {
+                    s.skip_once.remove(i);
+                } else {
+                    s.skip_once.push(id);
+                }
+            }
+            Action::ToggleIgnore(name) => {
+                if let Some(i) = s.ignore.iter().position(|x| *x == name) {
+                    s.ignore.remove(i);
+                } else {
+                    s.ignore.push(name);
+                    s.ignore.sort();
+                }
+            }
+            Action::RefreshFilesDb => {
+                s.files_db_needs_sync = false;
+                self.queue(JobKind::RefreshFiles, JobPayload::None);
+                let q = s.query.trim();
+                if search_kind(q, false) == JobKind::SearchFile {
+                    self.queue(JobKind::SearchFile, JobPayload::Query(q.to_string()));
+                }
+            }
+            Action::CheckUpgrades => {
+                s.upgrade_check_pending = true;
+                self.queue(JobKind::Upgrades, JobPayload::None);
+            }
+            Action::ConfirmInstall(id) => {
+                s.partial_upgrade_risk = None;
+                if self.wants_terminal(CommandKind::Install, Some(&id)) {
+                    self.copy_for_terminal(&mut s, shell_command(CommandKind::Install, &[id]));
+                } else {
+                    self.queue(JobKind::Install, JobPayload::Package(id));
+                }
+            }
+            Action::UpgradeThenInstall(id) => {
+                s.partial_upgrade_risk = None;
+                let jid = self.jid();
+                self.push(Job {
+                    id: jid,
+                    kind: JobKind::UpgradeAll,
+                    payload: JobPayload::Skip(s.upgrade_skips()),
+                    created_at: std::time::SystemTime::now(),
+                    cancel: CancelToken::new(),
+                });
+                s.install_after_upgrade = Some((jid, id));
+                self.queue(JobKind::Upgrades, JobPayload::None);
+            }
+            Action::DismissPartialUpgrade => s.partial_upgrade_risk = None,
+            Action::Prioritize(jid) => {
+                self.jobs.prioritize(jid);
+            }
+            Action::Deprioritize(jid) => {
+                self.jobs.deprioritize(jid);
+            }
+            Action::Cancel(jid) => {
+                if let Some(job) = self.in_flight.borrow().get(&jid) {
+                    job.cancel.cancel();
+                }
+            }
+            Action::CancelQueued(jid) => {
+                self.in_flight.borrow_mut().remove(&jid);
+                if self.jobs.remove(jid).is_some() {
+                    s.toast = Some(("Removed from queue".into(), std::time::Instant::now()));
+                }
+            }
+            Action::CopyCommand(kind, ids) => {
+                let cmd = shell_command(kind, &ids);
+                match crate::clipboard::copy(&cmd) {
+                    Ok(()) => s.push_log(&format!("copied to clipboard:\n{cmd}")),
+                    Err(e) => s.error = Some(format!("clipboard unavailable: {e}")),
+                }
+            }
+            Action::RunInTerminal(kind, ids) => {
+                let cmd = shell_command(kind, &ids);
+                match crate::launch::run_in_terminal(&cmd) {
+                    Ok(()) => {
+                        s.push_log(&format!("running in a terminal:\n{cmd}"));
+                        s.toast = Some(("Opened in a terminal".into(), std::time::Instant::now()));
+                    }
+                    Err(e) => s.error = Some(format!("can't open a terminal: {e}")),
+                }
+            }
+        }
+        // Every dispatch may have queued or reordered jobs, and the executor starting one
+        // is announced by a Progress, so this keeps the mirror current.
+        s.pending = self.jobs.pending();
+        s.pending.retain(|j| j.kind != JobKind::PrefetchDetails);
+        // `in_flight` gains a job on push and loses it on its final Progress
+        s.busy = self.in_flight.borrow().values().map(|j| j.kind).collect();
+        s.pending_jobs = s.busy.len();
+        if let Some(path) = self.prefs.as_ref().filter(|_| Prefs::of(&s) != prefs)
+            && let Err(e) = Prefs::of(&s).save(path)
+        {
+            s.push_log(&format!("preferences: {e}"));
         }
         self.state.set(s);
     }
 }
+
+/// One line for a finished transaction, e.g. "Installed firefox 120.0-1 (+3 dependencies,
+/// 180.0 MiB)"; the full list goes to the log.
+fn summary_headline(t: &TransactionSummary) -> String {
+    let size = if t.size_delta < 0 {
+        format!("{} freed", crate::human_size(t.size_delta.unsigned_abs()))
+    } else {
+        crate::human_size(t.size_delta as u64)
+    };
+    let others = |n: usize, one: &str, many: &str| match n {
+        0 => format!(" ({size})"),
+        1 => format!(" (+1 {one}, {size})"),
+        n => format!(" (+{n} {many}, {size})"),
+    };
+    let deps = |n: usize| others(n, "dependency", "dependencies");
+    let target = t.target.as_deref();
+    if let Some((name, v)) = t.installed.iter().find(|(n, _)| Some(n.as_str()) == target) {
+        return format!("Installed {name} {v}{}", deps(t.installed.len() - 1));
+    }
+    if let Some((name, _, v)) = t.upgraded.iter().find(|(n, ..)| Some(n.as_str()) == target) {
+        let new = others(t.installed.len(), "new package", "new packages");
+        return format!("Upgraded {name} to {v}{new}");
+    }
+    if let Some((name, v)) = t.removed.iter().find(|(n, _)| Some(n.as_str()) == target) {
+        return format!("Removed {name} {v}{}", deps(t.removed.len() - 1));
+    }
+    match (t.upgraded.len(), t.installed.len(), t.removed.len()) {
+        (0, 0, 0) => "Nothing changed".to_string(),
+        (u, i, r) => format!("Upgraded {u}, installed {i}, removed {r} ({size})"),
+    }
+}
+
+/// What a failed job was doing, for the error banner.
+fn failure_label(kind: JobKind) -> String {
+    match kind {
+        JobKind::Refresh => "Database sync".into(),
+        JobKind::RefreshFiles => "Files database sync".into(),
+        JobKind::Search | JobKind::SearchInstalled | JobKind::SearchFile => "Search".into(),
+        JobKind::Install | JobKind::InstallMany => "Install".into(),
+        JobKind::Remove | JobKind::RemoveMany => "Remove".into(),
+        JobKind::Upgrade | JobKind::UpgradeAll => "Upgrade".into(),
+        JobKind::Upgrades => "Upgrade check".into(),
+        other => format!("{other:?}"),
+    }
+}
+
+/// Queries that look like a path ("/usr/bin/foo") ask which packages provide it. With the
+/// installed filter on, only the local db is searched, which is instant and works offline.
+fn search_kind(q: &str, installed_only: bool) -> JobKind {
+    if q.starts_with('/') {
+        JobKind::SearchFile
+    } else if installed_only {
+        JobKind::SearchInstalled
+    } else {
+        JobKind::Search
+    }
+}
+
+/// How strongly to highlight `id`'s row given `AppState::changed`, from 1.0 when it just
+/// changed down to 0.0.
+pub fn change_highlight(changed: &[(PackageId, std::time::Instant)], id: &PackageId) -> f32 {
+    changed
+        .iter()
+        .find(|(c, _)| c == id)
+        .map(|(_, at)| 1.0 - at.elapsed().as_secs_f32() / CHANGE_HIGHLIGHT.as_secs_f32())
+        .unwrap_or(0.0)
+        .max(0.0)
+}
+
+/// What changed between two result sets of the same query: the ids of rows to highlight and
+/// a note per change.
+fn diff_results(old: &[PackageSummary], new: &[PackageSummary]) -> (Vec<PackageId>, Vec<String>) {
+    let mut changed = Vec::new();
+    let mut notes = Vec::new();
+    for n in new {
+        let Some(o) = old.iter().find(|o| o.id == n.id) else {
+            continue;
+        };
+        let note = if n.installed && !o.installed {
+            format!("{} is now installed", n.id.name)
+        } else if !n.installed && o.installed {
+            format!("{} was removed", n.id.name)
+        } else if n.version != o.version && !n.version.is_empty() {
+            format!("{} is now {}", n.id.name, n.version)
+        } else {
+            continue;
+        };
+        changed.push(n.id.clone());
+        notes.push(note);
+    }
+    for o in old.iter().filter(|o| !new.iter().any(|n| n.id == o.id)) {
+        notes.push(format!("{} is no longer listed", o.id.name));
+    }
+    (changed, notes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_log_trims_multibyte_lines_on_line_boundaries() {
+        let mut s = AppState::default();
+        // Multibyte characters on both sides of wherever the cut lands
+        for i in 0..20_000 {
+            s.push_log(&format!("ünïcødé → line {i} ✓"));
+        }
+        assert!(s.progress_log.len() <= MAX_LOG);
+        assert!(s.progress_log.ends_with('\n'));
+        for line in s.progress_log.lines() {
+            assert!(
+                line.starts_with("ünïcødé → line ") && line.ends_with('✓'),
+                "partial line kept: {line:?}"
+            );
+        }
+        assert!(s.progress_log.contains("line 19999 ✓"));
+    }
+
+    #[test]
+    fn large_upgrade_set_is_listed_whole_and_sorted() {
+        let store = Store::new(JobQueue::new());
+        store.state.update(|s| {
+            s.in_upgrades_view = true;
+            s.sort = SortMode::NameAsc;
+        });
+        // Reverse name order, alternating sources; only repo packages report a size
+        let items: Vec<PackageSummary> = (0..1200)
+            .rev()
+            .map(|i| PackageSummary {
+                id: PackageId {
+                    name: format!("pkg{i:05}"),
+                    source: if i % 2 == 0 {
+                        Source::Repo
+                    } else {
+                        Source::Aur
+                    },
+                },
+                version: "2.0-1".into(),
+                description: String::new(),
+                installed: true,
+                popular: None,
+                last_updated: None,
+                variant: AurVariant::Regular,
+                download_size: (i % 2 == 0).then_some(1024),
+                installed_version: Some("1.0-1".into()),
+                out_of_date: None,
+                group_members: None,
+                repo: None,
+                provides_match: None,
+            })
+            .collect();
+        store.dispatch(Action::Event(Event::Upgrades { items }));
+
+        let s = store.state.get();
+        assert_eq!(s.available_upgrade_count, Some(1200));
+        assert_eq!(s.repo_upgrades, 600);
+        assert_eq!(s.results.len(), 1200);
+        assert_eq!(s.upgrades_download, Some(600 * 1024));
+        assert_eq!(s.results[0].id.name, "pkg00000");
+        assert!(s.results.windows(2).all(|w| w[0].id.name < w[1].id.name));
+    }
+
+    #[test]
+    fn only_background_checks_that_find_more_upgrades_notify() {
+        // The check at launch sets the baseline
+        let mut s = AppState {
+            upgrade_check_pending: true,
+            ..AppState::default()
+        };
+        assert_eq!(s.count_upgrades(5), None);
+        // Listed from the Upgrades button
+        assert_eq!(s.count_upgrades(7), None);
+        s.upgrade_check_pending = true;
+        assert_eq!(s.count_upgrades(7), None);
+        s.upgrade_check_pending = true;
+        assert_eq!(s.count_upgrades(9), Some(9));
+        s.upgrade_check_pending = true;
+        assert_eq!(s.count_upgrades(3), None);
+        assert_eq!(s.available_upgrade_count, Some(3));
+    }
+
+    #[test]
+    fn ignored_names_stay_repo_packages_whatever_is_listed() {
+        let listed = |name: &str, source| PackageSummary {
+            id: PackageId {
+                name: name.into(),
+                source,
+            },
+            version: "1.0-1".into(),
+            description: String::new(),
+            installed: true,
+            popular: None,
+            last_updated: None,
+            variant: AurVariant::Regular,
+            download_size: None,
+            installed_version: None,
+            out_of_date: None,
+            group_members: None,
+            repo: None,
+            provides_match: None,
+        };
+        let s = AppState {
+            ignore: vec!["linux".into(), "mesa".into()],
+            results: vec![
+                listed("linux", Source::Aur),
+                listed("mesa", Source::Flatpak),
+            ]
+            .into(),
+            ..AppState::default()
+        };
+        let skips = s.upgrade_skips();
+        assert_eq!(skips.len(), 2);
+        assert!(skips.iter().all(|id| id.source == Source::Repo));
+    }
+
+    #[test]
+    fn orphaned_build_deps_go_in_one_removal() {
+        let store = Store::new(JobQueue::new());
+        store.state.update(|s| {
+            s.orphaned_build_deps = vec!["cmake".into(), "ninja".into(), "meson".into()];
+        });
+        store.dispatch(Action::RemoveOrphanedBuildDeps);
+
+        assert!(store.state.get().orphaned_build_deps.is_empty());
+        let jobs: Vec<Job> = store.in_flight.borrow().values().cloned().collect();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].kind, JobKind::RemoveMany);
+        match &jobs[0].payload {
+            JobPayload::Packages(ids) => {
+                let names: Vec<&str> = ids.iter().map(|id| id.name.as_str()).collect();
+                assert_eq!(names, ["cmake", "ninja", "meson"]);
+            }
+            other => panic!("unexpected payload {other:?}"),
+        }
+    }
+}
@@ -1,12 +1,18 @@
 use domain::*;
 use serde::Deserialize;
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
     fs,
     io::Write,
-    path::PathBuf,
-    process::Command,
-    time::{SystemTime, UNIX_EPOCH},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{DirBuilderExt, PermissionsExt},
+    },
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 #[derive(Deserialize)]
@@ -14,7 +20,7 @@ struct AurResponse<T> {
     results: Vec<T>,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 struct AurPkg {
     #[serde(rename = "Name")]
     name: String,
@@ -28,32 +34,1149 @@ struct AurPkg {
     maintainer: Option<String>,
     #[serde(rename = "LastModified")]
     last_modified: Option<u64>,
+    #[serde(rename = "OutOfDate")]
+    out_of_date: Option<u64>,
+    // Only present in info responses
+    #[serde(rename = "Conflicts", default)]
+    conflicts: Vec<String>,
+    #[serde(rename = "Replaces", default)]
+    replaces: Vec<String>,
+    #[serde(rename = "PackageBase")]
+    package_base: Option<String>,
+    #[serde(rename = "Depends", default)]
+    depends: Vec<String>,
+    #[serde(rename = "MakeDepends", default)]
+    make_depends: Vec<String>,
+    #[serde(rename = "CheckDepends", default)]
+    check_depends: Vec<String>,
+    /// "name: what it adds"
+    #[serde(rename = "OptDepends", default)]
+    opt_depends: Vec<String>,
+    #[serde(rename = "URL")]
+    url: Option<String>,
+    #[serde(rename = "Provides", default)]
+    provides: Vec<String>,
+}
+
+/// Where AUR git repos are fetched from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CloneProtocol {
+    #[default]
+    Https,
+    /// For maintainers with a key registered on the AUR; falls back to https without one.
+    Ssh,
+}
+
+impl CloneProtocol {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "https" => Some(Self::Https),
+            "ssh" => Some(Self::Ssh),
+            _ => None,
+        }
+    }
+
+    fn url(self, base: &str) -> String {
+        match self {
+            Self::Https => format!("https://aur.archlinux.org/{base}.git"),
+            Self::Ssh => format!("ssh://aur@aur.archlinux.org/{base}.git"),
+        }
+    }
+}
+
+/// Who builds AUR packages: the built-in clone-and-makepkg flow, or an AUR helper the
+/// user already has. `Auto` takes the first of paru and yay found on `PATH`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HelperChoice {
+    #[default]
+    Builtin,
+    Auto,
+    Paru,
+    Yay,
+}
+
+impl HelperChoice {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "builtin" | "none" => Some(Self::Builtin),
+            "auto" => Some(Self::Auto),
+            "paru" => Some(Self::Paru),
+            "yay" => Some(Self::Yay),
+            _ => None,
+        }
+    }
+
+    /// The helper to run, if the choice names one that is installed.
+    fn resolve(self) -> Option<&'static str> {
+        let candidates: &[&'static str] = match self {
+            Self::Builtin => &[],
+            Self::Auto => &["paru", "yay"],
+            Self::Paru => &["paru"],
+            Self::Yay => &["yay"],
+        };
+        candidates.iter().copied().find(|c| on_path(c))
+    }
+}
+
+fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH").is_some_and(|paths| {
+        std::env::split_paths(&paths).any(|dir| {
+            fs::metadata(dir.join(program))
+                .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        })
+    })
+}
+
+/// How `install` gets a package's build files. The default is a shallow https clone, kept
+/// in the cache dir and updated on later installs.
+#[derive(Clone, Debug)]
+pub struct CloneConfig {
+    pub protocol: CloneProtocol,
+    /// Commits to fetch; `None` (or 0) fetches the full history.
+    pub depth: Option<u32>,
+    /// Keep one checkout per package in the cache dir and update it on later installs,
+    /// instead of cloning into a throwaway dir each time. Local changes to it are discarded.
+    pub persistent: bool,
+    /// Where persistent checkouts live: its `soredowe-aur-builds` subdirectory, so nothing
+    /// else in it is ever touched. `None` uses `aur-builds` in the cache dir.
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for CloneConfig {
+    fn default() -> Self {
+        Self {
+            protocol: CloneProtocol::Https,
+            depth: Some(1),
+            persistent: true,
+            dir: None,
+        }
+    }
+}
+
+/// How the backend reaches the AUR RPC interface. The defaults suit aur.archlinux.org;
+/// point `base_url` at a mirror or a local instance instead.
+#[derive(Clone, Debug)]
+pub struct RpcConfig {
+    /// Endpoint up to the query string, e.g. `https://aur.archlinux.org/rpc/`.
+    pub base_url: String,
+    pub connect_timeout: Duration,
+    /// Limit on waiting for the response and again on reading its body.
+    pub read_timeout: Duration,
+    /// Sent as `User-Agent`; the AUR asks clients to identify themselves.
+    pub user_agent: String,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        Self {
+            base_url: "https://aur.archlinux.org/rpc/".into(),
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(30),
+            user_agent: "soredowe".into(),
+        }
+    }
 }
 
-pub struct AurBackend;
+impl RpcConfig {
+    /// The site `base_url` belongs to, e.g. `https://aur.archlinux.org/`, for the pages
+    /// served next to the RPC interface.
+    fn site(&self) -> String {
+        let base = self.base_url.trim_end_matches('/');
+        format!("{}/", base.strip_suffix("/rpc").unwrap_or(base))
+    }
+
+    fn agent(&self) -> ureq::Agent {
+        ureq::Agent::config_builder()
+            .timeout_connect(Some(self.connect_timeout))
+            .timeout_recv_response(Some(self.read_timeout))
+            .timeout_recv_body(Some(self.read_timeout))
+            .user_agent(&self.user_agent)
+            .build()
+            .into()
+    }
+}
+
+pub struct AurBackend {
+    events: Option<EventSink>,
+    verbosity: Verbosity,
+    privilege: Privilege,
+    clone: CloneConfig,
+    rpc: RpcConfig,
+    /// Built from `rpc`; shared so requests reuse connections.
+    agent: ureq::Agent,
+    rpc_cache: parking_lot::Mutex<RpcCache>,
+    /// `installed_set()` and when it was taken, shared by searches and details lookups.
+    installed: parking_lot::Mutex<Option<(Instant, Arc<HashSet<String>>)>>,
+    /// Checkouts whose build files were shown, by package name. The install that follows
+    /// builds exactly what was reviewed rather than fetching again.
+    reviewed: parking_lot::Mutex<HashMap<String, Checkout>>,
+    /// paru or yay, when installs are handed to a helper.
+    helper: Option<&'static str>,
+    /// Look upstream for new commits to VCS packages when listing upgrades.
+    vcs_check: bool,
+}
+
+/// A package's AUR repo on disk; the throwaway build dir, if any, goes when it's dropped.
+type Checkout = (Option<tempfile::TempDir>, PathBuf);
 impl AurBackend {
     pub fn new() -> Self {
-        Self
+        sweep_stale_build_dirs();
+        Self {
+            events: None,
+            verbosity: Verbosity::default(),
+            privilege: Privilege::default(),
+            clone: CloneConfig::default(),
+            rpc: RpcConfig::default(),
+            agent: RpcConfig::default().agent(),
+            rpc_cache: Default::default(),
+            installed: Default::default(),
+            reviewed: Default::default(),
+            helper: None,
+            vcs_check: false,
+        }
+    }
+
+    /// Installed package names, cached so type-ahead doesn't run `pacman -Qq` per keystroke.
+    /// The TTL covers changes nothing told us about (file watching off).
+    fn installed(&self) -> Arc<HashSet<String>> {
+        let mut cached = self.installed.lock();
+        match &*cached {
+            Some((at, set)) if at.elapsed() < INSTALLED_TTL => set.clone(),
+            _ => {
+                let set = Arc::new(installed_set());
+                *cached = Some((Instant::now(), set.clone()));
+                set
+            }
+        }
+    }
+
+    pub fn with_clone(mut self, mut clone: CloneConfig) -> Self {
+        clone.depth = clone.depth.filter(|d| *d > 0);
+        self.clone = clone;
+        self
+    }
+
+    /// Hand installs and upgrades to an AUR helper. A choice whose helper isn't installed
+    /// keeps the built-in flow.
+    pub fn with_helper(mut self, choice: HelperChoice) -> Self {
+        self.helper = choice.resolve();
+        self
+    }
+
+    /// Builds and installs `name` with `helper`, as the user; the helper escalates for
+    /// pacman itself, through the same mechanism as everything else here.
+    fn install_with_helper(
+        &self,
+        helper: &str,
+        name: &str,
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<()> {
+        let mut cmd = Command::new(helper);
+        cmd.args(["-S", "--aur", "--needed", "--noconfirm", "--removemake"]);
+        match self.privilege {
+            Privilege::Pkexec => {
+                cmd.args(["--sudo", "pkexec"]);
+            }
+            Privilege::Sudo => {
+                let askpass = std::env::var_os("SUDO_ASKPASS").is_some();
+                cmd.args(["--sudo", "sudo", "--sudoflags"])
+                    .arg(if askpass { "-A" } else { "-n" });
+            }
+            Privilege::Direct => {}
+        }
+        if helper == "paru" {
+            // Reviews happen in the GUI, if at all; paru would otherwise wait on a pager
+            cmd.arg("--skipreview");
+        } else {
+            cmd.args(["--answerdiff", "None", "--answerclean", "None"]);
+        }
+        cmd.arg(name);
+        let code = self.run_logged(cmd, sink, cancel, Stage::Building)?;
+        if code != 0 {
+            return Err(Error::Aur(format!("{helper} exit {code}")));
+        }
+        Ok(())
+    }
+
+    /// Also list installed VCS packages whose upstream has moved past the built commit.
+    /// Costs a `.SRCINFO` download and a `git ls-remote` per package on every check.
+    pub fn with_vcs_check(mut self, on: bool) -> Self {
+        self.vcs_check = on;
+        self
+    }
+
+    /// The commit the VCS source of `base` currently points at, if it can be found out.
+    /// An upstream that doesn't answer within `LS_REMOTE_TIMEOUT` is reported and skipped.
+    fn upstream_head(
+        &self,
+        base: &str,
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<Option<String>> {
+        let url = format!(
+            "{}cgit/aur.git/plain/.SRCINFO?h={}",
+            self.rpc.site(),
+            urlencoding::encode(base)
+        );
+        let Some(srcinfo) = self
+            .agent
+            .get(&url)
+            .call()
+            .ok()
+            .and_then(|mut r| r.body_mut().read_to_string().ok())
+        else {
+            return Ok(None);
+        };
+        let Some((repo, reference)) = vcs_source(&srcinfo) else {
+            return Ok(None);
+        };
+        check_cancel(cancel)?;
+
+        // Stopped by the job's cancel or by the timeout, whichever comes first
+        let stop = CancelToken::new();
+        let done = CancelToken::new();
+        let watch = {
+            let (stop, done, cancel) = (stop.clone(), done.clone(), cancel.clone());
+            std::thread::spawn(move || {
+                let start = Instant::now();
+                while !done.is_cancelled() {
+                    if cancel.is_cancelled() || start.elapsed() > LS_REMOTE_TIMEOUT {
+                        stop.cancel();
+                        return;
+                    }
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+            })
+        };
+        let mut cmd = Command::new("git");
+        cmd.args(["ls-remote", &repo, &reference])
+            .stdin(Stdio::null());
+        no_prompts(&mut cmd);
+        self.trace(&cmd, sink);
+        let head = Arc::new(parking_lot::Mutex::new(None));
+        let first = head.clone();
+        let on_out = move |l: String| {
+            let mut first = first.lock();
+            if first.is_none() {
+                *first = l.split_whitespace().next().map(str::to_string);
+            }
+        };
+        let run = run_piped(
+            cmd,
+            sink,
+            &Stage::Searching,
+            &stop,
+            on_out,
+            forward(sink, Stage::Searching, true),
+        );
+        done.cancel();
+        let _ = watch.join();
+        check_cancel(cancel)?;
+        if run?.1 {
+            sink.send(Progress {
+                job_id: 0,
+                stage: Stage::Searching,
+                percent: None,
+                bytes: None,
+                log: Some(format!(
+                    "aur: {repo} didn't answer; skipped checking {base}"
+                )),
+                warning: true,
+            })
+            .ok();
+            return Ok(None);
+        }
+        let head = head.lock().take();
+        Ok(head)
+    }
+
+    pub fn with_rpc(mut self, rpc: RpcConfig) -> Self {
+        self.agent = rpc.agent();
+        self.rpc = rpc;
+        self
+    }
+
+    /// One RPC v5 request; `query` is everything after `v=5&`. Connection failures and
+    /// timeouts are retried with backoff, announced on `sink`; HTTP errors are not.
+    fn rpc_call(
+        &self,
+        query: &str,
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<Vec<AurPkg>> {
+        if let Some(hit) = self.rpc_cache.lock().get(query) {
+            return Ok(hit);
+        }
+        let url = format!("{}?v=5&{query}", self.rpc.base_url);
+        let mut delay = RPC_BACKOFF;
+        let mut attempt = 1;
+        loop {
+            let res = self
+                .agent
+                .get(&url)
+                .call()
+                .and_then(|mut r| r.body_mut().read_json::<AurResponse<AurPkg>>());
+            match res {
+                Ok(resp) => {
+                    let mut cache = self.rpc_cache.lock();
+                    cache.put(query.to_string(), resp.results.clone());
+                    return Ok(resp.results);
+                }
+                Err(e) if transient(&e) && attempt < RPC_ATTEMPTS => {
+                    sink.send(Progress {
+                        job_id: 0,
+                        stage: Stage::Searching,
+                        percent: None,
+                        bytes: None,
+                        log: Some(format!(
+                            "AUR request failed ({e}); retrying in {:.1}s",
+                            delay.as_secs_f32()
+                        )),
+                        warning: true,
+                    })
+                    .ok();
+                    check_cancel(cancel)?;
+                    std::thread::sleep(delay);
+                    check_cancel(cancel)?;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err(e) => return Err(Error::Network(e.to_string())),
+            }
+        }
+    }
+
+    fn info_query(names: &[&str]) -> String {
+        let args: String = names
+            .iter()
+            .map(|n| format!("&arg[]={}", urlencoding::encode(n)))
+            .collect();
+        format!("type=info{args}")
+    }
+
+    fn info(&self, name: &str, sink: &ProgressSink, cancel: &CancelToken) -> Result<AurPkg> {
+        self.info_many(&[name], sink, cancel)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::Aur("not found".into()))
+    }
+
+    /// Looks up `names` in as few RPC requests as fit under `RPC_MAX_URI`; unknown names are
+    /// simply missing from the result.
+    fn info_many(
+        &self,
+        names: &[&str],
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<Vec<AurPkg>> {
+        let mut out = Vec::with_capacity(names.len());
+        // What every request's URL carries besides the names
+        let fixed = format!("{}?v=5&", self.rpc.base_url).len() + Self::info_query(&[]).len();
+        for chunk in uri_chunks(names, fixed) {
+            check_cancel(cancel)?;
+            out.extend(self.rpc_call(&Self::info_query(chunk), sink, cancel)?);
+        }
+        Ok(out)
+    }
+
+    fn depth_arg(&self) -> Option<String> {
+        self.clone.depth.map(|d| format!("--depth={d}"))
+    }
+
+    /// Runs the git command `git` builds for the repo URL of `base`. An ssh attempt that
+    /// fails (no key, unknown host) is retried over https.
+    fn git_remote(
+        &self,
+        base: &str,
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+        git: impl Fn(&str) -> Command,
+    ) -> Result<()> {
+        let mut protocols = vec![self.clone.protocol];
+        if self.clone.protocol != CloneProtocol::Https {
+            protocols.push(CloneProtocol::Https);
+        }
+        for (i, protocol) in protocols.into_iter().enumerate() {
+            if i > 0 {
+                sink.send(Progress {
+                    job_id: 0,
+                    stage: Stage::Resolving,
+                    percent: None,
+                    bytes: None,
+                    log: Some(format!(
+                        "aur: ssh fetch of {base} failed; retrying over https"
+                    )),
+                    warning: true,
+                })
+                .ok();
+            }
+            let mut cmd = git(&protocol.url(base));
+            no_prompts(&mut cmd);
+            if self.run_logged(cmd, sink, cancel, Stage::Downloading)? == 0 {
+                return Ok(());
+            }
+        }
+        Err(Error::Aur(format!("git fetch of {base} failed")))
+    }
+
+    /// Installs `deps` as dependencies so pacman can list them as orphans later. Other
+    /// failures are left for makepkg to report, but a dismissed password prompt stops the
+    /// install.
+    fn preinstall(&self, deps: &[String], sink: &ProgressSink, cancel: &CancelToken) -> Result<()> {
+        if deps.is_empty() {
+            return Ok(());
+        }
+        let mut cmd = self.privilege.command("pacman");
+        cmd.args(["-S", "--noconfirm", "--needed", "--asdeps"])
+            .args(deps);
+        match self.run_logged(cmd, sink, cancel, Stage::Installing) {
+            Ok(code) => match self.privilege.check("pacman -S", code) {
+                Err(Error::AuthCancelled) => Err(Error::AuthCancelled),
+                _ => Ok(()),
+            },
+            Err(Error::Cancelled) => Err(Error::Cancelled),
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Gets the AUR repo of `name`: the persistent checkout, updated, or a fresh clone in a
+    /// throwaway build dir.
+    fn fetch(&self, name: &str, sink: &ProgressSink, cancel: &CancelToken) -> Result<Checkout> {
+        if self.clone.persistent {
+            return Ok((None, self.checkout(name, sink, cancel)?));
+        }
+        let work = build_dir()?;
+        let dir = work.path().join(name);
+        self.git_remote(name, sink, cancel, |url| {
+            let mut cmd = Command::new("git");
+            cmd.arg("clone").args(self.depth_arg()).arg(url).arg(&dir);
+            cmd
+        })?;
+        Ok((Some(work), dir))
+    }
+
+    /// Where persistent checkouts are kept.
+    fn checkouts_root(&self) -> Result<PathBuf> {
+        self.clone
+            .dir
+            .as_ref()
+            .map(|d| d.join("soredowe-aur-builds"))
+            .or_else(|| cache_dir("aur-builds"))
+            .ok_or_else(|| Error::Internal("no cache directory".into()))
+    }
+
+    /// Brings the persistent checkout of `name` up to date, cloning it the first time. One
+    /// that can't be updated (interrupted clone, corrupt repo) is replaced by a fresh clone.
+    fn checkout(&self, name: &str, sink: &ProgressSink, cancel: &CancelToken) -> Result<PathBuf> {
+        if !valid_base(name) {
+            return Err(Error::Aur(format!("invalid package name: {name}")));
+        }
+        let root = self.checkouts_root()?;
+        // Private like `build_dir`, so nobody else can swap the PKGBUILD
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&root)
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        let dir = root.join(name);
+        let ours = dir.join(CHECKOUT_MARKER).is_file();
+        if ours && checkout_intact(&dir) {
+            self.update_checkout(name, &dir, sink, cancel)?;
+            return Ok(dir);
+        }
+        // Only what we cloned is ever reset or deleted
+        if dir.exists() && !ours {
+            return Err(Error::Aur(format!(
+                "{} wasn't cloned by soredowe; move it away to build {name} there",
+                dir.display()
+            )));
+        }
+        // git won't clone into what's left of a broken checkout
+        if dir.exists() {
+            sink.send(Progress {
+                job_id: 0,
+                stage: Stage::Downloading,
+                percent: None,
+                bytes: None,
+                log: Some(format!("checkout of {name} is broken; cloning it again")),
+                warning: true,
+            })
+            .ok();
+            fs::remove_dir_all(&dir).map_err(|e| Error::Internal(e.to_string()))?;
+        }
+        self.git_remote(name, sink, cancel, |url| {
+            let mut cmd = Command::new("git");
+            cmd.arg("clone").args(self.depth_arg()).arg(url).arg(&dir);
+            cmd
+        })?;
+        fs::write(dir.join(CHECKOUT_MARKER), b"").map_err(|e| Error::Internal(e.to_string()))?;
+        Ok(dir)
+    }
+
+    /// Fetches the latest commit into the checkout at `dir` and resets it to that.
+    fn update_checkout(
+        &self,
+        name: &str,
+        dir: &Path,
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<()> {
+        self.git_remote(name, sink, cancel, |url| {
+            let mut cmd = Command::new("git");
+            cmd.arg("-C")
+                .arg(dir)
+                .arg("fetch")
+                .args(self.depth_arg())
+                .arg(url);
+            cmd
+        })?;
+        // clean also drops the package built last time, so `find_built_pkg` can't pick it
+        let clean = ["clean", "-fdx", "-q", "-e", CHECKOUT_MARKER];
+        for args in [&["reset", "--hard", "FETCH_HEAD"][..], &clean[..]] {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(dir)
+                .args(args)
+                .status()
+                .map_err(|e| Error::Internal(e.to_string()))?;
+            if !status.success() {
+                return Err(Error::Aur(format!("updating checkout of {name} failed")));
+            }
+        }
+        Ok(())
+    }
+
+    /// How pacman gets root to install dependencies and built packages.
+    pub fn with_privilege(mut self, privilege: Privilege) -> Self {
+        self.privilege = privilege;
+        self
+    }
+
+    /// At `Verbosity::Debug`, build steps log their command line and pacman runs with
+    /// --debug. makepkg has no verbose mode of its own.
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Runs `cmd`, forwarding its output to `sink` line by line (stderr as warnings) so long
+    /// builds don't look frozen, and returns its exit code. Cancelling stops its whole
+    /// process group (see `run_piped`), so the compilers makepkg started stop too.
+    fn run_logged(
+        &self,
+        cmd: Command,
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+        stage: Stage,
+    ) -> Result<i32> {
+        self.trace(&cmd, sink);
+        if let Some(p) = Privilege::prompt_progress(&cmd) {
+            sink.send(p).ok();
+        }
+        let (status, cancelled) = run_piped(
+            cmd,
+            sink,
+            &stage,
+            cancel,
+            forward(sink, stage.clone(), false),
+            forward(sink, stage.clone(), true),
+        )?;
+        if cancelled {
+            return Err(Error::Cancelled);
+        }
+        Ok(status.code().unwrap_or(-1))
+    }
+
+    fn trace(&self, cmd: &Command, sink: &ProgressSink) {
+        if self.verbosity < Verbosity::Debug {
+            return;
+        }
+        sink.send(Progress {
+            job_id: 0,
+            stage: Stage::Building,
+            percent: None,
+            bytes: None,
+            log: Some(format!("$ {cmd:?}")),
+            warning: false,
+        })
+        .ok();
+    }
+
+    /// Report build dependencies left orphaned by an install on `events`.
+    pub fn with_events(mut self, events: EventSink) -> Self {
+        self.events = Some(events);
+        self
+    }
+}
+
+/// Tries per RPC request when the network, not the AUR, is at fault.
+const RPC_ATTEMPTS: u32 = 3;
+/// Pause before the first retry; doubled for each one after.
+const RPC_BACKOFF: Duration = Duration::from_millis(500);
+/// How long a VCS package's upstream gets to answer `git ls-remote`.
+const LS_REMOTE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Failures a retry may get past. HTTP status errors would only repeat.
+fn transient(e: &ureq::Error) -> bool {
+    matches!(
+        e,
+        ureq::Error::Io(_)
+            | ureq::Error::Timeout(_)
+            | ureq::Error::ConnectionFailed
+            | ureq::Error::HostNotFound
+    )
+}
+
+/// The longest request URI aurweb accepts, in bytes; longer info requests fail.
+const RPC_MAX_URI: usize = 4443;
+
+/// Splits `names` into runs whose `&arg[]=` parameters, URL-encoded, fit in what
+/// `RPC_MAX_URI` leaves after `fixed` bytes. A name too long for any request goes alone.
+fn uri_chunks<'a>(names: &'a [&'a str], fixed: usize) -> Vec<&'a [&'a str]> {
+    let budget = RPC_MAX_URI.saturating_sub(fixed);
+    let mut chunks = Vec::new();
+    let (mut start, mut used) = (0, 0);
+    for (i, name) in names.iter().enumerate() {
+        let len = "&arg[]=".len() + urlencoding::encode(name).len();
+        if i > start && used + len > budget {
+            chunks.push(&names[start..i]);
+            (start, used) = (i, 0);
+        }
+        used += len;
+    }
+    if start < names.len() {
+        chunks.push(&names[start..]);
+    }
+    chunks
+}
+
+fn to_details(p: AurPkg, installed: &HashSet<String>) -> PackageDetails {
+    let summary = PackageSummary {
+        id: PackageId {
+            name: p.name.clone(),
+            source: Source::Aur,
+        },
+        version: p.version,
+        description: p.description.unwrap_or_default(),
+        installed: installed.contains(&p.name),
+        popular: p.votes,
+        last_updated: ts(p.last_modified),
+        variant: AurVariant::classify(&p.name),
+        download_size: None,
+        installed_version: None,
+        out_of_date: ts(p.out_of_date),
+        group_members: None,
+        provides_match: None,
+        repo: None,
+    };
+    PackageDetails {
+        summary,
+        depends: dep_names(&p.depends),
+        opt_depends: dep_names(&p.opt_depends),
+        homepage: p.url.filter(|u| !u.is_empty()),
+        maintainer: p.maintainer,
+        size_install: None,
+        size_download: None,
+        architecture: None,
+        installed_conflicts: p
+            .conflicts
+            .iter()
+            .filter(|c| installed.contains(&strip_ver(c)))
+            .cloned()
+            .collect(),
+        conflicts: p.conflicts,
+        replaces: p.replaces,
+        provides: p.provides,
+        required_by: vec![],
+    }
+}
+
+/// `$XDG_CACHE_HOME/soredowe/<name>`: `aur-history` holds blobless mirrors of AUR repos,
+/// kept for their commit logs; `aur-builds` holds persistent checkouts; `aur-pkgbuilds`
+/// the PKGBUILD each package was last built from.
+fn cache_dir(name: &str) -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))?;
+    Some(base.join("soredowe").join(name))
+}
+
+/// Left in each persistent checkout we clone; nothing without it is reset or deleted.
+const CHECKOUT_MARKER: &str = ".soredowe-checkout";
+
+/// Whether `dir` is a checkout git can still work with. An interrupted clone or a damaged
+/// object store fails one of these; a network outage doesn't, so the checkout survives it.
+fn checkout_intact(dir: &Path) -> bool {
+    dir.join(".git").exists()
+        && [
+            &["rev-parse", "--verify", "-q", "HEAD"][..],
+            &["fsck", "--connectivity-only", "--no-progress"][..],
+        ]
+        .iter()
+        .all(|args| {
+            Command::new("git")
+                .arg("-C")
+                .arg(dir)
+                .args(*args)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .is_ok_and(|s| s.success())
+        })
+}
+
+/// Where the PKGBUILD `name` was last built from is kept, for diffing the next one against.
+fn built_pkgbuild(name: &str) -> Option<PathBuf> {
+    if !valid_base(name) {
+        return None;
+    }
+    Some(cache_dir("aur-pkgbuilds")?.join(name))
+}
+
+/// Keeps the PKGBUILD in `dir` as the one `name` was last built from. Best-effort: without
+/// it, the next review just has nothing to diff against.
+fn remember_pkgbuild(name: &str, dir: &Path) {
+    let Some(dest) = built_pkgbuild(name) else {
+        return;
+    };
+    let dir_ready = dest
+        .parent()
+        .is_some_and(|parent| fs::create_dir_all(parent).is_ok());
+    if dir_ready {
+        let _ = fs::copy(dir.join("PKGBUILD"), dest);
+    }
+}
+
+/// Unified diff from the PKGBUILD `name` was last built from to the one in `dir`; `None`
+/// if it was never built here or nothing changed.
+fn pkgbuild_diff(name: &str, dir: &Path) -> Option<String> {
+    let old = built_pkgbuild(name).filter(|p| p.is_file())?;
+    let out = Command::new("git")
+        .args(["diff", "--no-index", "--no-color", "--"])
+        .arg(&old)
+        .arg(dir.join("PKGBUILD"))
+        .output()
+        .ok()?;
+    // 1 is "files differ"; 0 is identical, anything else an error
+    if out.status.code() != Some(1) {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&out.stdout);
+    // git's header names the cache and checkout paths; the hunks are what matters
+    let hunks = &text[text.find("\n@@")? + 1..];
+    Some(format!("--- last built\n+++ to be built\n{hunks}"))
+}
+
+/// Package names and bases come from the network; they must not name a path outside the
+/// cache.
+fn valid_base(base: &str) -> bool {
+    !base.is_empty() && !base.starts_with('.') && !base.contains('/')
+}
+
+// ---------- parsing for `git log --format=%h%x09%at%x09%an%x09%s` ----------
+fn parse_git_log(out: &str) -> Vec<LogEntry> {
+    out.lines()
+        .filter_map(|l| {
+            let mut cols = l.splitn(4, '\t');
+            let hash = cols.next().filter(|h| !h.is_empty())?;
+            let at = cols.next().and_then(|t| t.parse().ok());
+            Some(LogEntry {
+                hash: hash.to_string(),
+                at: ts(at),
+                author: cols.next().unwrap_or("").to_string(),
+                subject: cols.next().unwrap_or("").to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Make/check depends that aren't also runtime depends, without version constraints.
+fn build_only<'a>(depends: &[String], build: impl IntoIterator<Item = &'a String>) -> Vec<String> {
+    let runtime: HashSet<String> = depends.iter().map(|d| strip_ver(d)).collect();
+    let mut out: Vec<String> = build
+        .into_iter()
+        .map(|d| strip_ver(d))
+        .filter(|d| !runtime.contains(d))
+        .collect();
+    out.sort();
+    out.dedup();
+    out
+}
+
+/// How long an RPC answer is reused. Long enough to spare the AUR type-ahead and
+/// back-and-forth browsing, short enough to pick up new uploads soon.
+const RPC_CACHE_TTL: Duration = Duration::from_secs(60);
+/// Answers kept; the least recently used one goes first.
+const RPC_CACHE_SIZE: usize = 64;
+
+/// Recent RPC answers by query string; the AUR asks clients not to repeat requests.
+#[derive(Default)]
+struct RpcCache {
+    /// (fetched, last used, results)
+    entries: HashMap<String, (Instant, Instant, Vec<AurPkg>)>,
+}
+
+impl RpcCache {
+    fn get(&mut self, query: &str) -> Option<Vec<AurPkg>> {
+        let (_, used, results) = self
+            .entries
+            .get_mut(query)
+            .filter(|(fetched, ..)| fetched.elapsed() < RPC_CACHE_TTL)?;
+        *used = Instant::now();
+        Some(results.clone())
+    }
+
+    fn put(&mut self, query: String, results: Vec<AurPkg>) {
+        if self.entries.len() >= RPC_CACHE_SIZE && !self.entries.contains_key(&query) {
+            let lru = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used, _))| *used)
+                .map(|(q, _)| q.clone());
+            if let Some(q) = lru {
+                self.entries.remove(&q);
+            }
+        }
+        let now = Instant::now();
+        self.entries.insert(query, (now, now, results));
+    }
+}
+
+/// How long `AurBackend::installed` trusts its cache without being invalidated.
+const INSTALLED_TTL: Duration = Duration::from_secs(60);
+
+const BUILD_DIR_PREFIX: &str = "soredowe-aur-";
+/// Build dirs older than this were leaked by a run that was killed (or aborted on panic,
+/// since release builds use panic = "abort"), not by a build still in progress.
+const STALE_BUILD_DIR_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Private build dir, removed on drop (so on every error and cancellation return).
+/// Mode 0700 keeps other users on a shared /tmp from swapping the PKGBUILD before the build.
+fn build_dir() -> Result<tempfile::TempDir> {
+    tempfile::Builder::new()
+        .prefix(BUILD_DIR_PREFIX)
+        .permissions(fs::Permissions::from_mode(0o700))
+        .tempdir()
+        .map_err(|e| Error::Internal(e.to_string()))
+}
+
+/// Removes build dirs whose TempDir never got dropped. Other users' dirs are skipped by
+/// the sticky bit on /tmp.
+fn sweep_stale_build_dirs() {
+    let Ok(rd) = fs::read_dir(std::env::temp_dir()) else {
+        return;
+    };
+    for e in rd.filter_map(|e| e.ok()) {
+        let ours = e
+            .file_name()
+            .to_string_lossy()
+            .starts_with(BUILD_DIR_PREFIX);
+        let stale = e
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.elapsed().ok())
+            .is_some_and(|age| age > STALE_BUILD_DIR_AGE);
+        if ours && stale {
+            let _ = fs::remove_dir_all(e.path());
+        }
+    }
+}
+
+/// Makes git fail rather than wait on a host key, passphrase or password prompt nobody
+/// sees.
+fn no_prompts(cmd: &mut Command) {
+    cmd.env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes")
+        .env("GIT_TERMINAL_PROMPT", "0");
+}
+
+fn check_cancel(cancel: &CancelToken) -> Result<()> {
+    if cancel.is_cancelled() {
+        Err(Error::Cancelled)
+    } else {
+        Ok(())
     }
 }
 
+/// The commit a VCS package was built from, as its pkgver records it: "1.2.r34.gabc1234"
+/// (git describe style) or "r120.abc1234". Without the `g`, a hash needs a letter in it,
+/// so a date pkgver like "20240101" isn't taken for one.
+fn vcs_commit(version: &str) -> Option<&str> {
+    let pkgver = version.rsplit_once('-').map_or(version, |(v, _)| v);
+    let last = pkgver.rsplit('.').next()?;
+    let (hash, described) = match last.strip_prefix('g') {
+        Some(h) => (h, true),
+        None => (last, false),
+    };
+    let hex = hash.len() >= 7 && hash.chars().all(|c| c.is_ascii_hexdigit());
+    (hex && (described || hash.chars().any(|c| c.is_ascii_alphabetic()))).then_some(hash)
+}
+
+/// The repository and ref of the first git source in a `.SRCINFO`. Sources pinned to a
+/// commit or tag never move, so they give `None`.
+fn vcs_source(srcinfo: &str) -> Option<(String, String)> {
+    let source = srcinfo
+        .lines()
+        .filter_map(|l| l.trim().strip_prefix("source = "))
+        .find_map(|s| {
+            s.split_once("::")
+                .map_or(s, |(_, u)| u)
+                .strip_prefix("git+")
+        })?;
+    let (url, fragment) = source.split_once('#').unwrap_or((source, ""));
+    let url = url.split_once('?').map_or(url, |(u, _)| u);
+    let reference = match fragment.split_once('=') {
+        None => "HEAD".to_string(),
+        Some(("branch", b)) => format!("refs/heads/{b}"),
+        Some(_) => return None,
+    };
+    Some((url.to_string(), reference))
+}
+
+/// Installed foreign packages (not in any sync db, i.e. AUR-built) with their versions.
+fn foreign_packages() -> Vec<(String, String)> {
+    let Ok(out) = Command::new("pacman").arg("-Qm").output() else {
+        return vec![];
+    };
+    String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .filter_map(|l| {
+            let (name, ver) = l.trim().split_once(' ')?;
+            Some((name.to_string(), ver.to_string()))
+        })
+        .collect()
+}
+
+/// Names from `checkrebuild` output, which lists "repo<TAB>pkgname" per broken package.
+fn parse_checkrebuild(out: &str) -> Vec<String> {
+    out.lines()
+        .filter_map(|l| l.split('\t').nth(1))
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty())
+        .collect()
+}
+
+/// Whether any ELF file of an installed package has a dependency ldd can't resolve.
+fn links_missing_library(name: &str, cancel: &CancelToken) -> Result<bool> {
+    let out = Command::new("pacman")
+        .args(["-Qlq", name])
+        .output()
+        .map_err(|e| Error::Internal(e.to_string()))?;
+    // Paths needn't be UTF-8, so keep them as bytes
+    for line in out.stdout.split(|b| *b == b'\n') {
+        check_cancel(cancel)?;
+        let path = std::path::Path::new(OsStr::from_bytes(line));
+        if line.is_empty() || line.ends_with(b"/") || !is_elf(path) {
+            continue;
+        }
+        let Ok(ldd) = Command::new("ldd").arg(path).output() else {
+            continue;
+        };
+        if String::from_utf8_lossy(&ldd.stdout).contains("=> not found") {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn is_elf(path: &std::path::Path) -> bool {
+    use std::io::Read;
+    let mut magic = [0u8; 4];
+    fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut magic))
+        .is_ok()
+        && magic == *b"\x7fELF"
+}
+
 fn ts(opt: Option<u64>) -> Option<SystemTime> {
     opt.map(|t| UNIX_EPOCH + std::time::Duration::from_secs(t))
 }
 
-fn parse_srcinfo_deps(srcinfo: &str) -> Vec<String> {
-    let mut out = Vec::new();
+/// (runtime depends, build-only depends)
+fn parse_srcinfo_deps(srcinfo: &str) -> (Vec<String>, Vec<String>) {
+    let mut depends = Vec::new();
+    let mut build = Vec::new();
     for line in srcinfo.lines() {
         let line = line.trim();
         if let Some(v) = line.strip_prefix("depends = ") {
-            out.push(strip_ver(v));
-        } else if let Some(v) = line.strip_prefix("makedepends = ") {
-            out.push(strip_ver(v));
+            depends.push(strip_ver(v));
+        } else if let Some(v) = line
+            .strip_prefix("makedepends = ")
+            .or_else(|| line.strip_prefix("checkdepends = "))
+        {
+            build.push(v.to_string());
         }
     }
-    out.sort();
-    out.dedup();
-    out
+    let build = build_only(&depends, &build);
+    depends.sort();
+    depends.dedup();
+    (depends, build)
+}
+
+/// A line handler for `run_piped` that sends each line to `sink`. A failed send means the
+/// UI is gone; the rest are dropped, while `run_piped` keeps draining the pipe.
+fn forward(sink: &ProgressSink, stage: Stage, warning: bool) -> impl FnMut(String) + Send {
+    let sink = sink.clone();
+    let mut gone = false;
+    move |l| {
+        if gone {
+            return;
+        }
+        gone = sink
+            .send(Progress {
+                job_id: 0,
+                stage: stage.clone(),
+                percent: None,
+                bytes: None,
+                log: Some(l),
+                warning,
+            })
+            .is_err();
+    }
+}
+
+/// Installed packages depending on `name`, from the local db the RPC can't see.
+fn required_by(name: &str) -> Vec<String> {
+    let Ok(out) = Command::new("pacman").args(["-Qi", name]).output() else {
+        return vec![];
+    };
+    String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .find_map(|l| l.strip_prefix("Required By     :"))
+        .map(|v| v.split_whitespace().filter(|n| *n != "None"))
+        .into_iter()
+        .flatten()
+        .map(str::to_string)
+        .collect()
+}
+
+/// `pacman -Qdtq`: dependencies nothing requires any more.
+fn orphans() -> HashSet<String> {
+    Command::new("pacman")
+        .arg("-Qdtq")
+        .output()
+        .map(|o| {
+            String::from_utf8_lossy(&o.stdout)
+                .lines()
+                .map(|l| l.trim().to_string())
+                .filter(|l| !l.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Bare package names from depends entries, sorted and deduplicated.
+fn dep_names(deps: &[String]) -> Vec<String> {
+    let mut names: Vec<String> = deps
+        .iter()
+        .map(|d| strip_ver(d.split(':').next().unwrap_or(d)))
+        .filter(|d| !d.is_empty())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
 }
 
 fn strip_ver(s: &str) -> String {
@@ -90,6 +1213,23 @@ fn installed_set() -> HashSet<String> {
 }
 
 impl PackageBackend for AurBackend {
+    fn invalidate_installed_cache(&self) {
+        *self.installed.lock() = None;
+        // The change may have been an AUR install or upgrade; ask afresh about it
+        self.rpc_cache.lock().entries.clear();
+    }
+
+    fn details_fresh(
+        &self,
+        id: &PackageId,
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<PackageDetails> {
+        let query = Self::info_query(&[&id.name]);
+        self.rpc_cache.lock().entries.remove(&query);
+        self.details(id, sink, cancel)
+    }
+
     fn refresh(&self, _sink: &ProgressSink, _cancel: &CancelToken) -> Result<()> {
         Ok(())
     }
@@ -98,7 +1238,7 @@ impl PackageBackend for AurBackend {
         &self,
         q: &str,
         sink: &ProgressSink,
-        _cancel: &CancelToken,
+        cancel: &CancelToken,
     ) -> Result<Vec<PackageSummary>> {
         let q = q.trim();
         if q.len() < 2 {
@@ -126,22 +1266,20 @@ impl PackageBackend for AurBackend {
 
         // Be explicit about name+description search to match user expectations
         // RPC v5 docs note 2+ chars and rate limiting; keep the guard above.
-        let url = format!(
-            "https://aur.archlinux.org/rpc/?v=5&type=search&by=name-desc&arg={}",
-            urlencoding::encode(q)
-        );
-        let mut resp = ureq::get(&url)
-            .call()
-            .map_err(|e| Error::Network(e.to_string()))?;
-        let resp: AurResponse<AurPkg> = resp
-            .body_mut()
-            .read_json()
-            .map_err(|e| Error::Network(e.to_string()))?;
+        // Matching is case-insensitive, so lowercasing lets "Foo" and "foo" share a cache entry
+        let results = self.rpc_call(
+            &format!(
+                "type=search&by=name-desc&arg={}",
+                urlencoding::encode(&q.to_lowercase())
+            ),
+            sink,
+            cancel,
+        )?;
+        check_cancel(cancel)?;
 
-        let installed = installed_set();
+        let installed = self.installed();
 
-        Ok(resp
-            .results
+        Ok(results
             .into_iter()
             .map(|p| PackageSummary {
                 id: PackageId {
@@ -153,58 +1291,147 @@ impl PackageBackend for AurBackend {
                 installed: installed.contains(&p.name),
                 popular: p.votes,
                 last_updated: ts(p.last_modified),
+                variant: AurVariant::classify(&p.name),
+                download_size: None,
+                installed_version: None,
+                out_of_date: None,
+                group_members: None,
+                provides_match: None,
+                repo: None,
             })
             .collect())
     }
 
-    fn details(
+    fn search_installed(
         &self,
-        id: &PackageId,
+        q: &str,
         _sink: &ProgressSink,
         _cancel: &CancelToken,
+    ) -> Result<Vec<PackageSummary>> {
+        // Built AUR packages are in the local db as foreign (-m) ones, so no RPC needed
+        let out = Command::new("pacman")
+            .args(["-Qms", "--color", "never", q])
+            .output()
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        let mut items: Vec<PackageSummary> = Vec::new();
+        for line in String::from_utf8_lossy(&out.stdout).lines() {
+            if let Some(head) = line.strip_prefix("local/") {
+                let mut cols = head.split_whitespace();
+                let (Some(name), Some(version)) = (cols.next(), cols.next()) else {
+                    continue;
+                };
+                items.push(PackageSummary {
+                    id: PackageId {
+                        name: name.to_string(),
+                        source: Source::Aur,
+                    },
+                    version: version.to_string(),
+                    description: String::new(),
+                    installed: true,
+                    popular: None,
+                    last_updated: None,
+                    variant: AurVariant::classify(name),
+                    download_size: None,
+                    installed_version: None,
+                    out_of_date: None,
+                    group_members: None,
+                    provides_match: None,
+                    repo: None,
+                });
+            } else if let Some(last) = items.last_mut() {
+                last.description = line.trim().to_string();
+            }
+        }
+        Ok(items)
+    }
+
+    fn details(
+        &self,
+        id: &PackageId,
+        sink: &ProgressSink,
+        cancel: &CancelToken,
     ) -> Result<PackageDetails> {
-        let url = format!(
-            "https://aur.archlinux.org/rpc/?v=5&type=info&arg[]={}",
-            urlencoding::encode(&id.name)
-        );
-        let mut resp = ureq::get(&url)
-            .call()
-            .map_err(|e| Error::Network(e.to_string()))?;
-        let resp: AurResponse<AurPkg> = resp
-            .body_mut()
-            .read_json()
-            .map_err(|e| Error::Network(e.to_string()))?;
-        let p = resp
-            .results
-            .into_iter()
-            .next()
-            .ok_or_else(|| Error::Aur("not found".into()))?;
+        let p = self.info(&id.name, sink, cancel)?;
+        let mut details = to_details(p, &self.installed());
+        if details.summary.installed {
+            details.required_by = required_by(&id.name);
+        }
+        Ok(details)
+    }
 
-        let installed = installed_set();
+    fn details_batch(
+        &self,
+        ids: &[PackageId],
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<Vec<PackageDetails>> {
+        let names: Vec<&str> = ids.iter().map(|id| id.name.as_str()).collect();
+        let installed = self.installed();
+        Ok(self
+            .info_many(&names, sink, cancel)?
+            .into_iter()
+            .map(|p| to_details(p, &installed))
+            .collect())
+    }
 
-        let summary = PackageSummary {
-            id: PackageId {
-                name: p.name.clone(),
-                source: Source::Aur,
-            },
-            version: p.version,
-            description: p.description.unwrap_or_default(),
-            installed: installed.contains(&p.name),
-            popular: p.votes,
-            last_updated: ts(p.last_modified),
+    fn sibling(
+        &self,
+        id: &PackageId,
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<Option<PackageSummary>> {
+        // By convention `foo-bin` repackages the upstream release of `foo`; VCS packages
+        // track a branch instead, so they have no counterpart
+        let name = match AurVariant::classify(&id.name) {
+            AurVariant::Prebuilt => id.name.strip_suffix("-bin").unwrap_or(&id.name).to_string(),
+            AurVariant::Regular => format!("{}-bin", id.name),
+            AurVariant::Vcs => return Ok(None),
         };
-        Ok(PackageDetails {
-            summary,
-            depends: vec![],
-            opt_depends: vec![],
-            homepage: None,
-            maintainer: p.maintainer,
-            size_install: None,
-            size_download: None,
-        })
+        match self.info_many(&[&name], sink, cancel) {
+            Ok(found) => Ok(found
+                .into_iter()
+                .next()
+                .map(|p| to_details(p, &self.installed()).summary)),
+            // Only a suggestion, so an offline lookup shouldn't surface as an error
+            Err(e) => {
+                sink.send(Progress {
+                    job_id: 0,
+                    stage: Stage::Resolving,
+                    percent: None,
+                    bytes: None,
+                    log: Some(format!("aur: looking up {name} failed: {e}")),
+                    warning: true,
+                })
+                .ok();
+                Ok(None)
+            }
+        }
     }
 
-    fn install(&self, id: &PackageId, sink: &ProgressSink, _cancel: &CancelToken) -> Result<()> {
+    fn files(
+        &self,
+        id: &PackageId,
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<Vec<PathBuf>> {
+        // Once built, AUR packages live in the local pacman db like any other. Paths
+        // needn't be UTF-8.
+        let out = Command::new("pacman")
+            .args(["-Qlq", &id.name])
+            .output()
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        if !out.status.success() {
+            return Err(Error::Aur("not installed".into()));
+        }
+        Ok(out
+            .stdout
+            .split(|b| *b == b'\n')
+            .filter(|l| !l.is_empty() && !l.ends_with(b"/"))
+            .map(|l| PathBuf::from(OsStr::from_bytes(l)))
+            .collect())
+    }
+
+    fn install(&self, id: &PackageId, sink: &ProgressSink, cancel: &CancelToken) -> Result<()> {
         sink.send(Progress {
             job_id: 0,
             stage: Stage::Building,
@@ -215,23 +1442,26 @@ impl PackageBackend for AurBackend {
         })
         .ok();
 
-        let work = tempfile::tempdir().map_err(|e| Error::Internal(e.to_string()))?;
-        let dir = work.path().join(&id.name);
-
-        // Shallow clone to reduce bandwidth
-        let status = Command::new("git")
-            .args([
-                "clone",
-                "--depth=1",
-                &format!("https://aur.archlinux.org/{}.git", id.name),
-                dir.to_str().unwrap(),
-            ])
-            .status()
-            .map_err(|e| Error::Internal(e.to_string()))?;
-        if !status.success() {
-            return Err(Error::Aur("git clone failed".into()));
+        // makepkg refuses to build as root, which is how containers and chroots usually run
+        if self.privilege == Privilege::Direct {
+            return Err(Error::Aur(
+                "makepkg can't run as root; build AUR packages as a regular user".into(),
+            ));
         }
 
+        // Every early return below drops `_work`, which deletes a throwaway checkout and
+        // build tree; a persistent checkout is kept and cleaned on its next use.
+        let reviewed = self.reviewed.lock().remove(&id.name);
+        // What was reviewed is built as is; otherwise the helper, if there is one, takes over
+        if let (Some(helper), None) = (self.helper, &reviewed) {
+            return self.install_with_helper(helper, &id.name, sink, cancel);
+        }
+        let (_work, dir) = match reviewed {
+            Some(checkout) => checkout,
+            None => self.fetch(&id.name, sink, cancel)?,
+        };
+        check_cancel(cancel)?;
+
         // Generate .SRCINFO (no shell redirection)
         let out = Command::new("makepkg")
             .arg("--printsrcinfo")
@@ -246,25 +1476,29 @@ impl PackageBackend for AurBackend {
         f.write_all(&out.stdout)
             .map_err(|e| Error::Internal(e.to_string()))?;
 
-        // Preinstall repo deps best-effort
+        check_cancel(cancel)?;
+
+        // Preinstall repo deps best-effort, remembering which build-only ones are new
         let srcinfo = String::from_utf8_lossy(&out.stdout);
-        let deps = parse_srcinfo_deps(&srcinfo);
-        if !deps.is_empty() {
-            let _ = Command::new("pkexec")
-                .args(["pacman", "-S", "--noconfirm", "--needed"])
-                .args(deps.iter().map(|s| s.as_str()))
-                .status();
-        }
+        let (depends, build) = parse_srcinfo_deps(&srcinfo);
+        let before = installed_set();
+        let new_build: Vec<String> = build
+            .iter()
+            .filter(|d| !before.contains(*d))
+            .cloned()
+            .collect();
+        self.preinstall(&depends, sink, cancel)?;
+        self.preinstall(&build, sink, cancel)?;
+
+        check_cancel(cancel)?;
 
         // Build package (no -i here)
-        let status = Command::new("makepkg")
-            .args(["-s", "--noconfirm"])
-            .current_dir(&dir)
-            .status()
-            .map_err(|e| Error::Internal(e.to_string()))?;
-        if !status.success() {
+        let mut cmd = Command::new("makepkg");
+        cmd.args(["-s", "--noconfirm"]).current_dir(&dir);
+        if self.run_logged(cmd, sink, cancel, Stage::Building)? != 0 {
             return Err(Error::Aur("makepkg failed".into()));
         }
+        check_cancel(cancel)?;
 
         // Install artifact via pacman -U
         let pkg =
@@ -272,39 +1506,455 @@ impl PackageBackend for AurBackend {
         if !validate_pkg_path(&pkg) {
             return Err(Error::Aur("invalid built package path".into()));
         }
-        let code = Command::new("pkexec")
-            .args(["pacman", "-U", "--noconfirm", pkg.to_str().unwrap()])
-            .status()
-            .map_err(|e| Error::Priv(e.to_string()))?;
-        if code.success() {
-            Ok(())
+        let mut cmd = self.privilege.command("pacman");
+        cmd.args(["-U", "--noconfirm"]).arg(&pkg);
+        if self.verbosity == Verbosity::Debug {
+            cmd.arg("--debug");
+        }
+        let code = self.run_logged(cmd, sink, cancel, Stage::Installing)?;
+        self.privilege.check("pacman -U", code)?;
+        remember_pkgbuild(&id.name, &dir);
+
+        // Offer to remove what was only needed for this build
+        if let Some(events) = &self.events {
+            let orphans = orphans();
+            let names: Vec<String> = new_build
+                .into_iter()
+                .filter(|d| orphans.contains(d))
+                .collect();
+            if !names.is_empty() {
+                let _ = events.send(Event::OrphanedBuildDeps { names });
+            }
+        }
+        Ok(())
+    }
+
+    fn history(
+        &self,
+        id: &PackageId,
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<Vec<LogEntry>> {
+        let p = self.info(&id.name, sink, cancel)?;
+        let base = p.package_base.unwrap_or(p.name);
+        if !valid_base(&base) {
+            return Err(Error::Aur(format!("invalid package base: {base}")));
+        }
+        let dir = cache_dir("aur-history")
+            .ok_or_else(|| Error::Internal("no cache directory".into()))?
+            .join(format!("{base}.git"));
+
+        let cached = dir.exists();
+        let fetched = if cached {
+            Command::new("git")
+                .arg("--git-dir")
+                .arg(&dir)
+                .args(["fetch", "--quiet"])
+                .status()
+                .map_err(|e| Error::Internal(e.to_string()))?
+                .success()
         } else {
-            Err(Error::Priv("pacman -U failed".into()))
+            self.git_remote(&base, sink, cancel, |url| {
+                let mut cmd = Command::new("git");
+                cmd.args(["clone", "--quiet", "--mirror", "--filter=blob:none"])
+                    .arg(url)
+                    .arg(&dir);
+                cmd
+            })?;
+            true
+        };
+        if !fetched {
+            // Offline: the log we already have is still worth showing
+            sink.send(Progress {
+                job_id: 0,
+                stage: Stage::Resolving,
+                percent: None,
+                bytes: None,
+                log: Some(format!(
+                    "aur: fetching {base} history failed; showing cached log"
+                )),
+                warning: true,
+            })
+            .ok();
         }
+        check_cancel(cancel)?;
+
+        let out = Command::new("git")
+            .arg("--git-dir")
+            .arg(&dir)
+            .args(["log", "-n", "50", "--format=%h%x09%at%x09%an%x09%s"])
+            .output()
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        if !out.status.success() {
+            return Err(Error::Aur("git log failed".into()));
+        }
+        Ok(parse_git_log(&String::from_utf8_lossy(&out.stdout)))
+    }
+
+    fn build_files(
+        &self,
+        id: &PackageId,
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<Vec<BuildFile>> {
+        let (work, dir) = self.fetch(&id.name, sink, cancel)?;
+        let mut names: Vec<String> = fs::read_dir(&dir)
+            .map_err(|e| Error::Internal(e.to_string()))?
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name().to_string_lossy().into_owned())
+            .filter(|n| n.ends_with(".install"))
+            .collect();
+        names.sort();
+        names.insert(0, "PKGBUILD".into());
+        let mut files: Vec<BuildFile> = names
+            .into_iter()
+            .filter_map(|n| {
+                let text = fs::read(dir.join(&n)).ok()?;
+                Some((n, String::from_utf8_lossy(&text).into_owned()))
+            })
+            .collect();
+        // On a rebuild, what changed is what needs reading
+        if let Some(diff) = pkgbuild_diff(&id.name, &dir) {
+            files.insert(0, (PKGBUILD_DIFF.into(), diff));
+        }
+        self.reviewed.lock().insert(id.name.clone(), (work, dir));
+        Ok(files)
+    }
+
+    fn build_deps(
+        &self,
+        id: &PackageId,
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<Vec<String>> {
+        let p = self.info(&id.name, sink, cancel)?;
+        let installed = self.installed();
+        Ok(
+            build_only(&p.depends, p.make_depends.iter().chain(&p.check_depends))
+                .into_iter()
+                .filter(|d| !installed.contains(d))
+                .collect(),
+        )
     }
 
     fn remove(&self, id: &PackageId, _sink: &ProgressSink, _cancel: &CancelToken) -> Result<()> {
-        let code = Command::new("pkexec")
-            .args(["pacman", "-Rns", "--noconfirm", &id.name])
+        let code = self
+            .privilege
+            .command("pacman")
+            .args(["-Rns", "--noconfirm", &id.name])
             .status()
             .map_err(|e| Error::Priv(e.to_string()))?;
-        if code.success() {
-            Ok(())
-        } else {
-            Err(Error::Priv("remove failed".into()))
+        self.privilege.check("remove", code.code().unwrap_or(-1))
+    }
+
+    fn remove_many(
+        &self,
+        ids: &[PackageId],
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<()> {
+        // Built packages are plain pacman packages, so they go in one removal
+        let code = self
+            .privilege
+            .command("pacman")
+            .args(["-Rns", "--noconfirm"])
+            .args(ids.iter().map(|id| id.name.as_str()))
+            .status()
+            .map_err(|e| Error::Priv(e.to_string()))?;
+        self.privilege.check("remove", code.code().unwrap_or(-1))
+    }
+
+    fn remove_preview(
+        &self,
+        id: &PackageId,
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<RemovePreview> {
+        // --print resolves the transaction without running it, so it needs no root
+        let out = Command::new("pacman")
+            .args(["-Rns", "--print", "--print-format", "%n", &id.name])
+            .output()
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        RemovePreview::from_pacman(&out)
+    }
+
+    fn clear_build_cache(&self, sink: &ProgressSink, _cancel: &CancelToken) -> Result<()> {
+        // Build records in `aur-pkgbuilds` stay: they aren't clones, and the next review
+        // diffs against them. The checkouts root may be the user's own dir, so only the
+        // checkouts we made in it go.
+        let checkouts = self
+            .checkouts_root()
+            .ok()
+            .and_then(|root| fs::read_dir(root).ok())
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|d| d.join(CHECKOUT_MARKER).is_file());
+        let dirs = checkouts.chain(cache_dir("aur-history").filter(|d| d.exists()));
+        for dir in dirs {
+            fs::remove_dir_all(&dir).map_err(|e| Error::Internal(e.to_string()))?;
+            sink.send(Progress {
+                job_id: 0,
+                stage: Stage::Cleaning,
+                percent: None,
+                bytes: None,
+                log: Some(format!("removed {}", dir.display())),
+                warning: false,
+            })
+            .ok();
         }
+        Ok(())
     }
 
-    fn upgrades(&self, _sink: &ProgressSink, _cancel: &CancelToken) -> Result<Vec<PackageSummary>> {
-        Ok(vec![]) // repo upgrades are implemented, would not be preferable to update apps already in repo with aur versions
+    fn rebuild_candidates(
+        &self,
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<Vec<PackageSummary>> {
+        let foreign = foreign_packages();
+        let broken: Vec<String> = match Command::new("checkrebuild").output() {
+            Ok(out) => parse_checkrebuild(&String::from_utf8_lossy(&out.stdout)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                sink.send(Progress {
+                    job_id: 0,
+                    stage: Stage::Verifying,
+                    percent: None,
+                    bytes: None,
+                    log: Some(
+                        "AUR: checkrebuild (rebuild-detector) not installed, scanning with ldd"
+                            .into(),
+                    ),
+                    warning: false,
+                })
+                .ok();
+                let mut broken = Vec::new();
+                for (name, _) in &foreign {
+                    if links_missing_library(name, cancel)? {
+                        broken.push(name.clone());
+                    }
+                }
+                broken
+            }
+            Err(e) => return Err(Error::Internal(format!("checkrebuild: {e}"))),
+        };
+
+        // checkrebuild also reports repo packages; those are the distro's to rebuild
+        Ok(foreign
+            .into_iter()
+            .filter(|(name, _)| broken.contains(name))
+            .map(|(name, version)| PackageSummary {
+                variant: AurVariant::classify(&name),
+                id: PackageId {
+                    name,
+                    source: Source::Aur,
+                },
+                version,
+                description: "links against a library that no longer exists".into(),
+                installed: true,
+                popular: None,
+                last_updated: None,
+                download_size: None,
+                installed_version: None,
+                out_of_date: None,
+                group_members: None,
+                provides_match: None,
+                repo: None,
+            })
+            .collect())
+    }
+
+    fn orphans(&self, _sink: &ProgressSink, _cancel: &CancelToken) -> Result<Vec<PackageSummary>> {
+        // -m: foreign packages, i.e. the ones built from the AUR
+        let out = Command::new("pacman")
+            .arg("-Qdtm")
+            .output()
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        // Exit 1 with no output just means there are none
+        Ok(String::from_utf8_lossy(&out.stdout)
+            .lines()
+            .filter_map(|l| l.trim().split_once(' '))
+            .map(|(name, version)| PackageSummary {
+                id: PackageId {
+                    name: name.to_string(),
+                    source: Source::Aur,
+                },
+                version: version.to_string(),
+                description: String::new(),
+                installed: true,
+                popular: None,
+                last_updated: None,
+                variant: AurVariant::classify(name),
+                download_size: None,
+                installed_version: None,
+                out_of_date: None,
+                group_members: None,
+                provides_match: None,
+                repo: None,
+            })
+            .collect())
+    }
+
+    fn upgrades(&self, sink: &ProgressSink, cancel: &CancelToken) -> Result<Vec<PackageSummary>> {
+        // Foreign packages only, so repo packages never get offered an AUR "upgrade".
+        // AurVariant::Vcs packages are set apart: their RPC version is a snapshot and never
+        // tracks the installed commit, so only `vcs_check` can tell they're behind.
+        let (vcs, installed): (HashMap<String, String>, HashMap<String, String>) =
+            foreign_packages()
+                .into_iter()
+                .partition(|(name, _)| AurVariant::classify(name) == AurVariant::Vcs);
+        let names: Vec<&str> = installed.keys().map(String::as_str).collect();
+        let all = self.installed();
+        let mut items = Vec::new();
+        for p in self.info_many(&names, sink, cancel)? {
+            let Some(local) = installed.get(&p.name) else {
+                continue;
+            };
+            if vercmp(&p.version, local) == std::cmp::Ordering::Greater {
+                let mut summary = to_details(p, &all).summary;
+                summary.installed_version = Some(local.clone());
+                items.push(summary);
+            }
+        }
+        if self.vcs_check && !vcs.is_empty() {
+            sink.send(Progress {
+                job_id: 0,
+                stage: Stage::Searching,
+                percent: None,
+                bytes: None,
+                log: Some(format!(
+                    "checking {} VCS packages for new commits",
+                    vcs.len()
+                )),
+                warning: false,
+            })
+            .ok();
+            let names: Vec<&str> = vcs.keys().map(String::as_str).collect();
+            for p in self.info_many(&names, sink, cancel)? {
+                check_cancel(cancel)?;
+                let Some(local) = vcs.get(&p.name) else {
+                    continue;
+                };
+                let Some(built) = vcs_commit(local) else {
+                    continue;
+                };
+                let base = p.package_base.clone().unwrap_or_else(|| p.name.clone());
+                let Some(head) = self.upstream_head(&base, sink, cancel)? else {
+                    continue;
+                };
+                if !head.starts_with(built) {
+                    let mut summary = to_details(p, &all).summary;
+                    summary.installed_version = Some(local.clone());
+                    summary.version = format!("commit {}", &head[..head.len().min(7)]);
+                    items.push(summary);
+                }
+            }
+        }
+        items.sort_by(|a, b| a.id.name.cmp(&b.id.name));
+        Ok(items)
     }
     fn upgrade(&self, id: &PackageId, sink: &ProgressSink, cancel: &CancelToken) -> Result<()> {
         // For AUR, “upgrade” is just “rebuild & install latest”.
         self.install(id, sink, cancel)
     }
 
-    fn upgrade_all(&self, _sink: &ProgressSink, _cancel: &CancelToken) -> Result<()> {
-        // Minimal first step: do nothing. We can iterate available AUR upgrades later.
+    /// Nothing: AUR upgrades are built one by one from the Upgrades view, each after its
+    /// build files can be reviewed.
+    fn upgrade_all(
+        &self,
+        _skip: &[PackageId],
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<()> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vcs_commit_reads_the_hash_from_pkgver() {
+        assert_eq!(vcs_commit("1.2.r34.gabc1234-1"), Some("abc1234"));
+        assert_eq!(vcs_commit("r120.abc1234-2"), Some("abc1234"));
+        assert_eq!(vcs_commit("1:0.9.r5.g1234567-1"), Some("1234567"));
+        assert_eq!(vcs_commit("r1.0a1b2c3d4e5-1"), Some("0a1b2c3d4e5"));
+    }
+
+    #[test]
+    fn vcs_commit_ignores_dates_and_plain_versions() {
+        for v in [
+            "20240101-1",
+            "2024.01.01-1",
+            "1.2.3-1",
+            "r120.1234567-1",
+            "r5.abc-1",
+        ] {
+            assert_eq!(vcs_commit(v), None, "{v:?}");
+        }
+    }
+
+    #[test]
+    fn vcs_source_reads_the_first_git_source() {
+        let srcinfo = "\
+pkgbase = foo-git
+\tpkgver = r10.abc1234
+\tsource = foo.desktop
+\tsource = foo::git+https://github.com/foo/foo.git?signed#branch=dev
+\tsource = git+https://example.org/bar.git
+";
+        assert_eq!(
+            vcs_source(srcinfo),
+            Some((
+                "https://github.com/foo/foo.git".to_string(),
+                "refs/heads/dev".to_string()
+            ))
+        );
+        assert_eq!(
+            vcs_source("source = git+https://example.org/bar.git"),
+            Some((
+                "https://example.org/bar.git".to_string(),
+                "HEAD".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn vcs_source_skips_pinned_and_non_git_sources() {
+        assert_eq!(
+            vcs_source("source = git+https://x.org/a.git#tag=v1.0"),
+            None
+        );
+        assert_eq!(
+            vcs_source("source = git+https://x.org/a.git#commit=abc1234"),
+            None
+        );
+        assert_eq!(vcs_source("source = https://x.org/a-1.0.tar.gz"), None);
+        assert_eq!(vcs_source(""), None);
+    }
+
+    #[test]
+    fn site_follows_the_configured_endpoint() {
+        assert_eq!(RpcConfig::default().site(), "https://aur.archlinux.org/");
+        let local = RpcConfig {
+            base_url: "http://localhost:8080/aur/rpc".into(),
+            ..RpcConfig::default()
+        };
+        assert_eq!(local.site(), "http://localhost:8080/aur/");
+    }
+
+    #[test]
+    fn uri_chunks_stay_under_the_uri_limit() {
+        let owned: Vec<String> = (0..600).map(|i| format!("python-package-{i:04}")).collect();
+        let names: Vec<&str> = owned.iter().map(String::as_str).collect();
+        let fixed = "https://aur.archlinux.org/rpc/?v=5&type=info".len();
+        let chunks = uri_chunks(&names, fixed);
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.iter().map(|c| c.len()).sum::<usize>(), names.len());
+        for chunk in chunks {
+            let args: usize = chunk.iter().map(|n| "&arg[]=".len() + n.len()).sum();
+            assert!(fixed + args <= RPC_MAX_URI);
+        }
+    }
+}
@@ -0,0 +1,594 @@
+use domain::*;
+use std::{
+    collections::{HashMap, HashSet},
+    process::{Command, Stdio},
+};
+
+/// Remote used for installs and for details of apps that aren't installed yet.
+const DEFAULT_REMOTE: &str = "flathub";
+
+/// Flatpak apps, installed per-user so no pkexec is involved.
+#[derive(Default)]
+pub struct FlatpakBackend {
+    verbosity: Verbosity,
+}
+impl FlatpakBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// At `Verbosity::Debug`, commands run with -v and log their command line.
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    fn run_stream(
+        &self,
+        mut cmd: Command,
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+        stage: Stage,
+    ) -> Result<i32> {
+        if self.verbosity == Verbosity::Debug {
+            cmd.arg("-v");
+            sink.send(Progress {
+                job_id: 0,
+                stage: stage.clone(),
+                percent: None,
+                bytes: None,
+                log: Some(format!("$ {cmd:?}")),
+                warning: false,
+            })
+            .ok();
+        }
+        let (status, cancelled) = run_piped(
+            cmd,
+            sink,
+            &stage,
+            cancel,
+            forward(sink, stage.clone(), false),
+            forward(sink, stage.clone(), true),
+        )?;
+        if cancelled {
+            return Err(Error::Cancelled);
+        }
+        Ok(status.code().unwrap_or(-1))
+    }
+
+    fn run_checked(
+        &self,
+        args: &[&str],
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+        stage: Stage,
+        what: &str,
+    ) -> Result<()> {
+        let mut cmd = Command::new("flatpak");
+        cmd.args(args);
+        let code = self.run_stream(cmd, sink, cancel, stage)?;
+        if code == 0 {
+            Ok(())
+        } else {
+            Err(Error::Internal(format!("flatpak {what} exit {code}")))
+        }
+    }
+}
+
+/// flatpak is optional on Arch; without it this source has nothing to sync or upgrade.
+fn flatpak_present() -> bool {
+    Command::new("flatpak")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok()
+}
+
+/// Installed apps' (version, installed size), by application id.
+fn installed_versions() -> HashMap<String, (String, u64)> {
+    let Ok(out) = Command::new("flatpak")
+        .args(["list", "--app", "--columns=application,version,size"])
+        .output()
+    else {
+        return HashMap::new();
+    };
+    String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .filter_map(|l| {
+            let cols: Vec<&str> = l.split('\t').map(|c| c.trim()).collect();
+            let app = cols.first().filter(|a| !a.is_empty())?;
+            let version = cols.get(1).unwrap_or(&"").to_string();
+            let size = cols.get(2).and_then(|s| parse_size(s)).unwrap_or(0);
+            Some((app.to_string(), (version, size)))
+        })
+        .collect()
+}
+
+/// Rows of `flatpak remote-ls --updates --app` with `columns`, split on tabs. None when
+/// flatpak isn't installed; a failed listing is reported and counts as no updates.
+fn update_rows(columns: &str, sink: &ProgressSink) -> Result<Vec<Vec<String>>> {
+    let out = match Command::new("flatpak")
+        .args(["remote-ls", "--updates", "--app"])
+        .arg(format!("--columns={columns}"))
+        .output()
+    {
+        Ok(o) => o,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(Error::Internal(e.to_string())),
+    };
+    if !out.status.success() {
+        sink.send(Progress {
+            job_id: 0,
+            stage: Stage::Verifying,
+            percent: None,
+            bytes: None,
+            log: Some(format!(
+                "flatpak: remote-ls --updates exit {} (treating as no upgrades)",
+                out.status.code().unwrap_or(-1)
+            )),
+            warning: true,
+        })
+        .ok();
+        return Ok(vec![]);
+    }
+    Ok(String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .map(|l| {
+            l.split('\t')
+                .map(|c| c.trim().to_string())
+                .collect::<Vec<_>>()
+        })
+        .filter(|cols| cols.first().is_some_and(|a| !a.is_empty()))
+        .collect())
+}
+
+/// A line handler for `run_piped` that sends each line to `sink`. After a failed send (UI
+/// gone) the rest are dropped, while `run_piped` keeps draining so flatpak doesn't die of
+/// a closed pipe.
+fn forward(sink: &ProgressSink, stage: Stage, warning: bool) -> impl FnMut(String) + Send + use<> {
+    let sink = sink.clone();
+    let mut gone = false;
+    move |l| {
+        if gone {
+            return;
+        }
+        gone = sink
+            .send(Progress {
+                job_id: 0,
+                stage: stage.clone(),
+                percent: None,
+                bytes: None,
+                log: Some(format!("flatpak: {l}")),
+                warning,
+            })
+            .is_err();
+    }
+}
+
+fn installed_apps() -> HashSet<String> {
+    let out = Command::new("flatpak")
+        .args(["list", "--app", "--columns=application"])
+        .output()
+        .ok();
+    let mut set = HashSet::new();
+    if let Some(out) = out {
+        for line in String::from_utf8_lossy(&out.stdout).lines() {
+            let n = line.trim();
+            if !n.is_empty() {
+                set.insert(n.to_string());
+            }
+        }
+    }
+    set
+}
+
+fn summary(app_id: &str, version: &str, description: &str, installed: bool) -> PackageSummary {
+    PackageSummary {
+        id: PackageId {
+            name: app_id.to_string(),
+            source: Source::Flatpak,
+        },
+        version: version.to_string(),
+        description: description.to_string(),
+        installed,
+        popular: None,
+        last_updated: None,
+        variant: AurVariant::Regular,
+        download_size: None,
+        installed_version: None,
+        out_of_date: None,
+        group_members: None,
+        provides_match: None,
+        repo: None,
+    }
+}
+
+// ---------- parsing for `search --columns=application,version,name,description` ----------
+fn parse_flatpak_search(out: &str, installed: &HashSet<String>) -> Vec<PackageSummary> {
+    let mut seen = HashSet::new();
+    out.lines()
+        .filter_map(|l| {
+            // "No matches found" and other chatter has no tab separators
+            let cols: Vec<&str> = l.split('\t').map(|c| c.trim()).collect();
+            if cols.len() < 4 || cols[0].is_empty() {
+                return None;
+            }
+            // The same app can be listed once per remote
+            if !seen.insert(cols[0].to_string()) {
+                return None;
+            }
+            let description = if cols[3].is_empty() {
+                cols[2].to_string()
+            } else {
+                format!("{} - {}", cols[2], cols[3])
+            };
+            Some(summary(
+                cols[0],
+                cols[1],
+                &description,
+                installed.contains(cols[0]),
+            ))
+        })
+        .collect()
+}
+
+// ---------- parsing for `info` / `remote-info` ----------
+fn parse_flatpak_info(out: &str, mut summary: PackageSummary) -> PackageDetails {
+    let mut size_install = None;
+    let mut size_download = None;
+    let mut depends = Vec::new();
+    let mut architecture = None;
+
+    for line in out.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+        let Some((key, value)) = line.split_once(": ") else {
+            // Title line: "Name - summary"
+            if summary.description.is_empty() {
+                summary.description = line.to_string();
+            }
+            continue;
+        };
+        let value = value.trim();
+        match key.trim() {
+            "Version" if summary.version.is_empty() => summary.version = value.to_string(),
+            "Installed" | "Installed size" => size_install = parse_size(value),
+            "Download" | "Download size" => size_download = parse_size(value),
+            "Runtime" => depends.push(value.to_string()),
+            "Arch" => architecture = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    PackageDetails {
+        summary,
+        depends,
+        opt_depends: vec![],
+        homepage: None,
+        maintainer: None,
+        size_install,
+        size_download,
+        architecture,
+        conflicts: vec![],
+        installed_conflicts: vec![],
+        replaces: vec![],
+        provides: vec![],
+        required_by: vec![],
+    }
+}
+
+/// Reads GLib-formatted sizes with SI units, as flatpak prints them: "250.1 MB",
+/// "123 bytes", or "1,5 GB" under locales with a decimal comma. `None` for anything else.
+fn parse_size(s: &str) -> Option<u64> {
+    let mut it = s.split_whitespace();
+    let n: f64 = it.next()?.replace(',', ".").parse().ok()?;
+    if !n.is_finite() || n < 0.0 {
+        return None;
+    }
+    let scale = match it.next()? {
+        "byte" | "bytes" => 1.0,
+        "kB" => 1e3,
+        "MB" => 1e6,
+        "GB" => 1e9,
+        "TB" => 1e12,
+        "PB" => 1e15,
+        _ => return None,
+    };
+    it.next().is_none().then_some((n * scale) as u64)
+}
+
+impl PackageBackend for FlatpakBackend {
+    fn refresh(&self, sink: &ProgressSink, cancel: &CancelToken) -> Result<()> {
+        if !flatpak_present() {
+            return Ok(());
+        }
+        self.run_checked(
+            &["update", "--appstream", "--noninteractive"],
+            sink,
+            cancel,
+            Stage::Refreshing,
+            "appstream update",
+        )
+    }
+
+    fn search(
+        &self,
+        q: &str,
+        sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<Vec<PackageSummary>> {
+        let q = q.trim();
+        sink.send(Progress {
+            job_id: 0,
+            stage: Stage::Searching,
+            percent: None,
+            bytes: None,
+            log: Some(format!("flatpak search: {q}")),
+            warning: false,
+        })
+        .ok();
+
+        let out = match Command::new("flatpak")
+            .args([
+                "search",
+                "--columns=application,version,name,description",
+                q,
+            ])
+            .output()
+        {
+            Ok(o) => o,
+            // flatpak is optional on Arch; without it this source simply has no results
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(Error::Internal(format!("spawn flatpak: {e}"))),
+        };
+        if !out.status.success() {
+            return Err(Error::Internal(format!(
+                "flatpak search exit {}: {}",
+                out.status.code().unwrap_or(-1),
+                String::from_utf8_lossy(&out.stderr).trim()
+            )));
+        }
+        let installed = installed_apps();
+        Ok(parse_flatpak_search(
+            &String::from_utf8_lossy(&out.stdout),
+            &installed,
+        ))
+    }
+
+    fn details(
+        &self,
+        id: &PackageId,
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<PackageDetails> {
+        // `info` only knows installed apps; fall back to the remote for everything else.
+        let local = Command::new("flatpak")
+            .args(["info", &id.name])
+            .output()
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        let (out, installed) = if local.status.success() {
+            (local, true)
+        } else {
+            let remote = Command::new("flatpak")
+                .args(["remote-info", DEFAULT_REMOTE, &id.name])
+                .output()
+                .map_err(|e| Error::Internal(e.to_string()))?;
+            if !remote.status.success() {
+                return Err(Error::Internal("flatpak remote-info failed".into()));
+            }
+            (remote, false)
+        };
+        let s = String::from_utf8_lossy(&out.stdout);
+        Ok(parse_flatpak_info(&s, summary(&id.name, "", "", installed)))
+    }
+
+    fn install(&self, id: &PackageId, sink: &ProgressSink, cancel: &CancelToken) -> Result<()> {
+        self.run_checked(
+            &[
+                "install",
+                "--user",
+                "--noninteractive",
+                "-y",
+                DEFAULT_REMOTE,
+                &id.name,
+            ],
+            sink,
+            cancel,
+            Stage::Installing,
+            "install",
+        )
+    }
+
+    fn remove(&self, id: &PackageId, sink: &ProgressSink, cancel: &CancelToken) -> Result<()> {
+        // No --user: let flatpak find whichever installation holds the app.
+        self.run_checked(
+            &["uninstall", "--noninteractive", "-y", &id.name],
+            sink,
+            cancel,
+            Stage::Removing,
+            "uninstall",
+        )
+    }
+
+    fn upgrades(&self, sink: &ProgressSink, _cancel: &CancelToken) -> Result<Vec<PackageSummary>> {
+        Ok(update_rows("application,version", sink)?
+            .iter()
+            .map(|cols| summary(&cols[0], cols.get(1).map_or("", String::as_str), "", true))
+            .collect())
+    }
+
+    fn upgrade(&self, id: &PackageId, sink: &ProgressSink, cancel: &CancelToken) -> Result<()> {
+        self.run_checked(
+            &["update", "--noninteractive", "-y", &id.name],
+            sink,
+            cancel,
+            Stage::Installing,
+            "update",
+        )
+    }
+
+    fn upgrade_all(
+        &self,
+        skip: &[PackageId],
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<()> {
+        if !flatpak_present() {
+            return Ok(());
+        }
+        if !skip.iter().any(|id| id.source == Source::Flatpak) {
+            return self.run_checked(
+                &["update", "--noninteractive", "-y"],
+                sink,
+                cancel,
+                Stage::Installing,
+                "update",
+            );
+        }
+        // flatpak has no --ignore, so name everything else explicitly
+        let apps: Vec<String> = self
+            .upgrades(sink, cancel)?
+            .into_iter()
+            .filter(|p| !skip.contains(&p.id))
+            .map(|p| p.id.name)
+            .collect();
+        if apps.is_empty() {
+            return Ok(());
+        }
+        let mut args = vec!["update", "--noninteractive", "-y"];
+        args.extend(apps.iter().map(String::as_str));
+        self.run_checked(&args, sink, cancel, Stage::Installing, "update")
+    }
+
+    /// From `remote-ls --updates`: flatpak has no dry run, so runtimes an app update pulls
+    /// in aren't counted.
+    fn plan_upgrade_all(
+        &self,
+        skip: &[PackageId],
+        sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<TransactionPlan> {
+        let installed = installed_versions();
+        let mut plan = TransactionPlan::default();
+        for cols in update_rows("application,version,download-size,installed-size", sink)? {
+            let app = &cols[0];
+            if skip.iter().any(|id| id.name == *app) {
+                continue;
+            }
+            let col = |i: usize| cols.get(i).map_or("", String::as_str);
+            let (old, old_size) = installed.get(app).cloned().unwrap_or_default();
+            plan.upgrades.push((app.clone(), old, col(1).to_string()));
+            plan.download_size += parse_size(col(2)).unwrap_or(0);
+            if let Some(size) = parse_size(col(3)) {
+                plan.install_size_delta += size as i64 - old_size as i64;
+            }
+        }
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `flatpak search --columns=application,version,name,description gnome calculator`
+    /// with flathub and flathub-beta both configured.
+    const SEARCH: &str = "\
+org.gnome.Calculator\t46.1\tCalculator\tPerform arithmetic, scientific or financial calculations
+org.gnome.Calculator\t47.beta\tCalculator\tPerform arithmetic, scientific or financial calculations
+io.github.Qalculate\t5.2.0\tQalculate!\t
+";
+
+    /// `flatpak info org.gnome.Calculator`
+    const INFO: &str = "
+GNOME Calculator - Perform arithmetic, scientific or financial calculations
+
+          ID: org.gnome.Calculator
+         Ref: app/org.gnome.Calculator/x86_64/stable
+        Arch: x86_64
+      Branch: stable
+     Version: 46.1
+     License: GPL-3.0-or-later
+      Origin: flathub
+  Collection: org.flathub.Stable
+Installation: system
+   Installed: 5.6\u{a0}MB
+     Runtime: org.gnome.Platform/x86_64/46
+         Sdk: org.gnome.Sdk/x86_64/46
+
+      Commit: 9b2c5f7e0d1a4c3b8e6f2a1d0c9b8a7f6e5d4c3b2a1f0e9d8c7b6a5f4e3d2c1b
+     Subject: Update to 46.1 (a1b2c3d4)
+        Date: 2024-05-01 10:00:00 +0000
+";
+
+    #[test]
+    fn search_lists_each_app_once() {
+        let installed = HashSet::from(["io.github.Qalculate".to_string()]);
+        let found = parse_flatpak_search(SEARCH, &installed);
+        assert_eq!(found.len(), 2);
+        // The first remote's listing wins
+        assert_eq!(found[0].id.name, "org.gnome.Calculator");
+        assert_eq!(found[0].version, "46.1");
+        assert_eq!(
+            found[0].description,
+            "Calculator - Perform arithmetic, scientific or financial calculations"
+        );
+        assert!(!found[0].installed);
+        assert_eq!(found[1].description, "Qalculate!");
+        assert!(found[1].installed);
+        assert!(found.iter().all(|p| p.id.source == Source::Flatpak));
+    }
+
+    #[test]
+    fn search_without_matches_is_empty() {
+        assert!(parse_flatpak_search("No matches found\n", &HashSet::new()).is_empty());
+        assert!(parse_flatpak_search("", &HashSet::new()).is_empty());
+    }
+
+    #[test]
+    fn info_reads_version_size_runtime_and_arch() {
+        let d = parse_flatpak_info(INFO, summary("org.gnome.Calculator", "", "", true));
+        assert_eq!(
+            d.summary.description,
+            "GNOME Calculator - Perform arithmetic, scientific or financial calculations"
+        );
+        assert_eq!(d.summary.version, "46.1");
+        assert_eq!(d.size_install, Some(5_600_000));
+        assert_eq!(d.size_download, None);
+        assert_eq!(d.depends, ["org.gnome.Platform/x86_64/46"]);
+        assert_eq!(d.architecture.as_deref(), Some("x86_64"));
+    }
+
+    #[test]
+    fn info_reads_remote_sizes() {
+        let out = "Version: 46.1\nDownload: 2,1 MB\nInstalled: 5.6 MB\n";
+        let d = parse_flatpak_info(out, summary("org.gnome.Calculator", "", "", false));
+        assert_eq!(d.size_download, Some(2_100_000));
+        assert_eq!(d.size_install, Some(5_600_000));
+    }
+
+    #[test]
+    fn parse_size_reads_glib_units() {
+        assert_eq!(parse_size("1 byte"), Some(1));
+        assert_eq!(parse_size("123 bytes"), Some(123));
+        assert_eq!(parse_size("1.5 kB"), Some(1_500));
+        assert_eq!(parse_size("250.1 MB"), Some(250_100_000));
+        assert_eq!(parse_size("1,5 GB"), Some(1_500_000_000));
+        assert_eq!(parse_size("2.0 TB"), Some(2_000_000_000_000));
+    }
+
+    #[test]
+    fn parse_size_rejects_malformed_input() {
+        for s in [
+            "",
+            "MB",
+            "12",
+            "12 MiB",
+            "-4 kB",
+            "inf GB",
+            "1.2.3 MB",
+            "5 MB extra",
+        ] {
+            assert_eq!(parse_size(s), None, "{s:?}");
+        }
+    }
+}
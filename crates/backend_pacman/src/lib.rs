@@ -1,19 +1,49 @@
 use domain::*;
 use regex::Regex;
 use std::{
-    io::{BufRead, BufReader},
+    collections::HashMap,
+    ffi::OsStr,
+    os::unix::ffi::OsStrExt,
+    path::PathBuf,
     process::{Command, Stdio},
+    sync::{Arc, Mutex},
 };
 
-pub struct PacmanCli;
+pub struct PacmanCli {
+    events: Option<EventSink>,
+    verbosity: Verbosity,
+    privilege: Privilege,
+}
 impl PacmanCli {
     pub fn new() -> Self {
-        Self
+        Self {
+            events: None,
+            verbosity: Verbosity::default(),
+            privilege: Privilege::default(),
+        }
+    }
+
+    /// How transactions get root.
+    pub fn with_privilege(mut self, privilege: Privilege) -> Self {
+        self.privilege = privilege;
+        self
+    }
+
+    /// At `Verbosity::Debug`, transactions run with --debug and log their command line.
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Report structured transaction results (e.g. which hooks ran) on `events`.
+    pub fn with_events(mut self, events: EventSink) -> Self {
+        self.events = Some(events);
+        self
     }
 
     fn parse_upgrades(out: &str) -> Vec<PackageSummary> {
         // Lines look like: "pkgname oldver -> newver"
-        let re = Regex::new(r"^(?P<name>\S+)\s+\S+\s+->\s+(?P<new>\S+)").unwrap();
+        let re = Regex::new(r"^(?P<name>\S+)\s+(?P<old>\S+)\s+->\s+(?P<new>\S+)").unwrap();
         out.lines()
             .filter_map(|l| {
                 re.captures(l).map(|c| PackageSummary {
@@ -26,11 +56,36 @@ impl PacmanCli {
                     installed: true,
                     popular: None,
                     last_updated: None,
+                    variant: AurVariant::Regular,
+                    download_size: None,
+                    installed_version: Some(c["old"].to_string()),
+                    out_of_date: None,
+                    group_members: None,
+                    provides_match: None,
+                    repo: None,
                 })
             })
             .collect()
     }
 
+    /// Download size per package of a full upgrade, from `pacman -Sup` (no root needed).
+    /// Empty if pacman can't tell, e.g. without sync dbs.
+    fn upgrade_download_sizes() -> std::collections::HashMap<String, u64> {
+        let Ok(out) = Command::new("pacman")
+            .args(["-Sup", "--print-format", "%n %s"])
+            .output()
+        else {
+            return Default::default();
+        };
+        String::from_utf8_lossy(&out.stdout)
+            .lines()
+            .filter_map(|l| {
+                let (name, size) = l.trim().split_once(' ')?;
+                Some((name.to_string(), size.parse().ok()?))
+            })
+            .collect()
+    }
+
     fn search_fallback_names(&self, q: &str, sink: &ProgressSink) -> Result<Vec<PackageSummary>> {
         let out = match std::process::Command::new("pacman")
             .args(["-Ssq", q])
@@ -82,6 +137,13 @@ impl PacmanCli {
                 installed: false,
                 popular: None,
                 last_updated: None,
+                variant: AurVariant::Regular,
+                download_size: None,
+                installed_version: None,
+                out_of_date: None,
+                group_members: None,
+                provides_match: None,
+                repo: None,
             })
             .collect::<Vec<_>>();
 
@@ -111,6 +173,318 @@ impl PacmanCli {
     }
 }
 
+const SYNC_DB_DIR: &str = "/var/lib/pacman/sync";
+
+/// True when at least one sync database has been downloaded. A fresh install has none
+/// until the first `pacman -Sy`.
+fn sync_dbs_present() -> bool {
+    std::fs::read_dir(SYNC_DB_DIR)
+        .map(|rd| {
+            rd.filter_map(|e| e.ok())
+                .any(|e| e.path().extension().is_some_and(|x| x == "db"))
+        })
+        .unwrap_or(false)
+}
+
+/// Sync dbs as fresh as the `-Syu` that follows will see them, without touching the real
+/// ones (a db-only sync is a partial upgrade waiting to happen). Like checkupdates: a
+/// throwaway dbpath links the local db and downloads the sync dbs under fakeroot, as
+/// pacman won't sync unprivileged. `None` without fakeroot or when the download fails.
+fn fresh_dbpath(cancel: &CancelToken) -> Option<tempfile::TempDir> {
+    if !on_path("fakeroot") || cancel.is_cancelled() {
+        return None;
+    }
+    let tmp = tempfile::Builder::new()
+        .prefix("soredowe-plan-db-")
+        .tempdir()
+        .ok()?;
+    let local = std::path::Path::new(SYNC_DB_DIR).with_file_name("local");
+    std::os::unix::fs::symlink(local, tmp.path().join("local")).ok()?;
+    let ok = Command::new("fakeroot")
+        .args(["--", "pacman", "-Sy", "--logfile", "/dev/null", "--dbpath"])
+        .arg(tmp.path())
+        .stdin(Stdio::null())
+        .output()
+        .is_ok_and(|o| o.status.success());
+    ok.then_some(tmp)
+}
+
+const PKG_CACHE_DIR: &str = "/var/cache/pacman/pkg";
+
+/// Versions of each package `paccache` leaves in the cache.
+const CACHE_KEEP: &str = "3";
+
+/// Whether pacman-contrib's `paccache` is installed; without it cleaning falls back to
+/// `pacman -Sc`, which keeps only installed versions.
+fn paccache_present() -> bool {
+    on_path("paccache")
+}
+
+fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+}
+
+/// Splits a package file name, "name-pkgver-pkgrel-arch.pkg.tar.zst", into the name and
+/// "pkgver-pkgrel" (epoch included); names may contain dashes, versions can't. `None` for
+/// signatures and anything else.
+fn split_pkg_file(file: &str) -> Option<(&str, String)> {
+    let (stem, ext) = file.split_once(".pkg.tar")?;
+    if ext.ends_with(".sig") {
+        return None;
+    }
+    let mut parts = stem.rsplitn(4, '-');
+    let (_arch, rel, ver, name) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+    Some((name, format!("{ver}-{rel}")))
+}
+
+/// Versions of `name` in the package cache, with their file.
+fn cached_packages(name: &str) -> Vec<(String, PathBuf)> {
+    let Ok(rd) = std::fs::read_dir(PKG_CACHE_DIR) else {
+        return vec![];
+    };
+    rd.filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let file = e.file_name().to_string_lossy().into_owned();
+            let (n, version) = split_pkg_file(&file)?;
+            (n == name).then(|| (version, e.path()))
+        })
+        .collect()
+}
+
+/// True when some sync database has no files database (`pacman -Fy`) or a newer one than
+/// it, so file searches would miss or misreport packages.
+fn files_db_stale() -> bool {
+    let modified = |p: &std::path::Path| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+    std::fs::read_dir(SYNC_DB_DIR)
+        .map(|rd| {
+            rd.filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|x| x == "db"))
+                .any(
+                    |db| match (modified(&db), modified(&db.with_extension("files"))) {
+                        (Some(db), Some(files)) => files < db,
+                        _ => true,
+                    },
+                )
+        })
+        .unwrap_or(false)
+}
+
+fn files_dbs_present() -> bool {
+    std::fs::read_dir(SYNC_DB_DIR)
+        .map(|rd| {
+            rd.filter_map(|e| e.ok())
+                .any(|e| e.path().extension().is_some_and(|x| x == "files"))
+        })
+        .unwrap_or(false)
+}
+
+// ---------- parsing for -F --machinereadable ----------
+/// Lines are "repo\0name\0version\0path"; one per matching file, so a package can repeat.
+fn parse_pacman_file_search(out: &str) -> Vec<PackageSummary> {
+    let installed: std::collections::HashSet<String> = Command::new("pacman")
+        .arg("-Qq")
+        .output()
+        .map(|o| {
+            String::from_utf8_lossy(&o.stdout)
+                .lines()
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut res: Vec<PackageSummary> = Vec::new();
+    for line in out.lines() {
+        let cols: Vec<&str> = line.split('\0').collect();
+        let [_repo, name, version, path] = cols[..] else {
+            continue;
+        };
+        if res.iter().any(|p| p.id.name == name) {
+            continue;
+        }
+        res.push(PackageSummary {
+            id: PackageId {
+                name: name.to_string(),
+                source: Source::Repo,
+            },
+            version: version.to_string(),
+            description: format!("provides /{path}"),
+            installed: installed.contains(name),
+            popular: None,
+            last_updated: None,
+            variant: AurVariant::Regular,
+            download_size: None,
+            installed_version: None,
+            out_of_date: None,
+            group_members: None,
+            provides_match: None,
+            repo: None,
+        });
+    }
+    res
+}
+
+/// Members of the pacman group `name`, from the sync dbs (`local` false, `-Sg`) or the
+/// local db (`-Qg`). Empty when there's no such group.
+fn group_members(name: &str, local: bool) -> Vec<String> {
+    let Ok(out) = Command::new("pacman")
+        .args([if local { "-Qg" } else { "-Sg" }, name])
+        .output()
+    else {
+        return vec![];
+    };
+    String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .filter_map(|l| l.split_whitespace().nth(1).map(str::to_string))
+        .collect()
+}
+
+/// Puts a row for the group named `q` first in `items`, if there's such a group and no
+/// package shares its name.
+fn with_group(q: &str, mut items: Vec<PackageSummary>) -> Vec<PackageSummary> {
+    if items.iter().any(|p| p.id.name == q) {
+        return items;
+    }
+    let members = group_members(q, false);
+    if members.is_empty() {
+        return items;
+    }
+    items.insert(0, group_summary(q, members.len()));
+    items
+}
+
+/// Puts the packages providing `q` under another name (jdk-openjdk for "java-runtime")
+/// first in `items`, unless a package is named `q` outright. Ones the search already
+/// listed are only marked.
+fn with_providers(q: &str, mut items: Vec<PackageSummary>) -> Vec<PackageSummary> {
+    // A provide is a single name; -Ss also takes several terms and regexes
+    let single_name = !q.contains(char::is_whitespace);
+    if !single_name || items.iter().any(|p| p.id.name == q) {
+        return items;
+    }
+    let mut found = Vec::new();
+    for p in providers(q, items.is_empty()) {
+        match items.iter_mut().find(|i| i.id == p.id) {
+            Some(listed) => listed.provides_match = p.provides_match,
+            None => found.push(p),
+        }
+    }
+    found.append(&mut items);
+    found
+}
+
+/// Sync packages whose provides include `name`. expac lists those for every package
+/// quickly; without it, only a `thorough` lookup reads the whole `pacman -Si` dump, which
+/// is slow enough to keep for searches that found nothing else.
+fn providers(name: &str, thorough: bool) -> Vec<PackageSummary> {
+    // (repo, name, version, description, provides with any "=version" cut)
+    let records: Vec<(String, String, String, String, Vec<String>)> = if on_path("expac") {
+        let Ok(out) = Command::new("expac")
+            .args(["-S", "-l", " ", "%r\t%n\t%v\t%P\t%d"])
+            .output()
+        else {
+            return vec![];
+        };
+        String::from_utf8_lossy(&out.stdout)
+            .lines()
+            .filter_map(|l| {
+                let mut cols = l.splitn(5, '\t');
+                let (repo, pkg, version, provides) =
+                    (cols.next()?, cols.next()?, cols.next()?, cols.next()?);
+                Some((
+                    repo.to_string(),
+                    pkg.to_string(),
+                    version.to_string(),
+                    cols.next().unwrap_or("").to_string(),
+                    provides
+                        .split_whitespace()
+                        .map(|p| p.split('=').next().unwrap_or(p).to_string())
+                        .collect(),
+                ))
+            })
+            .collect()
+    } else if thorough {
+        let Ok(out) = Command::new("pacman").arg("-Si").output() else {
+            return vec![];
+        };
+        String::from_utf8_lossy(&out.stdout)
+            .split("\n\n")
+            .filter_map(|rec| {
+                let field = |key: &str| {
+                    rec.lines()
+                        .find_map(|l| l.strip_prefix(key))
+                        .map(|v| v.trim().to_string())
+                };
+                // Long lists wrap onto indented lines
+                let provides: Vec<&str> = rec
+                    .lines()
+                    .skip_while(|l| !l.starts_with("Provides        :"))
+                    .enumerate()
+                    .take_while(|(i, l)| *i == 0 || l.starts_with(' '))
+                    .flat_map(|(_, l)| l.trim_start_matches("Provides        :").split_whitespace())
+                    .filter(|p| *p != "None")
+                    .collect();
+                Some((
+                    field("Repository      :")?,
+                    field("Name            :")?,
+                    field("Version         :")?,
+                    field("Description     :").unwrap_or_default(),
+                    provides
+                        .into_iter()
+                        .map(|p| p.split('=').next().unwrap_or(p).to_string())
+                        .collect(),
+                ))
+            })
+            .collect()
+    } else {
+        return vec![];
+    };
+    let installed = local_versions();
+    records
+        .into_iter()
+        .filter(|(_, pkg, _, _, provides)| pkg != name && provides.iter().any(|p| p == name))
+        .map(|(repo, pkg, version, description, _)| PackageSummary {
+            installed: installed.contains_key(&pkg),
+            id: PackageId {
+                name: pkg,
+                source: Source::Repo,
+            },
+            version,
+            description,
+            popular: None,
+            last_updated: None,
+            variant: AurVariant::Regular,
+            download_size: None,
+            installed_version: None,
+            out_of_date: None,
+            group_members: None,
+            repo: Some(repo),
+            provides_match: Some(name.to_string()),
+        })
+        .collect()
+}
+
+fn group_summary(name: &str, members: usize) -> PackageSummary {
+    PackageSummary {
+        id: PackageId {
+            name: name.to_string(),
+            source: Source::Repo,
+        },
+        version: String::new(),
+        description: format!("Package group of {members} packages"),
+        installed: group_members(name, true).len() == members,
+        popular: None,
+        last_updated: None,
+        variant: AurVariant::Regular,
+        download_size: None,
+        installed_version: None,
+        out_of_date: None,
+        group_members: Some(members),
+        provides_match: None,
+        repo: None,
+    }
+}
+
 // ---------- parsing for -Ss ----------
 fn parse_pacman_search(out: &str) -> Vec<PackageSummary> {
     let re_head =
@@ -123,6 +497,7 @@ fn parse_pacman_search(out: &str) -> Vec<PackageSummary> {
         if let Some(c) = re_head.captures(line) {
             let name = c["name"].to_string();
             let ver = c["ver"].to_string();
+            let repo = c["repo"].to_string();
             let installed = re_inst.is_match(line);
             last = Some(PackageSummary {
                 id: PackageId {
@@ -134,6 +509,13 @@ fn parse_pacman_search(out: &str) -> Vec<PackageSummary> {
                 installed,
                 popular: None,
                 last_updated: None,
+                variant: AurVariant::Regular,
+                download_size: None,
+                installed_version: None,
+                out_of_date: None,
+                group_members: None,
+                provides_match: None,
+                repo: Some(repo),
             });
         } else if line.starts_with(' ') || line.starts_with('\t') {
             if let Some(mut s) = last.take() {
@@ -148,7 +530,53 @@ fn parse_pacman_search(out: &str) -> Vec<PackageSummary> {
     res
 }
 
+// ---------- parsing for -Qs ----------
+/// "local/name version (groups)" headers, each followed by an indented description.
+fn parse_pacman_query(out: &str) -> Vec<PackageSummary> {
+    let re_head = Regex::new(r"^local/(?P<name>\S+)\s+(?P<ver>\S+)").unwrap();
+    let mut res: Vec<PackageSummary> = Vec::new();
+    for line in out.lines() {
+        if let Some(c) = re_head.captures(line) {
+            res.push(PackageSummary {
+                id: PackageId {
+                    name: c["name"].to_string(),
+                    source: Source::Repo,
+                },
+                version: c["ver"].to_string(),
+                description: String::new(),
+                installed: true,
+                popular: None,
+                last_updated: None,
+                variant: AurVariant::Regular,
+                download_size: None,
+                installed_version: None,
+                out_of_date: None,
+                group_members: None,
+                provides_match: None,
+                repo: None,
+            });
+        } else if let Some(last) = res.last_mut().filter(|_| line.starts_with([' ', '\t'])) {
+            last.description = line.trim().to_string();
+        }
+    }
+    res
+}
+
 // ---------- parsing for -Si ----------
+/// The record for `name` in -Si output, which has one per repo carrying it. Repos are listed
+/// in pacman.conf order, so the first exact match is the one pacman would install.
+fn stanza<'a>(out: &'a str, name: &str) -> &'a str {
+    let mut records = out.split("\n\n").filter(|rec| !rec.trim().is_empty());
+    let first = records.clone().next().unwrap_or("");
+    records
+        .find(|rec| {
+            rec.lines()
+                .find_map(|l| l.strip_prefix("Name            :"))
+                .is_some_and(|n| n.trim() == name)
+        })
+        .unwrap_or(first)
+}
+
 fn parse_pacman_details(out: &str, mut summary: PackageSummary) -> PackageDetails {
     let mut depends = Vec::new();
     let mut opt_depends = Vec::new();
@@ -156,23 +584,55 @@ fn parse_pacman_details(out: &str, mut summary: PackageSummary) -> PackageDetail
     let mut size_install = None;
     let mut size_download = None;
     let mut maintainer = None;
+    let mut architecture = None;
+    let mut conflicts = Vec::new();
+    let mut replaces = Vec::new();
+    let mut provides = Vec::new();
+    let mut required_by = Vec::new();
+    let list = |v: &str| -> Vec<String> {
+        if v.trim() == "None" {
+            vec![]
+        } else {
+            v.split_whitespace().map(str::to_string).collect()
+        }
+    };
+    // "name: reason [installed]"; only the name is kept
+    let opt_name = |v: &str| -> Option<String> {
+        let name = v.split(':').next().unwrap_or("").trim();
+        (!name.is_empty() && name != "None").then(|| name.to_string())
+    };
+    // pacman puts each optional dep after the first on its own indented line
+    let mut in_opt_depends = false;
 
     for line in out.lines().map(|l| l.trim_end()) {
+        if in_opt_depends && line.starts_with([' ', '\t']) {
+            opt_depends.extend(opt_name(line));
+            continue;
+        }
+        in_opt_depends = false;
         if let Some(v) = line.strip_prefix("Depends On      :") {
             if v.trim() != "None" {
                 depends = v.split_whitespace().map(|s| s.trim().to_string()).collect();
             }
         } else if let Some(v) = line.strip_prefix("Optional Deps   :") {
-            let name = v.split(':').next().unwrap_or("").trim();
-            if !name.is_empty() {
-                opt_depends.push(name.to_string());
-            }
+            opt_depends.extend(opt_name(v));
+            in_opt_depends = true;
         } else if let Some(v) = line.strip_prefix("URL             :") {
             homepage = Some(v.trim().to_string());
         } else if let Some(v) = line.strip_prefix("Installed Size  :") {
-            size_install = Some(parse_size(v.trim()));
+            size_install = parse_size(v);
         } else if let Some(v) = line.strip_prefix("Download Size   :") {
-            size_download = Some(parse_size(v.trim()));
+            size_download = parse_size(v);
+        } else if let Some(v) = line.strip_prefix("Architecture    :") {
+            architecture = Some(v.trim().to_string());
+        } else if let Some(v) = line.strip_prefix("Conflicts With  :") {
+            conflicts = list(v);
+        } else if let Some(v) = line.strip_prefix("Replaces        :") {
+            replaces = list(v);
+        } else if let Some(v) = line.strip_prefix("Provides        :") {
+            provides = list(v);
+        } else if let Some(v) = line.strip_prefix("Required By     :") {
+            required_by = list(v);
         } else if let Some(v) = line.strip_prefix("Packager        :") {
             maintainer = Some(v.trim().to_string());
         } else if let Some(v) = line.strip_prefix("Description     :") {
@@ -183,6 +643,10 @@ fn parse_pacman_details(out: &str, mut summary: PackageSummary) -> PackageDetail
             if summary.version.is_empty() {
                 summary.version = v.trim().to_string();
             }
+        } else if let Some(v) = line.strip_prefix("Repository      :")
+            && summary.repo.is_none()
+        {
+            summary.repo = Some(v.trim().to_string());
         }
     }
 
@@ -194,104 +658,592 @@ fn parse_pacman_details(out: &str, mut summary: PackageSummary) -> PackageDetail
         maintainer,
         size_install,
         size_download,
+        architecture,
+        conflicts,
+        installed_conflicts: vec![],
+        replaces,
+        provides,
+        required_by,
     }
 }
 
-fn parse_size(s: &str) -> u64 {
-    let mut it = s.split_whitespace();
-    let n: f64 = it.next().unwrap_or("0").parse().unwrap_or(0.0);
-    match it.next().unwrap_or("B") {
-        "KiB" => (n * 1024.0) as u64,
-        "MiB" => (n * 1024.0 * 1024.0) as u64,
-        "GiB" => (n * 1024.0 * 1024.0 * 1024.0) as u64,
-        _ => n as u64,
+// ---------- parsing for transaction hooks ----------
+/// Collects the hooks from the ":: Running pre/post-transaction hooks..." sections. Hooks show
+/// up as "(1/3) Updating initramfs..." (or "running 'x.hook'..." with --debug); any warning or
+/// error lines until the next hook are attributed to the one before them.
+fn parse_hooks(lines: &[String]) -> Vec<HookRun> {
+    let re_hook = Regex::new(r"^\(\s*\d+/\d+\)\s+(?P<name>.+?)\s*$").unwrap();
+    let re_debug = Regex::new(r"running '(?P<name>[^']+)'").unwrap();
+    let mut hooks: Vec<HookRun> = Vec::new();
+    let mut in_hooks = false;
+    for line in lines.iter().map(|l| l.trim()) {
+        if line.starts_with("::") {
+            in_hooks = line.contains("transaction hooks");
+            continue;
+        }
+        if !in_hooks {
+            continue;
+        }
+        let name = re_hook
+            .captures(line)
+            .or_else(|| re_debug.captures(line))
+            .map(|c| c["name"].trim_end_matches('.').to_string());
+        if let Some(name) = name {
+            hooks.push(HookRun {
+                name,
+                outcome: HookOutcome::Ok,
+                messages: Vec::new(),
+            });
+            continue;
+        }
+        let Some(hook) = hooks.last_mut() else {
+            continue;
+        };
+        let lower = line.to_lowercase();
+        if lower.starts_with("error") || lower.contains("==> error") {
+            hook.outcome = HookOutcome::Failed;
+            hook.messages.push(line.to_string());
+        } else if lower.contains("warning") {
+            if hook.outcome == HookOutcome::Ok {
+                hook.outcome = HookOutcome::Warning;
+            }
+            hook.messages.push(line.to_string());
+        }
     }
+    hooks
+}
+
+// ---------- parsing for transaction summaries ----------
+/// `pacman -Q` as name -> version.
+fn local_versions() -> HashMap<String, String> {
+    Command::new("pacman")
+        .arg("-Q")
+        .output()
+        .map(|o| {
+            String::from_utf8_lossy(&o.stdout)
+                .lines()
+                .filter_map(|l| l.split_once(' '))
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// "Packages (3) a-1.0-1  b-2:3.1-2" lists name-version-release; the list may wrap onto
+/// following lines up to a blank one. Whether each was installed or upgraded comes from the
+/// local versions `before` the transaction.
+fn parse_transaction(
+    lines: &[String],
+    before: &HashMap<String, String>,
+    removing: bool,
+) -> TransactionSummary {
+    let mut summary = TransactionSummary::default();
+    let mut packages = Vec::new();
+    let mut in_list = false;
+    let mut total = 0i64;
+    let mut net = None;
+    for line in lines.iter().map(|l| l.trim()) {
+        if let Some(rest) = line.strip_prefix("Packages (") {
+            in_list = true;
+            packages.extend(rest.split_whitespace().skip(1).map(str::to_string));
+            continue;
+        }
+        if in_list && !line.is_empty() {
+            packages.extend(line.split_whitespace().map(str::to_string));
+            continue;
+        }
+        in_list = false;
+        if let Some(v) = line.strip_prefix("Total Installed Size:") {
+            total = signed_size(v.trim()).unwrap_or(total);
+        } else if let Some(v) = line.strip_prefix("Total Removed Size:") {
+            total = signed_size(v.trim()).map_or(total, |n| -n);
+        } else if let Some(v) = line.strip_prefix("Net Upgrade Size:") {
+            net = signed_size(v.trim()).or(net);
+        }
+    }
+    // With upgrades in the mix, the net size is what the disk actually sees
+    summary.size_delta = net.unwrap_or(total);
+    for p in packages {
+        let mut parts = p.rsplitn(3, '-');
+        let (Some(rel), Some(ver), Some(name)) = (parts.next(), parts.next(), parts.next()) else {
+            continue;
+        };
+        let version = format!("{ver}-{rel}");
+        match before.get(name) {
+            _ if removing => summary.removed.push((name.to_string(), version)),
+            Some(old) if *old != version => {
+                summary
+                    .upgraded
+                    .push((name.to_string(), old.clone(), version))
+            }
+            // Reinstalled: nothing changed
+            Some(_) => {}
+            None => summary.installed.push((name.to_string(), version)),
+        }
+    }
+    summary
+}
+
+/// `parse_size` that keeps the sign of "-1.50 MiB".
+fn signed_size(s: &str) -> Option<i64> {
+    match s.strip_prefix('-') {
+        Some(abs) => parse_size(abs).map(|n| -(n as i64)),
+        None => parse_size(s).map(|n| n as i64),
+    }
+}
+
+// ---------- parsing for provider selection ----------
+/// Finds each ":: There are N providers available for dep:" prompt and the provider numbered 1,
+/// which is what --noconfirm picks. Candidates are listed per repository as "1) a  2) b".
+fn parse_providers(lines: &[String]) -> Vec<(String, String, usize)> {
+    let re_prompt =
+        Regex::new(r"There are (?P<n>\d+) providers available for (?P<dep>\S+?):?\s*$").unwrap();
+    let re_first = Regex::new(r"(?:^|\s)1\)\s+(?P<name>\S+)").unwrap();
+    let mut out = Vec::new();
+    let mut pending: Option<(String, usize)> = None;
+    for line in lines.iter().map(|l| l.trim()) {
+        if let Some(c) = re_prompt.captures(line) {
+            pending = Some((c["dep"].to_string(), c["n"].parse().unwrap_or(0)));
+            continue;
+        }
+        let Some(c) = re_first.captures(line) else {
+            continue;
+        };
+        if let Some((dep, n)) = pending.take() {
+            out.push((dep, c["name"].to_string(), n));
+        }
+    }
+    out
+}
+
+/// Host of the first remote URL in `pacman -Sp` output; local cache hits are file:// URLs.
+fn first_mirror_host(out: &str) -> Option<String> {
+    out.lines().find_map(|l| {
+        let rest = l
+            .trim()
+            .strip_prefix("https://")
+            .or_else(|| l.trim().strip_prefix("http://"))
+            .or_else(|| l.trim().strip_prefix("ftp://"))?;
+        rest.split('/')
+            .next()
+            .filter(|h| !h.is_empty())
+            .map(str::to_string)
+    })
+}
+
+/// Repo packages from `skip`, comma-separated for --ignore.
+fn ignore_list(skip: &[PackageId]) -> String {
+    skip.iter()
+        .filter(|id| id.source == Source::Repo)
+        .map(|id| id.name.as_str())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// `pacman -Si`/`-Qi` (per `op`) for several packages in one call, keyed by name; from
+/// the dbs under `dbpath` if given.
+fn info_records(
+    op: &str,
+    names: &[&str],
+    dbpath: Option<&std::path::Path>,
+) -> HashMap<String, PackageDetails> {
+    if names.is_empty() {
+        return HashMap::new();
+    }
+    let mut cmd = Command::new("pacman");
+    if let Some(db) = dbpath {
+        cmd.arg("--dbpath").arg(db);
+    }
+    let Ok(out) = cmd.arg(op).args(names).output() else {
+        return HashMap::new();
+    };
+    String::from_utf8_lossy(&out.stdout)
+        .split("\n\n")
+        .filter_map(|rec| {
+            let name = rec
+                .lines()
+                .find_map(|l| l.strip_prefix("Name            :"))?
+                .trim()
+                .to_string();
+            let summary = PackageSummary {
+                id: PackageId {
+                    name: name.clone(),
+                    source: Source::Repo,
+                },
+                version: String::new(),
+                description: String::new(),
+                installed: false,
+                popular: None,
+                last_updated: None,
+                variant: AurVariant::Regular,
+                download_size: None,
+                installed_version: None,
+                out_of_date: None,
+                group_members: None,
+                provides_match: None,
+                repo: None,
+            };
+            Some((name, parse_pacman_details(rec, summary)))
+        })
+        // The same name in several repos: keep the first, highest-priority one
+        .fold(HashMap::new(), |mut map, (name, details)| {
+            map.entry(name).or_insert(details);
+            map
+        })
+}
+
+// ---------- parsing for transaction progress ----------
+/// Terminal width pacman is told it has. Off a tty it draws no progress bars unless
+/// `COLUMNS` is set.
+const BAR_COLUMNS: &str = "80";
+
+/// Progress markers in pacman's transaction output: "( 2/5) installing foo" steps, a
+/// trailing "45%" from a progress bar, and what a download bar has transferred so far
+/// ("12.3 MiB", not the "5.0 MiB/s" rate). Several bars at once are drawn with cursor
+/// movement escapes, which `clean` drops.
+struct ProgressParser {
+    step: Regex,
+    percent: Regex,
+    size: Regex,
+    escape: Regex,
+}
+
+impl ProgressParser {
+    fn new() -> Self {
+        Self {
+            step: Regex::new(r"^\(\s*(?P<n>\d+)/(?P<m>\d+)\)").unwrap(),
+            percent: Regex::new(r"(?P<p>\d{1,3})%\s*$").unwrap(),
+            size: Regex::new(r"(?P<size>\d+(?:[.,]\d+)?\s*(?:B|KiB|MiB|GiB|TiB))(?:\s|$)").unwrap(),
+            escape: Regex::new(r"\x1b\[[0-9;?]*[A-Za-z]").unwrap(),
+        }
+    }
+
+    /// `line` without terminal escapes.
+    fn clean(&self, line: &str) -> String {
+        self.escape.replace_all(line, "").into_owned()
+    }
+
+    /// How far the line's progress bar is, if it has one.
+    fn bar(&self, line: &str) -> Option<f32> {
+        self.percent
+            .captures(line.trim())
+            .and_then(|c| c["p"].parse::<f32>().ok())
+            .map(|p| (p / 100.0).min(1.0))
+    }
+
+    /// Overall fraction done and (bytes done, file size), where the line says. A step
+    /// counts as done once its own bar reaches 100%; a file's size is worked out from
+    /// its bar.
+    fn parse(&self, line: &str) -> (Option<f32>, Option<(u64, u64)>) {
+        let line = line.trim();
+        let pct = self.bar(line);
+        let bytes = pct.zip(self.size.captures(line)).and_then(|(p, c)| {
+            let done = parse_size(&c["size"])?;
+            let total = if p > 0.0 { (done as f32 / p) as u64 } else { 0 };
+            Some((done, total.max(done)))
+        });
+        let step = self.step.captures(line).and_then(|c| {
+            let n = c["n"].parse::<f32>().ok()?;
+            let m = c["m"].parse::<f32>().ok().filter(|m| *m > 0.0)?;
+            Some((n, m))
+        });
+        let percent = match step {
+            Some((n, m)) => Some(((n - 1.0 + pct.unwrap_or(0.0)) / m).clamp(0.0, 1.0)),
+            None => pct,
+        };
+        (percent, bytes)
+    }
+}
+
+/// Bytes downloaded across a whole transaction, against pacman's "Total Download Size:".
+#[derive(Default)]
+struct DownloadTally {
+    total: Option<u64>,
+    /// Bytes so far per file, keyed by the bar's first word (the file name).
+    files: HashMap<String, u64>,
+}
+
+impl DownloadTally {
+    /// Folds in one output line and its per-file `bytes`, giving the transaction-wide
+    /// (done, total) once the total is known.
+    fn update(&mut self, line: &str, bytes: Option<(u64, u64)>) -> Option<(u64, u64)> {
+        let line = line.trim();
+        if let Some(v) = line.strip_prefix("Total Download Size:") {
+            self.total = parse_size(v);
+            self.files.clear();
+            return self.total.map(|t| (0, t));
+        }
+        let total = self.total?;
+        let (done, _) = bytes?;
+        // pacman's own aggregate bar for parallel downloads, "Total ( 2/5)"
+        if line.starts_with("Total (") {
+            return Some((done.min(total), total));
+        }
+        let file = line.split_whitespace().next()?;
+        self.files.insert(file.to_string(), done);
+        Some((self.files.values().sum::<u64>().min(total), total))
+    }
+}
+
+/// Reads pacman's human-readable sizes: "123.45 MiB", "2TiB", or "1,5 GiB" under locales
+/// with a decimal comma. `None` for anything else.
+fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let end = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+        .unwrap_or(s.len());
+    let (n, unit) = s.split_at(end);
+    let n: f64 = n.replace(',', ".").parse().ok()?;
+    let scale: u64 = match unit.trim_start() {
+        "" | "B" => 1,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        "TiB" => 1 << 40,
+        _ => return None,
+    };
+    Some((n * scale as f64) as u64)
 }
 
 impl PacmanCli {
     fn run_stream(
         &self,
-        mut cmd: Command,
+        cmd: Command,
         sink: &ProgressSink,
         cancel: &CancelToken,
         stage: Stage,
     ) -> Result<i32> {
-        let mut child = cmd
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .map_err(|e| Error::Internal(format!("spawn: {e}")))?;
-        let out = child.stdout.take().unwrap();
-        let err = child.stderr.take().unwrap();
+        Ok(self.run_stream_lines(cmd, sink, cancel, stage)?.0)
+    }
+
+    /// pacman as root, with the width it needs to draw progress bars.
+    fn pacman_command(&self) -> Command {
+        self.privilege
+            .command_env("pacman", &[("COLUMNS", BAR_COLUMNS)])
+    }
 
+    /// `run_stream` that also returns everything the command printed.
+    fn run_stream_lines(
+        &self,
+        mut cmd: Command,
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+        stage: Stage,
+    ) -> Result<(i32, Vec<String>)> {
+        if self.verbosity == Verbosity::Debug {
+            cmd.arg("--debug");
+            sink.send(Progress {
+                job_id: 0,
+                stage: stage.clone(),
+                percent: None,
+                bytes: None,
+                log: Some(format!("$ {cmd:?}")),
+                warning: false,
+            })
+            .ok();
+        }
+        if let Some(p) = Privilege::prompt_progress(&cmd) {
+            sink.send(p).ok();
+        }
         let jid = 0u64;
         let tx1 = sink.clone();
         let tx2 = sink.clone();
 
         let stage_out = stage.clone();
-        let stage_err = stage;
+        let stage_err = stage.clone();
+
+        // Both streams in arrival order, for post-processing once the command exits.
+        let transcript = Arc::new(Mutex::new(Vec::<String>::new()));
+        let lines_out = transcript.clone();
+        let lines_err = transcript.clone();
 
-        let t1 = std::thread::spawn(move || {
-            for l in BufReader::new(out).lines().flatten() {
-                let _ = tx1.send(Progress {
+        // A failed send means the UI is gone; keep draining anyway so the command isn't
+        // killed by a closed pipe mid-transaction.
+        let parser = ProgressParser::new();
+        let mut tally = DownloadTally::default();
+        let mut gone = false;
+        let on_out = move |l: String| {
+            let l = parser.clean(&l);
+            // A bar redrawn on its way to 100% only moves the progress, not the log
+            let redraw = parser.bar(&l).is_some_and(|p| p < 1.0);
+            if !redraw {
+                lines_out.lock().unwrap().push(l.clone());
+            }
+            if gone {
+                return;
+            }
+            let (percent, bytes) = parser.parse(&l);
+            let tallied = tally.update(&l, bytes);
+            let stage = if l.contains("downloading") || bytes.is_some() {
+                Stage::Downloading
+            } else {
+                stage_out.clone()
+            };
+            let bytes = tallied.or(bytes);
+            gone = tx1
+                .send(Progress {
                     job_id: jid,
-                    stage: stage_out.clone(),
-                    percent: None,
-                    bytes: None,
-                    log: Some(l),
+                    stage,
+                    percent,
+                    bytes,
+                    log: (!redraw).then_some(l),
                     warning: false,
-                });
+                })
+                .is_err();
+        };
+        let mut gone = false;
+        let on_err = move |l: String| {
+            lines_err.lock().unwrap().push(l.clone());
+            if gone {
+                return;
             }
-        });
-
-        let t2 = std::thread::spawn(move || {
-            for l in BufReader::new(err).lines().flatten() {
-                let _ = tx2.send(Progress {
+            gone = tx2
+                .send(Progress {
                     job_id: jid,
                     stage: stage_err.clone(),
                     percent: None,
                     bytes: None,
                     log: Some(l),
                     warning: true,
-                });
-            }
-        });
+                })
+                .is_err();
+        };
 
-        loop {
-            match child.try_wait() {
-                Ok(Some(status)) => {
-                    let _ = t1.join();
-                    let _ = t2.join();
-                    return Ok(status.code().unwrap_or(-1));
-                }
-                Ok(None) => {
-                    if cancel.is_cancelled() {
-                        #[cfg(unix)]
-                        {
-                            let _ = nix::sys::signal::kill(
-                                nix::unistd::Pid::from_raw(child.id() as i32),
-                                nix::sys::signal::Signal::SIGTERM,
-                            );
-                        }
-                        let _ = child.wait();
-                        let _ = t1.join();
-                        let _ = t2.join();
-                        return Err(Error::Cancelled);
-                    }
-                    std::thread::sleep(std::time::Duration::from_millis(16));
-                }
-                Err(e) => return Err(Error::Internal(format!("wait: {e}"))),
-            }
+        let (status, cancelled) = run_piped(cmd, sink, &stage, cancel, on_out, on_err)?;
+        // A transaction that completed despite the cancel is reported as done
+        if cancelled && !status.success() {
+            return Err(Error::Cancelled);
+        }
+        let transcript = transcript.lock().unwrap();
+        // The lock file is left alone: it may belong to a pacman still running
+        if !status.success()
+            && transcript
+                .iter()
+                .any(|l| l.contains("unable to lock database"))
+        {
+            return Err(Error::DbLocked);
+        }
+        self.report_providers(&transcript, sink);
+        self.report_hooks(&transcript, sink);
+        Ok((status.code().unwrap_or(-1), transcript.clone()))
+    }
+}
+
+impl PacmanCli {
+    /// Asks pacman (unprivileged, via --print) where `args` would download from and
+    /// reports the mirror host.
+    fn report_mirror(&self, args: &[&str], sink: &ProgressSink) {
+        let Some(events) = &self.events else {
+            return;
+        };
+        let Ok(out) = Command::new("pacman").args(args).arg("--print").output() else {
+            return;
+        };
+        if let Some(host) = first_mirror_host(&String::from_utf8_lossy(&out.stdout)) {
+            sink.send(Progress {
+                job_id: 0,
+                stage: Stage::Downloading,
+                percent: None,
+                bytes: None,
+                log: Some(format!("repo: downloading from {host}")),
+                warning: false,
+            })
+            .ok();
+            let _ = events.send(Event::DownloadMirror { host });
+        }
+    }
+
+    /// Runs a privileged install/remove/upgrade (`what` names it in errors) and reports
+    /// what it changed.
+    fn run_transaction(
+        &self,
+        what: &str,
+        cmd: Command,
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+        stage: Stage,
+        target: Option<&str>,
+    ) -> Result<()> {
+        let removing = matches!(stage, Stage::Removing);
+        let before = local_versions();
+        let (code, transcript) = self.run_stream_lines(cmd, sink, cancel, stage)?;
+        self.privilege.check(what, code)?;
+        if let Some(events) = &self.events {
+            let mut summary = parse_transaction(&transcript, &before, removing);
+            summary.target = target.map(str::to_string);
+            let _ = events.send(Event::TransactionSummary {
+                summary: Box::new(summary),
+            });
+        }
+        Ok(())
+    }
+
+    /// --noconfirm silently takes the default provider; say which one it was.
+    fn report_providers(&self, transcript: &[String], sink: &ProgressSink) {
+        for (dep, chosen, n) in parse_providers(transcript) {
+            sink.send(Progress {
+                job_id: 0,
+                stage: Stage::Resolving,
+                percent: None,
+                bytes: None,
+                log: Some(format!(
+                    "repo: {n} providers for {dep}; --noconfirm chose {chosen}. \
+                     Run it in a terminal to pick another"
+                )),
+                warning: true,
+            })
+            .ok();
+        }
+    }
+
+    fn report_hooks(&self, transcript: &[String], sink: &ProgressSink) {
+        let hooks = parse_hooks(transcript);
+        if hooks.is_empty() {
+            return;
+        }
+        for h in hooks.iter().filter(|h| h.outcome == HookOutcome::Failed) {
+            sink.send(Progress {
+                job_id: 0,
+                stage: Stage::Verifying,
+                percent: None,
+                bytes: None,
+                log: Some(format!(
+                    "repo: hook '{}' failed; check the log before rebooting",
+                    h.name
+                )),
+                warning: true,
+            })
+            .ok();
+        }
+        if let Some(events) = &self.events {
+            let _ = events.send(Event::HookSummary { hooks });
         }
     }
 }
 
 impl PackageBackend for PacmanCli {
+    /// Only `-Sy`: a refresh never upgrades behind the user's back. Installing from
+    /// freshly synced databases without upgrading is a partial upgrade, so the log says
+    /// so and the UI holds repo installs back until "Upgrade all" (`-Syu`) has run.
     fn refresh(&self, sink: &ProgressSink, cancel: &CancelToken) -> Result<()> {
-        let mut cmd = Command::new("pacman");
+        let mut cmd = self.pacman_command();
         cmd.args(["-Sy", "--noconfirm"]);
         let code = self.run_stream(cmd, sink, cancel, Stage::Refreshing)?;
         if code == 0 {
+            sink.send(Progress {
+                job_id: 0,
+                stage: Stage::Refreshing,
+                percent: None,
+                bytes: None,
+                log: Some(
+                    "repo: databases synced without upgrading (pacman -Sy). While upgrades \
+                     are pending, installing would be a partial upgrade, which Arch doesn't \
+                     support; run Upgrade all (pacman -Syu) first."
+                        .into(),
+                ),
+                warning: true,
+            })
+            .ok();
             Ok(())
         } else {
             Err(Error::Alpm(format!("pacman -Sy exit {code}")))
@@ -302,8 +1254,11 @@ impl PackageBackend for PacmanCli {
         &self,
         q: &str,
         sink: &ProgressSink,
-        _cancel: &CancelToken,
+        cancel: &CancelToken,
     ) -> Result<Vec<PackageSummary>> {
+        if cancel.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
         let q = q.trim();
         if q.len() < 2 {
             sink.send(Progress {
@@ -355,7 +1310,10 @@ impl PackageBackend for PacmanCli {
 
         if out.status.success() {
             // Happy path
-            return Ok(parse_pacman_search(&stdout));
+            return Ok(with_group(
+                q,
+                with_providers(q, parse_pacman_search(&stdout)),
+            ));
         }
 
         // 2) Status != 0. If we still got lines on stdout, parse them.
@@ -372,7 +1330,31 @@ impl PackageBackend for PacmanCli {
                 warning: true,
             })
             .ok();
-            return Ok(parse_pacman_search(&stdout));
+            return Ok(with_group(q, parse_pacman_search(&stdout)));
+        }
+
+        // No sync dbs at all (never refreshed): -Ssq can't help either, so ask for a refresh.
+        // A single missing one ("database file for 'foo' does not exist"), e.g. a newly
+        // added repo, is only a db error below: the others still searched fine.
+        if !sync_dbs_present() {
+            sink.send(Progress {
+                job_id: 0,
+                stage: Stage::Searching,
+                percent: None,
+                bytes: None,
+                log: Some(
+                    "repo: package databases have never been synced. Run Refresh to download them."
+                        .into(),
+                ),
+                warning: true,
+            })
+            .ok();
+            return Err(Error::SyncDbMissing);
+        }
+
+        // Nothing printed at all is pacman's "no matches", though a group may still be named so
+        if stderr.trim().is_empty() {
+            return Ok(with_group(q, with_providers(q, vec![])));
         }
 
         // stderr-only failure: explain and fall back to -Ssq
@@ -403,6 +1385,27 @@ impl PackageBackend for PacmanCli {
         self.search_fallback_names(q, sink)
     }
 
+    fn search_installed(
+        &self,
+        q: &str,
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<Vec<PackageSummary>> {
+        // -n: native packages only; foreign ones belong to the AUR backend
+        let out = Command::new("pacman")
+            .args(["-Qns", "--color", "never", q])
+            .output()
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        // Exit 1 with no output just means no match
+        if !out.status.success() && !out.stderr.is_empty() {
+            return Err(Error::Alpm(format!(
+                "pacman -Qs: {}",
+                String::from_utf8_lossy(&out.stderr).trim()
+            )));
+        }
+        Ok(parse_pacman_query(&String::from_utf8_lossy(&out.stdout)))
+    }
+
     fn details(
         &self,
         id: &PackageId,
@@ -414,6 +1417,13 @@ impl PackageBackend for PacmanCli {
             .output()
             .map_err(|e| Error::Internal(e.to_string()))?;
         if !out.status.success() {
+            // Group rows from search have no -Si; list their members as what they pull in
+            let members = group_members(&id.name, false);
+            if !members.is_empty() {
+                let mut details = parse_pacman_details("", group_summary(&id.name, members.len()));
+                details.depends = members;
+                return Ok(details);
+            }
             return Err(Error::Alpm("pacman -Si failed".into()));
         }
         let s = String::from_utf8_lossy(&out.stdout);
@@ -424,30 +1434,363 @@ impl PackageBackend for PacmanCli {
             installed: false,
             popular: None,
             last_updated: None,
+            variant: AurVariant::Regular,
+            download_size: None,
+            installed_version: None,
+            out_of_date: None,
+            group_members: None,
+            provides_match: None,
+            repo: None,
         };
-        Ok(parse_pacman_details(&s, summary))
+        let mut details = parse_pacman_details(stanza(&s, &id.name), summary.clone());
+        // Only the local db knows what depends on an installed package
+        if let Ok(out) = Command::new("pacman").args(["-Qi", &id.name]).output()
+            && out.status.success()
+        {
+            let local = parse_pacman_details(&String::from_utf8_lossy(&out.stdout), summary);
+            details.required_by = local.required_by;
+        }
+        let local = local_versions();
+        details.installed_conflicts = details
+            .conflicts
+            .iter()
+            // Entries may carry a version constraint, e.g. "foo<2"
+            .filter(|c| local.contains_key(c.split(['<', '>', '=']).next().unwrap_or(c)))
+            .cloned()
+            .collect();
+        Ok(details)
     }
 
-    fn install(&self, id: &PackageId, sink: &ProgressSink, cancel: &CancelToken) -> Result<()> {
-        let mut cmd = Command::new("pkexec");
-        cmd.args(["pacman", "-S", "--noconfirm", "--needed", &id.name]);
-        let code = self.run_stream(cmd, sink, cancel, Stage::Installing)?;
+    fn details_batch(
+        &self,
+        ids: &[PackageId],
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<Vec<PackageDetails>> {
+        let names: Vec<&str> = ids.iter().map(|id| id.name.as_str()).collect();
+        Ok(info_records("-Si", &names, None).into_values().collect())
+    }
+
+    fn search_file(
+        &self,
+        path: &str,
+        sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<Vec<PackageSummary>> {
+        if !files_dbs_present() {
+            return Err(Error::FilesDbMissing);
+        }
+        if files_db_stale() {
+            sink.send(Progress {
+                job_id: 0,
+                stage: Stage::Searching,
+                percent: None,
+                bytes: None,
+                log: Some("repo: files database is older than the sync databases".into()),
+                warning: true,
+            })
+            .ok();
+            if let Some(events) = &self.events {
+                let _ = events.send(Event::FilesDbNeedsSync);
+            }
+        }
+        sink.send(Progress {
+            job_id: 0,
+            stage: Stage::Searching,
+            percent: None,
+            bytes: None,
+            log: Some(format!("repo file search: {path}")),
+            warning: false,
+        })
+        .ok();
+
+        let out = Command::new("pacman")
+            .args(["-F", "--machinereadable", path])
+            .output()
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        // Exit 1 with no output just means nothing provides the path
+        if !out.status.success() && !out.stderr.is_empty() {
+            return Err(Error::Alpm(format!(
+                "pacman -F: {}",
+                String::from_utf8_lossy(&out.stderr).trim()
+            )));
+        }
+        Ok(parse_pacman_file_search(&String::from_utf8_lossy(
+            &out.stdout,
+        )))
+    }
+
+    fn refresh_files(&self, sink: &ProgressSink, cancel: &CancelToken) -> Result<()> {
+        let mut cmd = self.pacman_command();
+        cmd.args(["-Fy", "--noconfirm"]);
+        let code = self.run_stream(cmd, sink, cancel, Stage::Refreshing)?;
         if code == 0 {
             Ok(())
         } else {
-            Err(Error::Priv(format!("install exit {code}")))
+            Err(Error::Alpm(format!("pacman -Fy exit {code}")))
+        }
+    }
+
+    fn files(
+        &self,
+        id: &PackageId,
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<Vec<PathBuf>> {
+        let mut out = Command::new("pacman")
+            .args(["-Qlq", &id.name])
+            .output()
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        // Not installed: the files database knows what it would install
+        if !out.status.success() {
+            if !files_dbs_present() {
+                return Err(Error::FilesDbMissing);
+            }
+            out = Command::new("pacman")
+                .args(["-Flq", &id.name])
+                .output()
+                .map_err(|e| Error::Internal(e.to_string()))?;
+        }
+        if !out.status.success() {
+            return Err(Error::Alpm("pacman -Ql failed".into()));
         }
+        // Directories end in '/'; only files are interesting. Paths needn't be UTF-8.
+        Ok(out
+            .stdout
+            .split(|b| *b == b'\n')
+            .filter(|l| !l.is_empty() && !l.ends_with(b"/"))
+            .map(|l| PathBuf::from(OsStr::from_bytes(l)))
+            .collect())
+    }
+
+    fn install(&self, id: &PackageId, sink: &ProgressSink, cancel: &CancelToken) -> Result<()> {
+        self.report_mirror(&["-S", &id.name], sink);
+        let mut cmd = self.pacman_command();
+        cmd.args(["-S", "--noconfirm", "--needed", &id.name]);
+        self.run_transaction(
+            "install",
+            cmd,
+            sink,
+            cancel,
+            Stage::Installing,
+            Some(&id.name),
+        )
+    }
+
+    fn install_many(
+        &self,
+        ids: &[PackageId],
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<()> {
+        // One transaction, so one authentication prompt for the lot
+        let names: Vec<&str> = ids.iter().map(|id| id.name.as_str()).collect();
+        let mut args = vec!["-S"];
+        args.extend(&names);
+        self.report_mirror(&args, sink);
+        let mut cmd = self.pacman_command();
+        cmd.args(["-S", "--noconfirm", "--needed"]).args(&names);
+        self.run_transaction("install", cmd, sink, cancel, Stage::Installing, None)
     }
 
     fn remove(&self, id: &PackageId, sink: &ProgressSink, cancel: &CancelToken) -> Result<()> {
-        let mut cmd = Command::new("pkexec");
-        cmd.args(["pacman", "-Rns", "--noconfirm", &id.name]);
-        let code = self.run_stream(cmd, sink, cancel, Stage::Removing)?;
-        if code == 0 {
-            Ok(())
-        } else {
-            Err(Error::Priv(format!("remove exit {code}")))
+        let mut cmd = self.pacman_command();
+        cmd.args(["-Rns", "--noconfirm", &id.name]);
+        self.run_transaction("remove", cmd, sink, cancel, Stage::Removing, Some(&id.name))
+    }
+
+    fn remove_many(
+        &self,
+        ids: &[PackageId],
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<()> {
+        let mut cmd = self.pacman_command();
+        cmd.args(["-Rns", "--noconfirm"])
+            .args(ids.iter().map(|id| id.name.as_str()));
+        self.run_transaction("remove", cmd, sink, cancel, Stage::Removing, None)
+    }
+
+    fn remove_preview(
+        &self,
+        id: &PackageId,
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<RemovePreview> {
+        // --print resolves the transaction without running it, so it needs no root
+        let out = Command::new("pacman")
+            .args(["-Rns", "--print", "--print-format", "%n", &id.name])
+            .output()
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        RemovePreview::from_pacman(&out)
+    }
+
+    /// `pactree -r` from pacman-contrib, a few levels deep; without it, only the direct
+    /// dependents from `pacman -Qi`.
+    fn who_requires(
+        &self,
+        id: &PackageId,
+        sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<Vec<String>> {
+        match Command::new("pactree")
+            .args(["-r", "-d", "3", &id.name])
+            .output()
+        {
+            Ok(out) if out.status.success() => Ok(String::from_utf8_lossy(&out.stdout)
+                .lines()
+                .skip(1)
+                .map(str::to_string)
+                .collect()),
+            Ok(_) => Err(Error::Alpm(format!("pactree -r {} failed", id.name))),
+            Err(_) => {
+                sink.send(Progress {
+                    job_id: 0,
+                    stage: Stage::Resolving,
+                    percent: None,
+                    bytes: None,
+                    log: Some(
+                        "repo: pactree not found (pacman-contrib); listing direct dependents only"
+                            .into(),
+                    ),
+                    warning: true,
+                })
+                .ok();
+                Ok(info_records("-Qi", &[id.name.as_str()], None)
+                    .remove(&id.name)
+                    .map(|d| d.required_by)
+                    .unwrap_or_default())
+            }
+        }
+    }
+
+    fn plan_clean_cache(
+        &self,
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<CacheCleanPlan> {
+        if !paccache_present() {
+            return Ok(CacheCleanPlan {
+                command: "pacman -Sc".into(),
+                ..Default::default()
+            });
         }
+        // "==> finished dry run: 12 candidates (disk space saved: 345.67 MiB)"
+        let out = Command::new("paccache")
+            .args(["-dvk", CACHE_KEEP])
+            .output()
+            .map_err(|e| Error::Internal(format!("paccache: {e}")))?;
+        let text = String::from_utf8_lossy(&out.stdout);
+        let files = text
+            .lines()
+            .filter(|l| l.starts_with('/'))
+            .filter_map(|l| l.rsplit('/').next().map(str::to_string))
+            .collect();
+        let reclaim = text
+            .split_once("disk space saved:")
+            .and_then(|(_, rest)| parse_size(rest.trim().trim_end_matches(')')));
+        Ok(CacheCleanPlan {
+            command: format!("paccache -rk{CACHE_KEEP}"),
+            files,
+            reclaim,
+        })
+    }
+
+    fn clean_cache(&self, sink: &ProgressSink, cancel: &CancelToken) -> Result<()> {
+        let (what, mut cmd) = if paccache_present() {
+            let mut cmd = self.privilege.command("paccache");
+            cmd.arg(format!("-rk{CACHE_KEEP}"));
+            ("paccache", cmd)
+        } else {
+            let mut cmd = self.pacman_command();
+            cmd.args(["-Sc", "--noconfirm"]);
+            ("pacman -Sc", cmd)
+        };
+        cmd.stdin(Stdio::null());
+        let code = self.run_stream(cmd, sink, cancel, Stage::Cleaning)?;
+        self.privilege.check(what, code)
+    }
+
+    fn cached_versions(
+        &self,
+        id: &PackageId,
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<Vec<String>> {
+        let Some(installed) = local_versions().remove(&id.name) else {
+            return Ok(vec![]);
+        };
+        let mut versions: Vec<String> = cached_packages(&id.name)
+            .into_iter()
+            .map(|(v, _)| v)
+            .filter(|v| vercmp(v, &installed) == std::cmp::Ordering::Less)
+            .collect();
+        versions.sort_by(|a, b| vercmp(b, a));
+        versions.dedup();
+        Ok(versions)
+    }
+
+    fn downgrade(
+        &self,
+        id: &PackageId,
+        version: &str,
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<()> {
+        let Some((_, file)) = cached_packages(&id.name)
+            .into_iter()
+            .find(|(v, _)| v == version)
+        else {
+            return Err(Error::Alpm(format!(
+                "{} {version} is no longer in {PKG_CACHE_DIR}",
+                id.name
+            )));
+        };
+        let mut cmd = self.pacman_command();
+        cmd.args([
+            OsStr::new("-U"),
+            OsStr::new("--noconfirm"),
+            file.as_os_str(),
+        ]);
+        self.run_transaction(
+            "downgrade",
+            cmd,
+            sink,
+            cancel,
+            Stage::Installing,
+            Some(&id.name),
+        )
+    }
+
+    fn orphans(&self, _sink: &ProgressSink, _cancel: &CancelToken) -> Result<Vec<PackageSummary>> {
+        // -n: native packages only; foreign ones belong to the AUR backend
+        let out = Command::new("pacman")
+            .arg("-Qdtn")
+            .output()
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        // Exit 1 with no output just means there are none
+        Ok(String::from_utf8_lossy(&out.stdout)
+            .lines()
+            .filter_map(|l| l.trim().split_once(' '))
+            .map(|(name, version)| PackageSummary {
+                id: PackageId {
+                    name: name.to_string(),
+                    source: Source::Repo,
+                },
+                version: version.to_string(),
+                description: String::new(),
+                installed: true,
+                popular: None,
+                last_updated: None,
+                variant: AurVariant::Regular,
+                download_size: None,
+                installed_version: None,
+                out_of_date: None,
+                group_members: None,
+                provides_match: None,
+                repo: None,
+            })
+            .collect())
     }
 
     fn upgrades(&self, sink: &ProgressSink, _cancel: &CancelToken) -> Result<Vec<PackageSummary>> {
@@ -475,30 +1818,317 @@ impl PackageBackend for PacmanCli {
         }
 
         let stdout = String::from_utf8_lossy(&out.stdout);
-        Ok(Self::parse_upgrades(&stdout))
+        let mut items = Self::parse_upgrades(&stdout);
+        let sizes = Self::upgrade_download_sizes();
+        for p in &mut items {
+            p.download_size = sizes.get(&p.id.name).copied();
+        }
+        Ok(items)
     }
 
     fn upgrade(&self, id: &PackageId, sink: &ProgressSink, cancel: &CancelToken) -> Result<()> {
         // Upgrades a single repo package to the latest available version.
-        let mut cmd = Command::new("pkexec");
-        cmd.args(["pacman", "-S", "--noconfirm", "--needed", &id.name]);
-        let code = self.run_stream(cmd, sink, cancel, Stage::Installing)?;
-        if code == 0 {
-            Ok(())
-        } else {
-            Err(Error::Priv(format!("upgrade exit {code}")))
+        self.report_mirror(&["-S", &id.name], sink);
+        let mut cmd = self.pacman_command();
+        cmd.args(["-S", "--noconfirm", "--needed", &id.name]);
+        self.run_transaction(
+            "upgrade",
+            cmd,
+            sink,
+            cancel,
+            Stage::Installing,
+            Some(&id.name),
+        )
+    }
+
+    fn plan_upgrade_all(
+        &self,
+        skip: &[PackageId],
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<TransactionPlan> {
+        // The real run refreshes first (-Syu), so the plan is worked out on refreshed dbs too
+        let ignore = ignore_list(skip);
+        let dbpath = fresh_dbpath(cancel);
+        if cancel.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        let mut cmd = Command::new("pacman");
+        cmd.args(["-Sup", "--print-format", "%n %v %s"]);
+        if !ignore.is_empty() {
+            cmd.args(["--ignore", &ignore]);
+        }
+        match &dbpath {
+            Some(db) => {
+                cmd.arg("--dbpath").arg(db.path());
+            }
+            None => {
+                sink.send(Progress {
+                    job_id: 0,
+                    stage: Stage::Resolving,
+                    percent: None,
+                    bytes: None,
+                    log: Some(
+                        "repo: couldn't refresh a copy of the databases (is fakeroot \
+                         installed?); the plan uses the last sync and the upgrade may differ"
+                            .into(),
+                    ),
+                    warning: true,
+                })
+                .ok();
+            }
+        }
+        let out = cmd.output().map_err(|e| Error::Internal(e.to_string()))?;
+        if !out.status.success() {
+            return Err(Error::Alpm(format!(
+                "pacman -Sup: {}",
+                String::from_utf8_lossy(&out.stderr).trim()
+            )));
+        }
+        let local = local_versions();
+
+        let mut plan = TransactionPlan::default();
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        for line in stdout.lines() {
+            let mut cols = line.split_whitespace();
+            let (Some(name), Some(new), Some(size)) = (cols.next(), cols.next(), cols.next())
+            else {
+                continue;
+            };
+            plan.download_size += size.parse::<u64>().unwrap_or(0);
+            match local.get(name) {
+                Some(old) => plan
+                    .upgrades
+                    .push((name.to_string(), old.clone(), new.to_string())),
+                None => plan.installs.push((name.to_string(), new.to_string())),
+            }
+        }
+
+        let targets: Vec<&str> = plan
+            .upgrades
+            .iter()
+            .map(|(n, _, _)| n.as_str())
+            .chain(plan.installs.iter().map(|(n, _)| n.as_str()))
+            .collect();
+        let new_info = info_records("-Si", &targets, dbpath.as_ref().map(|d| d.path()));
+
+        // New packages can push out installed ones they replace or conflict with
+        for (name, _) in &plan.installs {
+            let Some(d) = new_info.get(name) else {
+                continue;
+            };
+            for gone in d.replaces.iter().chain(&d.conflicts) {
+                // Entries may carry a version constraint, e.g. "foo<2"
+                let gone = gone.split(['<', '>', '=']).next().unwrap_or(gone);
+                if local.contains_key(gone) && !plan.removals.iter().any(|r| r == gone) {
+                    plan.removals.push(gone.to_string());
+                }
+            }
         }
+
+        // Upgraded and removed packages give back what they take up now
+        let replaced: Vec<&str> = plan
+            .upgrades
+            .iter()
+            .map(|(n, _, _)| n.as_str())
+            .chain(plan.removals.iter().map(String::as_str))
+            .collect();
+        let old_info = info_records("-Qi", &replaced, None);
+        let size = |d: Option<&PackageDetails>| d.and_then(|d| d.size_install).unwrap_or(0) as i64;
+        plan.install_size_delta = targets.iter().map(|n| size(new_info.get(*n))).sum::<i64>()
+            - replaced.iter().map(|n| size(old_info.get(*n))).sum::<i64>();
+        Ok(plan)
     }
 
-    fn upgrade_all(&self, sink: &ProgressSink, cancel: &CancelToken) -> Result<()> {
+    fn upgrade_all(
+        &self,
+        skip: &[PackageId],
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<()> {
         // Full system upgrade, as pacman documents (-Syu).
-        let mut cmd = Command::new("pkexec");
-        cmd.args(["pacman", "-Syu", "--noconfirm"]);
-        let code = self.run_stream(cmd, sink, cancel, Stage::Installing)?;
-        if code == 0 {
-            Ok(())
-        } else {
-            Err(Error::Priv(format!("upgrade-all exit {code}")))
+        let ignore = ignore_list(skip);
+        let mut args = vec!["-Su"];
+        if !ignore.is_empty() {
+            args.extend(["--ignore", &ignore]);
+        }
+        self.report_mirror(&args, sink);
+        let mut cmd = self.pacman_command();
+        cmd.args(["-Syu", "--noconfirm"]);
+        if !ignore.is_empty() {
+            cmd.args(["--ignore", &ignore]);
+        }
+        self.run_transaction("upgrade-all", cmd, sink, cancel, Stage::Installing, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `pacman -Si ffmpeg`, trimmed to the fields the parser reads.
+    const FFMPEG_SI: &str = "\
+Repository      : extra
+Name            : ffmpeg
+Version         : 2:7.1.1-3
+Description     : Complete solution to record, convert and stream audio and video
+Architecture    : x86_64
+URL             : https://ffmpeg.org
+Provides        : libavcodec.so=61-64  libavdevice.so=61-64  libavfilter.so=10-64
+Depends On      : alsa-lib  aom  bzip2  fontconfig  gnutls  gsm  lame
+Optional Deps   : avisynthplus: AviSynthPlus support
+                  frei0r-plugins: Frei0r video effects support
+                  intel-media-sdk: Intel QuickSync support (legacy)
+                  ladspa: LADSPA filters
+                  nvidia-utils: Nvidia NVDEC/NVENC support
+                  onevpl-intel-gpu: Intel QuickSync support
+Conflicts With  : None
+Replaces        : None
+Download Size   : 10.61 MiB
+Installed Size  : 38.42 MiB
+Packager        : Maxime Gauduin <alucryd@archlinux.org>
+";
+
+    fn summary(name: &str) -> PackageSummary {
+        PackageSummary {
+            id: PackageId {
+                name: name.into(),
+                source: Source::Repo,
+            },
+            version: String::new(),
+            description: String::new(),
+            installed: false,
+            popular: None,
+            last_updated: None,
+            variant: AurVariant::Regular,
+            download_size: None,
+            installed_version: None,
+            out_of_date: None,
+            group_members: None,
+            provides_match: None,
+            repo: None,
+        }
+    }
+
+    #[test]
+    fn details_keep_every_optional_dep() {
+        let d = parse_pacman_details(FFMPEG_SI, summary("ffmpeg"));
+        assert_eq!(
+            d.opt_depends,
+            [
+                "avisynthplus",
+                "frei0r-plugins",
+                "intel-media-sdk",
+                "ladspa",
+                "nvidia-utils",
+                "onevpl-intel-gpu"
+            ]
+        );
+        // The block ends at the next field
+        assert!(d.conflicts.is_empty());
+        assert_eq!(d.size_download, parse_size("10.61 MiB"));
+    }
+
+    #[test]
+    fn stanza_picks_the_record_named_like_the_target() {
+        // A -Si run printing two repos' records, the first of another package
+        let out = "\
+Repository      : testing
+Name            : foo-git
+Version         : 2.0-1
+
+Repository      : extra
+Name            : foo
+Version         : 1.0-1
+Description     : The real foo
+
+";
+        let rec = stanza(out, "foo");
+        assert!(rec.contains("Repository      : extra"));
+        let d = parse_pacman_details(rec, summary("foo"));
+        assert_eq!(d.summary.version, "1.0-1");
+        assert_eq!(d.summary.repo.as_deref(), Some("extra"));
+        // No exact match: the first record, from the highest-priority repo
+        assert!(stanza(out, "bar").contains("Name            : foo-git"));
+    }
+
+    #[test]
+    fn details_read_conflicts_and_replaces() {
+        let out = "\
+Name            : pipewire-jack
+Conflicts With  : jack  jack2
+Replaces        : pipewire-jack-dropin  jack2<1.9
+";
+        let d = parse_pacman_details(out, summary("pipewire-jack"));
+        assert_eq!(d.conflicts, ["jack", "jack2"]);
+        assert_eq!(d.replaces, ["pipewire-jack-dropin", "jack2<1.9"]);
+    }
+
+    #[test]
+    fn details_read_none_as_no_conflicts_or_replaces() {
+        let d = parse_pacman_details(FFMPEG_SI, summary("ffmpeg"));
+        assert!(d.conflicts.is_empty());
+        assert!(d.replaces.is_empty());
+    }
+
+    #[test]
+    fn details_without_optional_deps() {
+        let d = parse_pacman_details(
+            "Optional Deps   : None\nReplaces        : None\n",
+            summary("x"),
+        );
+        assert!(d.opt_depends.is_empty());
+    }
+
+    /// Two parallel downloads as pacman draws them with `COLUMNS=80` off a tty: redraws
+    /// end in `\r`, finished bars in `\n`, and bars after the first move the cursor.
+    const DOWNLOAD_BARS: &str = "\
+Total Download Size:   3.00 MiB\n\
+:: Retrieving packages...\n \
+zlib-1:1.3.1-2-x86_64  512.0 KiB  1024 KiB/s 00:01 [#####---------------]  25%\r \
+zlib-1:1.3.1-2-x86_64 1024.0 KiB  1024 KiB/s 00:01 [##########----------]  50%\r\
+\x1b[1B\x1b[K pcre2-10.44-1-x86_64   512.0 KiB   512 KiB/s 00:01 [##########----------]  50%\r\
+\x1b[1A\x1b[K zlib-1:1.3.1-2-x86_64    2.0 MiB  1024 KiB/s 00:00 [####################] 100%\n \
+pcre2-10.44-1-x86_64  1024.0 KiB   512 KiB/s 00:00 [####################] 100%\n \
+Total ( 2/2)             3.0 MiB  1536 KiB/s 00:00 [####################] 100%\n";
+
+    #[test]
+    fn download_bars_move_the_progress_and_the_tally() {
+        let parser = ProgressParser::new();
+        let mut tally = DownloadTally::default();
+        let mut tallies = Vec::new();
+        for l in DOWNLOAD_BARS.split(['\r', '\n']) {
+            let l = parser.clean(l);
+            let (percent, bytes) = parser.parse(&l);
+            if l.contains('%') {
+                assert!(percent.is_some() && bytes.is_some(), "{l:?}");
+            }
+            tallies.extend(tally.update(&l, bytes));
+        }
+        let mib = 1 << 20;
+        assert_eq!(
+            parser.parse(" zlib-1:1.3.1-2-x86_64 1024.0 KiB  1024 KiB/s 00:01 [###---]  50%"),
+            (Some(0.5), Some((mib, 2 * mib)))
+        );
+        assert_eq!(tallies.first(), Some(&(0, 3 * mib)));
+        assert_eq!(tallies.last(), Some(&(3 * mib, 3 * mib)));
+        assert!(tallies.windows(2).all(|w| w[0].0 <= w[1].0), "{tallies:?}");
+        assert_eq!(tallies.len(), 7);
+    }
+
+    #[test]
+    fn parse_size_reads_pacman_units() {
+        assert_eq!(parse_size("123.45 MiB"), Some(129_446_707));
+        assert_eq!(parse_size("2 TiB"), Some(2 << 40));
+        assert_eq!(parse_size("2TiB"), Some(2 << 40));
+        assert_eq!(parse_size("1,5 GiB"), Some(3 << 29));
+        assert_eq!(parse_size("512 B"), Some(512));
+    }
+
+    #[test]
+    fn parse_size_rejects_malformed_input() {
+        for s in ["", "MiB", "12 MB", "1.2.3 KiB", "-4 KiB", "abc", "5 MiB/s"] {
+            assert_eq!(parse_size(s), None, "{s:?}");
         }
     }
 }
@@ -1,6 +1,10 @@
 use crossbeam_channel as chan;
-use parking_lot::Mutex;
+use parking_lot::{Condvar, Mutex};
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    process::Command,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
@@ -8,19 +12,80 @@ use std::{
     time::SystemTime,
 };
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+/// Ordered by declaration, so repo entries sort ahead of same-named AUR ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
 pub enum Source {
     Repo,
     Aur,
+    Flatpak,
+    /// A source provided by an out-of-tree backend, tagged by name (see `BackendRegistry`).
+    /// Not serializable: the backend may be gone by the time the data is read back.
+    #[serde(skip)]
+    Custom(&'static str),
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+// `Custom` holds a &'static str, so it can't be deserialized; it's skipped on the way out.
+#[derive(Deserialize)]
+enum StoredSource {
+    Repo,
+    Aur,
+    Flatpak,
+}
+
+impl<'de> Deserialize<'de> for Source {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        Ok(match StoredSource::deserialize(d)? {
+            StoredSource::Repo => Source::Repo,
+            StoredSource::Aur => Source::Aur,
+            StoredSource::Flatpak => Source::Flatpak,
+        })
+    }
+}
+
+impl Source {
+    /// Short label used in logs and badges.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Source::Repo => "repo",
+            Source::Aur => "AUR",
+            Source::Flatpak => "Flatpak",
+            Source::Custom(name) => name,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct PackageId {
     pub name: String,
     pub source: Source,
 }
 
-#[derive(Clone, Debug)]
+/// How an AUR package is built, inferred from its name suffix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AurVariant {
+    #[default]
+    Regular,
+    /// `-git`, `-svn`, `-hg`, ...: builds the latest upstream commit, so the RPC version
+    /// says nothing about what is installed.
+    Vcs,
+    /// `-bin`: repackages an upstream binary.
+    Prebuilt,
+}
+
+impl AurVariant {
+    pub fn classify(name: &str) -> Self {
+        const VCS: [&str; 6] = ["-git", "-svn", "-hg", "-bzr", "-darcs", "-fossil"];
+        if VCS.iter().any(|s| name.ends_with(s)) {
+            Self::Vcs
+        } else if name.ends_with("-bin") {
+            Self::Prebuilt
+        } else {
+            Self::Regular
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PackageSummary {
     pub id: PackageId,
     pub version: String,
@@ -28,9 +93,32 @@ pub struct PackageSummary {
     pub installed: bool,
     pub popular: Option<u32>,
     pub last_updated: Option<SystemTime>,
+    /// Always `Regular` outside the AUR.
+    pub variant: AurVariant,
+    /// Bytes an upgrade would download (0 if already cached), when the source knows.
+    #[serde(default)]
+    pub download_size: Option<u64>,
+    /// The version installed now, for upgrades, where `version` is the new one.
+    #[serde(default)]
+    pub installed_version: Option<String>,
+    /// When the package was flagged out of date upstream (AUR only).
+    #[serde(default)]
+    pub out_of_date: Option<SystemTime>,
+    /// Set when the row is a pacman group (e.g. `gnome`) rather than a package: how many
+    /// packages it has. Installing or removing it acts on all of them.
+    #[serde(default)]
+    pub group_members: Option<usize>,
+    /// Sync repository a repo package comes from ("core", "extra", a third-party one), when
+    /// the listing says.
+    #[serde(default)]
+    pub repo: Option<String>,
+    /// Set when the package turned up because it provides the searched name (e.g.
+    /// `jdk-openjdk` for "java-runtime"), not because its name or description matched.
+    #[serde(default)]
+    pub provides_match: Option<String>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PackageDetails {
     pub summary: PackageSummary,
     pub depends: Vec<String>,
@@ -39,6 +127,23 @@ pub struct PackageDetails {
     pub maintainer: Option<String>,
     pub size_install: Option<u64>,
     pub size_download: Option<u64>,
+    /// As reported by the source, e.g. "x86_64" or "any" for arch-independent packages.
+    pub architecture: Option<String>,
+    /// Packages that can't be installed alongside this one; installing it removes them.
+    #[serde(default)]
+    pub conflicts: Vec<String>,
+    /// The `conflicts` that are installed now, i.e. what installing this one would remove.
+    #[serde(default)]
+    pub installed_conflicts: Vec<String>,
+    /// Packages this one supersedes.
+    #[serde(default)]
+    pub replaces: Vec<String>,
+    /// Names (and virtual names like "sh") this package satisfies dependencies on.
+    #[serde(default)]
+    pub provides: Vec<String>,
+    /// Installed packages that depend on this one; empty when it isn't installed.
+    #[serde(default)]
+    pub required_by: Vec<String>,
 }
 
 #[derive(Clone, Debug)]
@@ -53,8 +158,12 @@ pub enum Stage {
     Removing,
     Verifying,
     Cleaning,
+    /// A polkit prompt is up and the command waits on it; the next stage means it's past.
+    Authorizing,
     Finished,
     Failed,
+    /// The job stopped because its CancelToken fired; not a failure.
+    Cancelled,
 }
 
 #[derive(Clone, Debug)]
@@ -67,20 +176,446 @@ pub struct Progress {
     pub warning: bool,
 }
 
+/// How much goes into the log, from warnings only to full command output plus the exact
+/// commands run. Backends also pass their own debug flags at `Debug`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    #[default]
+    Normal,
+    Debug,
+}
+
+impl Verbosity {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "quiet" => Some(Self::Quiet),
+            "normal" => Some(Self::Normal),
+            "debug" => Some(Self::Debug),
+            _ => None,
+        }
+    }
+}
+
+/// Compares pacman versions (`[epoch:]version[-release]`) the way libalpm's `alpm_pkg_vercmp`
+/// does, so "1.10" > "1.9", "1.0" > "1.0rc1" and any epoch beats no epoch.
+pub fn vercmp(a: &str, b: &str) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+    if a == b {
+        return Ordering::Equal;
+    }
+    // Epoch defaults to 0, even when empty; the release is only compared when both have one
+    fn evr(s: &str) -> (&str, &str, Option<&str>) {
+        let (epoch, rest) = match s.split_once(':') {
+            Some(("", r)) => ("0", r),
+            Some((e, r)) if e.bytes().all(|c| c.is_ascii_digit()) => (e, r),
+            _ => ("0", s),
+        };
+        match rest.rsplit_once('-') {
+            Some((v, r)) => (epoch, v, Some(r)),
+            None => (epoch, rest, None),
+        }
+    }
+    let (e1, v1, r1) = evr(a);
+    let (e2, v2, r2) = evr(b);
+    rpmvercmp(e1, e2)
+        .then_with(|| rpmvercmp(v1, v2))
+        .then_with(|| match (r1, r2) {
+            (Some(r1), Some(r2)) => rpmvercmp(r1, r2),
+            _ => Ordering::Equal,
+        })
+}
+
+/// Segment-wise comparison of digit and letter runs, as in libalpm's `rpmvercmp`.
+fn rpmvercmp(a: &str, b: &str) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        let (si, sj) = (i, j);
+        while i < a.len() && !a[i].is_ascii_alphanumeric() {
+            i += 1;
+        }
+        while j < b.len() && !b[j].is_ascii_alphanumeric() {
+            j += 1;
+        }
+        if i == a.len() || j == b.len() {
+            break;
+        }
+        // More separators wins: "1..0" > "1.0"
+        if i - si != j - sj {
+            return (i - si).cmp(&(j - sj));
+        }
+        let numeric = a[i].is_ascii_digit();
+        let run = |s: &[u8], mut k: usize| {
+            while k < s.len()
+                && (if numeric {
+                    s[k].is_ascii_digit()
+                } else {
+                    s[k].is_ascii_alphabetic()
+                })
+            {
+                k += 1;
+            }
+            k
+        };
+        let (ei, ej) = (run(a, i), run(b, j));
+        // A number beats letters in the same place
+        if ej == j {
+            return if numeric {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+        let (mut x, mut y) = (&a[i..ei], &b[j..ej]);
+        if numeric {
+            while x.len() > 1 && x[0] == b'0' {
+                x = &x[1..];
+            }
+            while y.len() > 1 && y[0] == b'0' {
+                y = &y[1..];
+            }
+            let by_len = x.len().cmp(&y.len());
+            if by_len != Ordering::Equal {
+                return by_len;
+            }
+        }
+        let ord = x.cmp(y);
+        if ord != Ordering::Equal {
+            return ord;
+        }
+        (i, j) = (ei, ej);
+    }
+    let (rest_a, rest_b) = (a.get(i), b.get(j));
+    match (rest_a, rest_b) {
+        (None, None) => Ordering::Equal,
+        // A leftover letter run ("1.0rc1" vs "1.0") is a pre-release, so older
+        (None, Some(c)) if !c.is_ascii_alphabetic() => Ordering::Less,
+        (Some(c), _) if c.is_ascii_alphabetic() => Ordering::Less,
+        _ => Ordering::Greater,
+    }
+}
+
+/// How backends run commands that need root.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Privilege {
+    /// polkit prompt; needs polkitd on the system bus.
+    #[default]
+    Pkexec,
+    /// For systems without polkit. Can't prompt without a terminal, so it only works with
+    /// `SUDO_ASKPASS` or cached credentials.
+    Sudo,
+    /// Already root, e.g. in a container or chroot.
+    Direct,
+}
+
+impl Privilege {
+    /// A command running `program` as root.
+    pub fn command(self, program: &str) -> Command {
+        match self {
+            Self::Pkexec => {
+                let mut cmd = Command::new("pkexec");
+                cmd.arg(program);
+                cmd
+            }
+            Self::Sudo => {
+                let mut cmd = Command::new("sudo");
+                // -n fails at once rather than waiting on a prompt nobody sees
+                let askpass = std::env::var_os("SUDO_ASKPASS").is_some();
+                cmd.arg(if askpass { "-A" } else { "-n" }).arg(program);
+                cmd
+            }
+            Self::Direct => Command::new(program),
+        }
+    }
+
+    /// `command` with `vars` set for `program`. pkexec clears the environment, so under it
+    /// they're set through env(1), and the polkit dialog names env as what runs.
+    pub fn command_env(self, program: &str, vars: &[(&str, &str)]) -> Command {
+        match self {
+            Self::Pkexec => {
+                let mut cmd = Command::new("pkexec");
+                cmd.arg("env")
+                    .args(vars.iter().map(|(k, v)| format!("{k}={v}")))
+                    .arg(program);
+                cmd
+            }
+            Self::Sudo | Self::Direct => {
+                // sudo keeps COLUMNS and the like through its default env_keep
+                let mut cmd = self.command(program);
+                cmd.envs(vars.iter().copied());
+                cmd
+            }
+        }
+    }
+
+    /// The progress to report right after spawning `cmd`, if it's about to wait on a polkit
+    /// dialog: nothing else shows on screen until the user answers it.
+    pub fn prompt_progress(cmd: &Command) -> Option<Progress> {
+        (cmd.get_program() == "pkexec").then(|| Progress {
+            job_id: 0,
+            stage: Stage::Authorizing,
+            percent: None,
+            bytes: None,
+            log: Some("Waiting for authorization…".into()),
+            warning: false,
+        })
+    }
+
+    /// Maps the exit code of a `command` to a result. pkexec exits 126 when its dialog is
+    /// dismissed and 127 when authentication fails or is cancelled.
+    pub fn check(self, what: &str, code: i32) -> Result<()> {
+        match (self, code) {
+            (_, 0) => Ok(()),
+            (Self::Pkexec, 126 | 127) => Err(Error::AuthCancelled),
+            _ => Err(Error::Priv(format!("{what} exit {code}"))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookOutcome {
+    Ok,
+    Warning,
+    Failed,
+}
+
+/// One pacman hook that ran during a transaction, with any warning/error lines it printed.
+#[derive(Clone, Debug)]
+pub struct HookRun {
+    pub name: String,
+    pub outcome: HookOutcome,
+    pub messages: Vec<String>,
+}
+
+/// One commit in a package's packaging history.
+#[derive(Clone, Debug)]
+pub struct LogEntry {
+    pub hash: String,
+    pub at: Option<SystemTime>,
+    pub author: String,
+    pub subject: String,
+}
+
+/// What a finished transaction changed, from its output.
+#[derive(Clone, Debug, Default)]
+pub struct TransactionSummary {
+    /// The package the transaction was run for; `None` for a full upgrade.
+    pub target: Option<String>,
+    /// (name, version)
+    pub installed: Vec<(String, String)>,
+    /// (name, old version, new version)
+    pub upgraded: Vec<(String, String, String)>,
+    /// (name, version)
+    pub removed: Vec<(String, String)>,
+    /// Change in installed size in bytes; negative when space was freed.
+    pub size_delta: i64,
+}
+
+/// What a transaction would change, worked out before running it.
+#[derive(Clone, Debug, Default)]
+pub struct TransactionPlan {
+    /// (name, installed version, new version)
+    pub upgrades: Vec<(String, String, String)>,
+    /// New packages pulled in, with their version.
+    pub installs: Vec<(String, String)>,
+    /// Installed packages the transaction removes because a new one replaces or
+    /// conflicts with them.
+    pub removals: Vec<String>,
+    /// Bytes to download; cached packages count as 0.
+    pub download_size: u64,
+    /// Change in installed size in bytes; negative when space is freed.
+    pub install_size_delta: i64,
+}
+
+impl TransactionPlan {
+    /// Adds what `other` would change, for a plan spanning several backends.
+    pub fn merge(&mut self, other: TransactionPlan) {
+        self.upgrades.extend(other.upgrades);
+        self.installs.extend(other.installs);
+        self.removals.extend(other.removals);
+        self.download_size += other.download_size;
+        self.install_size_delta += other.install_size_delta;
+    }
+}
+
+/// What cleaning the package cache would do, worked out before running it.
+#[derive(Clone, Debug, Default)]
+pub struct CacheCleanPlan {
+    /// The command that will run, for the confirmation.
+    pub command: String,
+    /// Package files it removes, when a dry run can tell.
+    pub files: Vec<String>,
+    /// Bytes freed, when a dry run can tell.
+    pub reclaim: Option<u64>,
+}
+
+/// A build script as (file name, contents).
+pub type BuildFile = (String, String);
+
+/// Name of the `BuildFile` holding a unified diff of the PKGBUILD since it was last built.
+pub const PKGBUILD_DIFF: &str = "PKGBUILD.diff";
+
+/// What removing a package would take with it, from a dry run.
+#[derive(Clone, Debug, Default)]
+pub struct RemovePreview {
+    /// Every package the removal takes, the requested one included.
+    pub names: Vec<String>,
+    /// Why the removal can't go ahead: installed packages that still depend on it, one
+    /// pacman message each (e.g. "removing foo breaks dependency 'foo' required by bar").
+    pub breaks: Vec<String>,
+}
+
+impl RemovePreview {
+    /// Reads `pacman -Rns --print --print-format %n`, shared by the backends whose packages
+    /// live in the local db.
+    pub fn from_pacman(out: &std::process::Output) -> Result<Self> {
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        let breaks: Vec<String> = stderr
+            .lines()
+            .filter(|l| l.contains("breaks dependency"))
+            .map(|l| l.trim_start_matches(':').trim().to_string())
+            .collect();
+        if !out.status.success() && breaks.is_empty() {
+            return Err(Error::Alpm(stderr.trim().to_string()));
+        }
+        let names = String::from_utf8_lossy(&out.stdout)
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(String::from)
+            .collect();
+        Ok(Self { names, breaks })
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Event {
     SearchResults {
         query: String,
         items: Vec<PackageSummary>,
+        /// From `JobKind::SearchInstalled`, so only installed packages were looked at.
+        installed_only: bool,
+        /// Id of the search job. Ids only grow, so results from a search older than the
+        /// latest one can be told apart and dropped.
+        generation: u64,
     },
     Details {
-        item: PackageDetails,
+        item: Box<PackageDetails>,
     },
     Upgrades {
         items: Vec<PackageSummary>,
     },
-    /// Sent when the system package state likely changed (install/remove/upgrade).
+    /// Sent when the system package state likely changed (install/remove/upgrade/refresh).
     SystemChanged,
+    /// Sent when the sync databases have never been downloaded, so repo search can't work
+    /// until a first refresh.
+    SyncDbMissing,
+    /// Hooks pacman ran during a transaction. A failed hook (e.g. mkinitcpio) can leave the
+    /// system unbootable, so the UI surfaces these prominently.
+    HookSummary {
+        hooks: Vec<HookRun>,
+    },
+    /// Host serving the current transaction's downloads, to help diagnose slow mirrors.
+    DownloadMirror {
+        host: String,
+    },
+    /// One backend's share of a search still running, so fast sources show before slow
+    /// ones; the final `SearchResults` carries everything.
+    SearchResultsPartial {
+        query: String,
+        source: Source,
+        items: Vec<PackageSummary>,
+        generation: u64,
+    },
+    /// Packages whose files include the searched path (`JobKind::SearchFile`).
+    FileSearchResults {
+        path: String,
+        items: Vec<PackageSummary>,
+    },
+    /// The files database used for file searches is missing or older than the sync
+    /// databases; the UI offers to sync it.
+    FilesDbNeedsSync,
+    /// Result of `JobKind::PlanUpgradeAll`, for confirmation before the real upgrade.
+    UpgradePlan {
+        plan: Box<TransactionPlan>,
+    },
+    /// Result of `JobKind::PlanCleanCache`, for confirmation before the real clean.
+    CacheCleanPlan {
+        plan: CacheCleanPlan,
+    },
+    /// Result of `JobKind::Orphans`.
+    Orphans {
+        items: Vec<PackageSummary>,
+    },
+    /// Result of `JobKind::CheckRebuilds`; empty when nothing is broken.
+    RebuildsNeeded {
+        items: Vec<PackageSummary>,
+    },
+    /// Paths an installed package owns.
+    Files {
+        id: PackageId,
+        files: Vec<PathBuf>,
+    },
+    /// Result of `JobKind::BuildDeps`, for confirmation before building `id`.
+    BuildDeps {
+        id: PackageId,
+        deps: Vec<String>,
+    },
+    /// Result of `JobKind::PrefetchDetails`.
+    DetailsBatch {
+        items: Vec<PackageDetails>,
+    },
+    /// Result of `JobKind::History`.
+    History {
+        id: PackageId,
+        entries: Vec<LogEntry>,
+    },
+    /// Result of `JobKind::Sibling`: the other build of `id`, if there is one.
+    Sibling {
+        id: PackageId,
+        sibling: Option<Box<PackageSummary>>,
+    },
+    /// Result of `JobKind::ShowBuildFiles`: (file name, contents), PKGBUILD first. On a
+    /// rebuild, a `PKGBUILD_DIFF` against the last built PKGBUILD comes before it.
+    BuildFiles {
+        id: PackageId,
+        files: Vec<BuildFile>,
+    },
+    /// Result of `JobKind::WhoRequires`: installed packages depending on `id`, as the
+    /// lines of an indented tree.
+    WhoRequires {
+        id: PackageId,
+        tree: Vec<String>,
+    },
+    /// Result of `JobKind::CachedVersions`, newest first.
+    CachedVersions {
+        id: PackageId,
+        versions: Vec<String>,
+    },
+    /// Result of `JobKind::RemovePreview`.
+    RemovePreview {
+        id: PackageId,
+        preview: RemovePreview,
+    },
+    /// A job ended with an error (not a cancel). `message` is the error's own text.
+    JobFailed {
+        job_id: u64,
+        kind: JobKind,
+        message: String,
+    },
+    /// Sent after a successful install, remove or upgrade.
+    TransactionSummary {
+        summary: Box<TransactionSummary>,
+    },
+    /// Build-only dependencies an install pulled in that nothing needs any more.
+    OrphanedBuildDeps {
+        names: Vec<String>,
+    },
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -95,6 +630,16 @@ pub enum Error {
     Priv(String),
     #[error("cancelled")]
     Cancelled,
+    /// The user dismissed the password prompt; as neutral as `Cancelled`.
+    #[error("authentication cancelled")]
+    AuthCancelled,
+    /// pacman's db.lck is held, usually by another pacman running; worth retrying later.
+    #[error("package database locked by another package operation")]
+    DbLocked,
+    #[error("sync databases missing; run a refresh first")]
+    SyncDbMissing,
+    #[error("files database missing; sync it first")]
+    FilesDbMissing,
     #[error("internal: {0}")]
     Internal(String),
 }
@@ -114,6 +659,138 @@ impl CancelToken {
     }
 }
 pub type ProgressSink = chan::Sender<Progress>;
+pub type EventSink = chan::Sender<Event>;
+
+/// How long a cancelled command gets to exit after SIGTERM before it's killed.
+const STOP_GRACE: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// What pacman prints as it starts changing the system; from there on a kill would leave
+/// packages half-installed and the db locked.
+const PACMAN_COMMITTING: [&str; 2] = [
+    ":: Running pre-transaction hooks",
+    ":: Processing package changes",
+];
+
+/// Runs `cmd` in its own process group, handing each line it prints to `on_out` or
+/// `on_err` as it arrives, and returns its exit status and whether it was cancelled.
+///
+/// Cancelling sends the group SIGTERM and, if it's still running `STOP_GRACE` later,
+/// SIGKILL; so hooks and helpers it started stop too. Once a pacman inside it has begun
+/// committing it's left to finish instead, with a warning on `sink`. Neither signal
+/// reaches a group running as root under pkexec; that one is always waited out.
+pub fn run_piped(
+    mut cmd: Command,
+    sink: &ProgressSink,
+    stage: &Stage,
+    cancel: &CancelToken,
+    mut on_out: impl FnMut(String) + Send + 'static,
+    on_err: impl FnMut(String) + Send + 'static,
+) -> Result<(std::process::ExitStatus, bool)> {
+    use nix::sys::signal::{killpg, Signal};
+    use std::process::Stdio;
+
+    std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+    let mut child = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::Internal(format!("spawn: {e}")))?;
+    let committing = Arc::new(AtomicBool::new(false));
+    let seen = committing.clone();
+    let readers = [
+        read_lines(child.stdout.take(), move |l| {
+            if PACMAN_COMMITTING.iter().any(|m| l.starts_with(m)) {
+                seen.store(true, Ordering::SeqCst);
+            }
+            on_out(l)
+        }),
+        read_lines(child.stderr.take(), on_err),
+    ];
+    let group = nix::unistd::Pid::from_raw(child.id() as i32);
+
+    // When SIGTERM went out, and whether the grace period has been dealt with
+    let mut stopping: Option<(std::time::Instant, bool)> = None;
+    let status = loop {
+        if let Some(status) = child
+            .try_wait()
+            .map_err(|e| Error::Internal(format!("wait: {e}")))?
+        {
+            break status;
+        }
+        match &mut stopping {
+            None if cancel.is_cancelled() => {
+                let _ = killpg(group, Signal::SIGTERM);
+                stopping = Some((std::time::Instant::now(), false));
+            }
+            Some((at, done)) if !*done && at.elapsed() > STOP_GRACE => {
+                *done = true;
+                if committing.load(Ordering::SeqCst) {
+                    sink.send(Progress {
+                        job_id: 0,
+                        stage: stage.clone(),
+                        percent: None,
+                        bytes: None,
+                        log: Some(
+                            "still finishing: pacman is committing the transaction and can't \
+                             be stopped safely"
+                                .into(),
+                        ),
+                        warning: true,
+                    })
+                    .ok();
+                } else {
+                    let _ = killpg(group, Signal::SIGKILL);
+                }
+            }
+            _ => {}
+        }
+        std::thread::sleep(std::time::Duration::from_millis(16));
+    };
+    for r in readers.into_iter().flatten() {
+        let _ = r.join();
+    }
+    Ok((status, stopping.is_some()))
+}
+
+/// Hands each line of `out` to `f` on a thread of its own, reading to the end so the
+/// command never dies of a closed pipe.
+fn read_lines(
+    out: Option<impl std::io::Read + Send + 'static>,
+    f: impl FnMut(String) + Send + 'static,
+) -> Option<std::thread::JoinHandle<()>> {
+    let out = out?;
+    Some(std::thread::spawn(move || split_lines(out, f)))
+}
+
+/// Splits `out` into lines ending in `\n` or `\r`: progress bars (pacman's, git's, curl's)
+/// redraw themselves with `\r`, and each redraw counts as a line. `\r\n` ends just one.
+fn split_lines(out: impl std::io::Read, mut f: impl FnMut(String)) {
+    use std::io::BufRead;
+    let mut out = std::io::BufReader::new(out);
+    let mut line = Vec::new();
+    let mut after_cr = false;
+    loop {
+        let buf = match out.fill_buf() {
+            Ok([]) => break,
+            Ok(buf) => buf,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+        for &b in buf {
+            match b {
+                b'\n' if after_cr => {}
+                b'\n' | b'\r' => f(String::from_utf8_lossy(&std::mem::take(&mut line)).into()),
+                _ => line.push(b),
+            }
+            after_cr = b == b'\r';
+        }
+        let n = buf.len();
+        out.consume(n);
+    }
+    if !line.is_empty() {
+        f(String::from_utf8_lossy(&line).into());
+    }
+}
 
 pub trait PackageBackend: Send + Sync {
     fn refresh(&self, sink: &ProgressSink, cancel: &CancelToken) -> Result<()>;
@@ -123,6 +800,20 @@ pub trait PackageBackend: Send + Sync {
         sink: &ProgressSink,
         cancel: &CancelToken,
     ) -> Result<Vec<PackageSummary>>;
+
+    /// `search` limited to installed packages, answered locally so it's fast and works
+    /// offline. The default filters a full `search`.
+    fn search_installed(
+        &self,
+        q: &str,
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<Vec<PackageSummary>> {
+        let mut items = self.search(q, sink, cancel)?;
+        items.retain(|p| p.installed);
+        Ok(items)
+    }
+
     fn details(
         &self,
         id: &PackageId,
@@ -133,19 +824,301 @@ pub trait PackageBackend: Send + Sync {
     fn remove(&self, id: &PackageId, sink: &ProgressSink, cancel: &CancelToken) -> Result<()>;
     fn upgrades(&self, sink: &ProgressSink, cancel: &CancelToken) -> Result<Vec<PackageSummary>>;
     fn upgrade(&self, id: &PackageId, sink: &ProgressSink, cancel: &CancelToken) -> Result<()>;
-    fn upgrade_all(&self, sink: &ProgressSink, cancel: &CancelToken) -> Result<()>;
+    /// Upgrades everything from this backend except the packages in `skip`, which are all
+    /// its own.
+    fn upgrade_all(
+        &self,
+        skip: &[PackageId],
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<()>;
+
+    /// Like `details`, but must not answer from a cache. Backends that cache details
+    /// override this to drop the entry and refetch.
+    fn details_fresh(
+        &self,
+        id: &PackageId,
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<PackageDetails> {
+        self.details(id, sink, cancel)
+    }
+
+    /// Packages from any repository that provide `path`, installed or not. Backends
+    /// without a file index return none.
+    fn search_file(
+        &self,
+        _path: &str,
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<Vec<PackageSummary>> {
+        Ok(vec![])
+    }
+
+    /// Downloads the index `search_file` uses.
+    fn refresh_files(&self, _sink: &ProgressSink, _cancel: &CancelToken) -> Result<()> {
+        Ok(())
+    }
+
+    /// Details for several packages at once, to prefetch them; ones that can't be fetched
+    /// are left out. The default fetches them one by one.
+    fn details_batch(
+        &self,
+        ids: &[PackageId],
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<Vec<PackageDetails>> {
+        let mut out = Vec::new();
+        for id in ids {
+            if cancel.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+            if let Ok(d) = self.details(id, sink, cancel) {
+                out.push(d);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Paths owned by an installed package, or that a not yet installed one would install
+    /// where the backend can tell (`Error::FilesDbMissing` when that needs a files
+    /// database that isn't there). Backends without a file list return none.
+    fn files(
+        &self,
+        _id: &PackageId,
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<Vec<PathBuf>> {
+        Ok(vec![])
+    }
+
+    /// Recent commits to the package's packaging repo, newest first. Backends without one
+    /// return none.
+    fn history(
+        &self,
+        _id: &PackageId,
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<Vec<LogEntry>> {
+        Ok(vec![])
+    }
+
+    /// What `upgrade_all` with the same `skip` would do, without doing it.
+    fn plan_upgrade_all(
+        &self,
+        _skip: &[PackageId],
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<TransactionPlan> {
+        Ok(TransactionPlan::default())
+    }
+
+    /// What `clean_cache` would remove.
+    fn plan_clean_cache(
+        &self,
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<CacheCleanPlan> {
+        Ok(CacheCleanPlan::default())
+    }
+
+    /// Delete old package files from the package cache.
+    fn clean_cache(&self, _sink: &ProgressSink, _cancel: &CancelToken) -> Result<()> {
+        Ok(())
+    }
+
+    /// Delete the source checkouts kept between builds; the next build fetches afresh.
+    fn clear_build_cache(&self, _sink: &ProgressSink, _cancel: &CancelToken) -> Result<()> {
+        Ok(())
+    }
+
+    /// Packages installed as dependencies that nothing installed requires any more.
+    fn orphans(&self, _sink: &ProgressSink, _cancel: &CancelToken) -> Result<Vec<PackageSummary>> {
+        Ok(vec![])
+    }
+
+    /// Installed packages this backend built locally that link against libraries which no
+    /// longer exist (e.g. after a soname bump), so they need a rebuild.
+    fn rebuild_candidates(
+        &self,
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<Vec<PackageSummary>> {
+        Ok(vec![])
+    }
+
+    /// Dependencies that installing `id` pulls in only to build it (make/check depends) and
+    /// that aren't installed yet. Empty for backends that don't build from source.
+    fn build_deps(
+        &self,
+        _id: &PackageId,
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+
+    /// The same software packaged the other way round: the prebuilt `-bin` package for a
+    /// from-source one, or the reverse. `None` when there is no such package.
+    fn sibling(
+        &self,
+        _id: &PackageId,
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<Option<PackageSummary>> {
+        Ok(None)
+    }
+
+    /// The build scripts of `id` (PKGBUILD and install hooks) as (file name, contents), to
+    /// read before building it. Empty for backends that don't build from source.
+    fn build_files(
+        &self,
+        _id: &PackageId,
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<Vec<BuildFile>> {
+        Ok(vec![])
+    }
+
+    /// Versions of installed `id` older than the installed one that can be reinstalled
+    /// without a download, newest first.
+    fn cached_versions(
+        &self,
+        _id: &PackageId,
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+
+    /// Installed packages that depend on installed `id`, directly or not, as the lines of an
+    /// indented tree with `id` itself left out.
+    fn who_requires(
+        &self,
+        _id: &PackageId,
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+
+    /// Install several packages of this backend. The default installs them one at a time;
+    /// backends that can do it in a single transaction override this.
+    fn install_many(
+        &self,
+        ids: &[PackageId],
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<()> {
+        for id in ids {
+            if cancel.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+            self.install(id, sink, cancel)?;
+        }
+        Ok(())
+    }
+
+    /// Remove several packages of this backend, like `install_many`.
+    fn remove_many(
+        &self,
+        ids: &[PackageId],
+        sink: &ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<()> {
+        for id in ids {
+            if cancel.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+            self.remove(id, sink, cancel)?;
+        }
+        Ok(())
+    }
+
+    /// Reinstall `version` of `id`, one of `cached_versions`.
+    fn downgrade(
+        &self,
+        id: &PackageId,
+        _version: &str,
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<()> {
+        Err(Error::Internal(format!(
+            "{} packages can't be downgraded",
+            id.source.label()
+        )))
+    }
+
+    /// What removing `id` would take with it, without removing anything.
+    fn remove_preview(
+        &self,
+        id: &PackageId,
+        _sink: &ProgressSink,
+        _cancel: &CancelToken,
+    ) -> Result<RemovePreview> {
+        Ok(RemovePreview {
+            names: vec![id.name.clone()],
+            breaks: vec![],
+        })
+    }
+
+    /// Forget whatever the backend cached about installed packages. Called after each
+    /// transaction and when the local database changes underneath.
+    fn invalidate_installed_cache(&self) {}
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum JobKind {
     Refresh,
+    /// Sync the files database behind `SearchFile`.
+    RefreshFiles,
     Search,
+    /// `Search` among installed packages only.
+    SearchInstalled,
+    /// Which packages provide a path (`JobPayload::Query`).
+    SearchFile,
     Details,
+    /// Details for the rows on screen (`JobPayload::Packages`), fetched in the background.
+    PrefetchDetails,
+    Files,
+    /// Packaging commit log of a package (`JobPayload::Package`).
+    History,
     Install,
+    /// Install several packages (`JobPayload::Packages`), one transaction per backend
+    /// where the backend supports it.
+    InstallMany,
     Remove,
+    /// Remove several packages (`JobPayload::Packages`), batched like `InstallMany`.
+    RemoveMany,
     Upgrades,
     Upgrade,
     UpgradeAll,
+    /// Preview an `UpgradeAll` (same payload) as a `TransactionPlan`.
+    PlanUpgradeAll,
+    /// Preview a `CleanCache`.
+    PlanCleanCache,
+    /// Delete old package files from the package cache.
+    CleanCache,
+    /// Delete every backend's kept build checkouts.
+    ClearBuildCache,
+    /// Look for locally built packages broken by library updates.
+    CheckRebuilds,
+    /// List dependencies nothing requires any more, from every backend.
+    Orphans,
+    /// List what building a package (`JobPayload::Package`) would install only for the build.
+    BuildDeps,
+    /// Look up the prebuilt or from-source counterpart of a package (`JobPayload::Package`).
+    Sibling,
+    /// Dry-run removing a package (`JobPayload::Package`) to list what goes with it.
+    RemovePreview,
+    /// Tree of installed packages depending on a package (`JobPayload::Package`).
+    WhoRequires,
+    /// List older versions of a package (`JobPayload::Package`) still in the package cache.
+    CachedVersions,
+    /// Reinstall a cached older version (`JobPayload::Version`).
+    Downgrade,
+    /// Fetch the build scripts of a package (`JobPayload::Package`) for review.
+    ShowBuildFiles,
 }
 
 #[derive(Clone, Debug)]
@@ -153,6 +1126,18 @@ pub enum JobPayload {
     None,
     Query(String),
     Package(PackageId),
+    /// Packages an `UpgradeAll` leaves out this time.
+    Skip(Vec<PackageId>),
+    Packages(Vec<PackageId>),
+    /// `force` bypasses any details cache in the backend.
+    Details {
+        id: PackageId,
+        force: bool,
+    },
+    Version {
+        id: PackageId,
+        version: String,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -164,65 +1149,370 @@ pub struct Job {
     pub cancel: CancelToken,
 }
 
+/// Maps each `Source` to the backend serving it. Search and upgrade listings fan out over
+/// every registered backend in registration order; per-package jobs go to the backend for
+/// the package's source, and jobs without a package (refresh, upgrade-all) go to `Source::Repo`.
+///
+/// Adding a third source: implement `PackageBackend`, pick a `Source` (a new variant, or
+/// `Source::Custom("name")` for out-of-tree backends) and `register` it in app_shell's `main`.
+/// The Executor needs no changes.
+#[derive(Clone, Default)]
+pub struct BackendRegistry {
+    backends: Vec<(Source, Arc<dyn PackageBackend>)>,
+}
+
+impl BackendRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `backend` for `source`, replacing any backend already registered for it.
+    pub fn register(mut self, source: Source, backend: Arc<dyn PackageBackend>) -> Self {
+        if let Some(slot) = self.backends.iter_mut().find(|(s, _)| *s == source) {
+            slot.1 = backend;
+        } else {
+            self.backends.push((source, backend));
+        }
+        self
+    }
+
+    pub fn get(&self, source: Source) -> Option<&dyn PackageBackend> {
+        self.backends
+            .iter()
+            .find(|(s, _)| *s == source)
+            .map(|(_, b)| &**b)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Source, &dyn PackageBackend)> {
+        self.backends.iter().map(|(s, b)| (*s, &**b))
+    }
+
+    /// Runs `f` on every backend in registration order, so pacman goes first. One that
+    /// fails doesn't stop the others; the first error is returned. Cancelling stops at once.
+    fn each(&self, mut f: impl FnMut(Source, &dyn PackageBackend) -> Result<()>) -> Result<()> {
+        let mut first = None;
+        for (source, backend) in self.iter() {
+            match f(source, backend) {
+                Ok(()) => {}
+                Err(Error::Cancelled) => return Err(Error::Cancelled),
+                Err(e) => {
+                    first.get_or_insert(e);
+                }
+            }
+        }
+        first.map_or(Ok(()), Err)
+    }
+}
+
+/// Jobs waiting for the `Executor`, drained front to back. Unlike a channel, jobs that
+/// haven't started can still be reordered; clones share the same queue.
+#[derive(Clone, Default)]
+pub struct JobQueue {
+    inner: Arc<(Mutex<Pending>, Condvar)>,
+}
+
+#[derive(Default)]
+struct Pending {
+    jobs: VecDeque<Job>,
+    closed: bool,
+}
+
+impl JobQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Jobs pushed after `close` are dropped.
+    pub fn push(&self, job: Job) {
+        let (pending, ready) = &*self.inner;
+        let mut pending = pending.lock();
+        if !pending.closed {
+            pending.jobs.push_back(job);
+            ready.notify_one();
+        }
+    }
+
+    /// Drops the jobs that haven't started and lets the `Executor` exit once its current
+    /// job finishes.
+    pub fn close(&self) {
+        let (pending, ready) = &*self.inner;
+        let mut pending = pending.lock();
+        pending.closed = true;
+        pending.jobs.clear();
+        ready.notify_all();
+    }
+
+    /// Blocks until a job is available; `None` once closed.
+    fn pop(&self) -> Option<Job> {
+        let (pending, ready) = &*self.inner;
+        let mut pending = pending.lock();
+        loop {
+            if pending.closed {
+                return None;
+            }
+            if let Some(job) = pending.jobs.pop_front() {
+                return Some(job);
+            }
+            ready.wait(&mut pending);
+        }
+    }
+
+    /// Moves a pending job one place towards the front. Returns false if it already
+    /// started (or is first).
+    pub fn prioritize(&self, job_id: u64) -> bool {
+        let jobs = &mut self.inner.0.lock().jobs;
+        match jobs.iter().position(|j| j.id == job_id) {
+            Some(i) if i > 0 => {
+                jobs.swap(i, i - 1);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Moves a pending job one place towards the back.
+    pub fn deprioritize(&self, job_id: u64) -> bool {
+        let jobs = &mut self.inner.0.lock().jobs;
+        match jobs.iter().position(|j| j.id == job_id) {
+            Some(i) if i + 1 < jobs.len() => {
+                jobs.swap(i, i + 1);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Takes a job out before it starts. `None` if it already started (or never existed).
+    pub fn remove(&self, job_id: u64) -> Option<Job> {
+        let jobs = &mut self.inner.0.lock().jobs;
+        let i = jobs.iter().position(|j| j.id == job_id)?;
+        jobs.remove(i)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.0.lock().jobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Snapshot of the jobs that haven't started, in run order.
+    pub fn pending(&self) -> Vec<Job> {
+        self.inner.0.lock().jobs.iter().cloned().collect()
+    }
+}
+
 static TXN_MUTEX: Mutex<()> = Mutex::new(());
 
+/// How the jobs an `Executor` ran so far ended.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct JobCounts {
+    pub completed: u64,
+    pub failed: u64,
+    pub cancelled: u64,
+}
+
+/// Sent to `ExecutorHandle::subscribe` receivers whenever a job starts or ends.
+#[derive(Clone, Debug)]
+pub struct ExecutorStatus {
+    pub current: Option<Job>,
+    pub pending: usize,
+    pub counts: JobCounts,
+}
+
+/// What the executor thread shares with its handle.
+#[derive(Default)]
+struct ExecutorState {
+    current: Option<Job>,
+    counts: JobCounts,
+    subscribers: Vec<chan::Sender<ExecutorStatus>>,
+}
+
+impl ExecutorState {
+    fn publish(&mut self, pending: usize) {
+        let status = ExecutorStatus {
+            current: self.current.clone(),
+            pending,
+            counts: self.counts,
+        };
+        // Dropped receivers unsubscribe
+        self.subscribers
+            .retain(|tx| tx.send(status.clone()).is_ok());
+    }
+}
+
+/// Observes and controls a running `Executor`, for the GUI as well as headless embedders.
+pub struct ExecutorHandle {
+    jobs: JobQueue,
+    state: Arc<Mutex<ExecutorState>>,
+    thread: std::thread::JoinHandle<()>,
+}
+
+impl ExecutorHandle {
+    /// Jobs that haven't started, in run order.
+    pub fn pending(&self) -> Vec<Job> {
+        self.jobs.pending()
+    }
+
+    /// The job running now, if any.
+    pub fn current(&self) -> Option<Job> {
+        self.state.lock().current.clone()
+    }
+
+    pub fn counts(&self) -> JobCounts {
+        self.state.lock().counts
+    }
+
+    /// A status snapshot after every job start and end, starting with the current one.
+    pub fn subscribe(&self) -> chan::Receiver<ExecutorStatus> {
+        let (tx, rx) = chan::unbounded();
+        let mut state = self.state.lock();
+        state.subscribers.push(tx);
+        state.publish(self.jobs.len());
+        rx
+    }
+
+    /// Drops a queued job, or asks the running one to stop. False if neither matches.
+    pub fn cancel(&self, job_id: u64) -> bool {
+        if self.jobs.remove(job_id).is_some() {
+            self.state.lock().publish(self.jobs.len());
+            return true;
+        }
+        match &self.state.lock().current {
+            Some(job) if job.id == job_id => {
+                job.cancel.cancel();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Drops the queued jobs and waits for the running one to finish.
+    pub fn shutdown(self) {
+        self.jobs.close();
+        let _ = self.thread.join();
+    }
+}
+
 pub struct Executor {
-    repo: Arc<dyn PackageBackend>,
-    aur: Arc<dyn PackageBackend>,
+    backends: BackendRegistry,
     tx_prog: chan::Sender<Progress>,
     tx_evt: chan::Sender<Event>,
-    rx_jobs: chan::Receiver<Job>,
+    jobs: JobQueue,
 }
 
 impl Executor {
     pub fn new(
-        repo: Arc<dyn PackageBackend>,
-        aur: Arc<dyn PackageBackend>,
+        backends: BackendRegistry,
         tx_prog: chan::Sender<Progress>,
         tx_evt: chan::Sender<Event>,
-        rx_jobs: chan::Receiver<Job>,
+        jobs: JobQueue,
     ) -> Self {
         Self {
-            repo,
-            aur,
+            backends,
             tx_prog,
             tx_evt,
-            rx_jobs,
+            jobs,
         }
     }
 
-    pub fn run(self) {
-        std::thread::spawn(move || {
-            while let Ok(job) = self.rx_jobs.recv() {
+    /// Runs jobs on a background thread until the queue is closed or the UI stops
+    /// listening.
+    pub fn run(self) -> ExecutorHandle {
+        let state = Arc::new(Mutex::new(ExecutorState::default()));
+        let jobs = self.jobs.clone();
+        let shared = state.clone();
+        let thread = std::thread::spawn(move || {
+            while let Some(job) = self.jobs.pop() {
+                {
+                    let mut state = shared.lock();
+                    state.current = Some(job.clone());
+                    state.publish(self.jobs.len());
+                }
                 let sink = self.tx_prog.clone();
                 let tx_evt = self.tx_evt.clone();
                 let cancel = job.cancel.clone();
-                let send = |p: Progress| {
-                    let _ = sink.send(p);
-                };
+                // False once the receiver is dropped, i.e. the app is shutting down
+                let send = |p: Progress| sink.send(p).is_ok();
 
-                let repo = &self.repo;
-                let aur = &self.aur;
-                let pick = |payload: &JobPayload| -> &dyn PackageBackend {
-                    match payload {
-                        JobPayload::Package(id) if id.source == Source::Aur => &*self.aur,
-                        _ => &*self.repo,
+                let backends = &self.backends;
+                let pick = |payload: &JobPayload| -> Result<&dyn PackageBackend> {
+                    let source = match payload {
+                        JobPayload::Package(id)
+                        | JobPayload::Details { id, .. }
+                        | JobPayload::Version { id, .. } => id.source,
+                        // Other package-less jobs (RefreshFiles, cache cleaning, ...) are
+                        // pacman's
+                        _ => Source::Repo,
+                    };
+                    backends.get(source).ok_or_else(|| {
+                        Error::Internal(format!("no backend registered for {}", source.label()))
+                    })
+                };
+                // Each backend gets the part of a `Skip` payload naming its own packages
+                let share = |source: Source| -> Vec<PackageId> {
+                    match &job.payload {
+                        JobPayload::Skip(ids) => ids
+                            .iter()
+                            .filter(|id| id.source == source)
+                            .cloned()
+                            .collect(),
+                        _ => vec![],
                     }
                 };
 
-                send(Progress {
+                if !send(Progress {
                     job_id: job.id,
                     stage: Stage::Queued,
                     percent: None,
                     bytes: None,
                     log: None,
                     warning: false,
-                });
+                }) {
+                    break;
+                }
 
                 let run_job = || -> Result<()> {
                     match job.kind {
-                        JobKind::Refresh => pick(&job.payload).refresh(&sink, &cancel),
-                        JobKind::Search => {
+                        JobKind::Refresh => backends.each(|_, b| b.refresh(&sink, &cancel)),
+                        JobKind::RefreshFiles => pick(&job.payload)?.refresh_files(&sink, &cancel),
+                        JobKind::SearchFile => {
+                            let path = if let JobPayload::Query(q) = &job.payload {
+                                q.trim().to_string()
+                            } else {
+                                String::new()
+                            };
+                            let mut items: Vec<PackageSummary> = Vec::new();
+                            for (source, backend) in backends.iter() {
+                                match backend.search_file(&path, &sink, &cancel) {
+                                    Ok(mut v) => items.append(&mut v),
+                                    Err(Error::FilesDbMissing) => {
+                                        let _ = tx_evt.send(Event::FilesDbNeedsSync);
+                                    }
+                                    Err(e) => {
+                                        let _ = sink.send(Progress {
+                                            job_id: job.id,
+                                            stage: Stage::Searching,
+                                            percent: None,
+                                            bytes: None,
+                                            log: Some(format!(
+                                                "{} file search failed: {e}",
+                                                source.label()
+                                            )),
+                                            warning: true,
+                                        });
+                                    }
+                                }
+                            }
+                            items.sort_by(|a, b| a.id.name.cmp(&b.id.name));
+                            tx_evt
+                                .send(Event::FileSearchResults { path, items })
+                                .map_err(|e| Error::Internal(e.to_string()))?;
+                            Ok(())
+                        }
+                        JobKind::Search | JobKind::SearchInstalled => {
+                            let installed_only = job.kind == JobKind::SearchInstalled;
                             let q = if let JobPayload::Query(q) = &job.payload {
                                 q.trim().to_string()
                             } else {
@@ -232,6 +1522,8 @@ impl Executor {
                                 let _ = tx_evt.send(Event::SearchResults {
                                     query: q,
                                     items: vec![],
+                                    installed_only,
+                                    generation: job.id,
                                 });
                                 return Ok(());
                             }
@@ -239,58 +1531,300 @@ impl Executor {
                             let mut any_ok = false;
                             let mut items: Vec<PackageSummary> = Vec::new();
 
-                            // Repo
-                            match repo.search(&q, &sink, &cancel) {
-                                Ok(mut v) => {
-                                    items.append(&mut v);
-                                    any_ok = true;
+                            for (source, backend) in backends.iter() {
+                                // Superseded by a newer search; skip the slower sources
+                                if cancel.is_cancelled() {
+                                    return Err(Error::Cancelled);
                                 }
-                                Err(e) => {
-                                    let _ = sink.send(Progress {
-                                        job_id: job.id,
-                                        stage: Stage::Searching,
-                                        percent: None,
-                                        bytes: None,
-                                        log: Some(format!("repo search failed: {e}")),
-                                        warning: true,
-                                    });
+                                let found = if installed_only {
+                                    backend.search_installed(&q, &sink, &cancel)
+                                } else {
+                                    backend.search(&q, &sink, &cancel)
+                                };
+                                match found {
+                                    Ok(mut v) => {
+                                        let _ = tx_evt.send(Event::SearchResultsPartial {
+                                            query: q.clone(),
+                                            source,
+                                            items: v.clone(),
+                                            generation: job.id,
+                                        });
+                                        items.append(&mut v);
+                                        any_ok = true;
+                                    }
+                                    Err(Error::SyncDbMissing) => {
+                                        let _ = tx_evt.send(Event::SyncDbMissing);
+                                    }
+                                    Err(Error::Cancelled) => return Err(Error::Cancelled),
+                                    Err(e) => {
+                                        let _ = sink.send(Progress {
+                                            job_id: job.id,
+                                            stage: Stage::Searching,
+                                            percent: None,
+                                            bytes: None,
+                                            log: Some(format!(
+                                                "{} search failed: {e}",
+                                                source.label()
+                                            )),
+                                            warning: true,
+                                        });
+                                    }
                                 }
                             }
 
-                            // AUR
-                            match aur.search(&q, &sink, &cancel) {
-                                Ok(mut v) => {
-                                    items.append(&mut v);
-                                    any_ok = true;
+                            // If all failed, bubble a failure to the final Progress; otherwise continue.
+                            if !any_ok {
+                                return Err(Error::Alpm("all backends failed".into()));
+                            }
+
+                            // Same-named packages from different sources end up adjacent
+                            items.sort_by(|a, b| {
+                                (&a.id.name, a.id.source).cmp(&(&b.id.name, b.id.source))
+                            });
+                            tx_evt
+                                .send(Event::SearchResults {
+                                    query: q,
+                                    items,
+                                    installed_only,
+                                    generation: job.id,
+                                })
+                                .map_err(|e| Error::Internal(e.to_string()))?;
+                            Ok(())
+                        }
+                        JobKind::Details => {
+                            let (id, force) = match &job.payload {
+                                JobPayload::Package(id) => (Some(id), false),
+                                JobPayload::Details { id, force } => (Some(id), *force),
+                                _ => (None, false),
+                            };
+                            if let Some(id) = id {
+                                let backend = pick(&job.payload)?;
+                                let det = if force {
+                                    backend.details_fresh(id, &sink, &cancel)?
+                                } else {
+                                    backend.details(id, &sink, &cancel)?
+                                };
+                                tx_evt
+                                    .send(Event::Details {
+                                        item: Box::new(det),
+                                    })
+                                    .map_err(|e| Error::Internal(e.to_string()))?;
+                            }
+                            Ok(())
+                        }
+                        JobKind::PlanUpgradeAll => {
+                            let mut plan = TransactionPlan::default();
+                            backends.each(|source, b| {
+                                plan.merge(b.plan_upgrade_all(&share(source), &sink, &cancel)?);
+                                Ok(())
+                            })?;
+                            tx_evt
+                                .send(Event::UpgradePlan {
+                                    plan: Box::new(plan),
+                                })
+                                .map_err(|e| Error::Internal(e.to_string()))?;
+                            Ok(())
+                        }
+                        JobKind::PlanCleanCache => {
+                            let plan = pick(&JobPayload::None)?.plan_clean_cache(&sink, &cancel)?;
+                            tx_evt
+                                .send(Event::CacheCleanPlan { plan })
+                                .map_err(|e| Error::Internal(e.to_string()))?;
+                            Ok(())
+                        }
+                        JobKind::CleanCache => {
+                            let _g = TXN_MUTEX.lock();
+                            pick(&JobPayload::None)?.clean_cache(&sink, &cancel)
+                        }
+                        JobKind::ClearBuildCache => {
+                            // Not while a build is working in one of them
+                            let _g = TXN_MUTEX.lock();
+                            for (_, backend) in backends.iter() {
+                                backend.clear_build_cache(&sink, &cancel)?;
+                            }
+                            Ok(())
+                        }
+                        JobKind::PrefetchDetails => {
+                            let JobPayload::Packages(ids) = &job.payload else {
+                                return Ok(());
+                            };
+                            let mut items = Vec::new();
+                            for (source, backend) in backends.iter() {
+                                let mine: Vec<PackageId> = ids
+                                    .iter()
+                                    .filter(|id| id.source == source)
+                                    .cloned()
+                                    .collect();
+                                if mine.is_empty() {
+                                    continue;
                                 }
-                                Err(e) => {
+                                match backend.details_batch(&mine, &sink, &cancel) {
+                                    Ok(mut v) => items.append(&mut v),
+                                    Err(Error::Cancelled) => return Err(Error::Cancelled),
+                                    Err(e) => {
+                                        let _ = sink.send(Progress {
+                                            job_id: job.id,
+                                            stage: Stage::Resolving,
+                                            percent: None,
+                                            bytes: None,
+                                            log: Some(format!(
+                                                "{} details prefetch failed: {e}",
+                                                source.label()
+                                            )),
+                                            warning: true,
+                                        });
+                                    }
+                                }
+                            }
+                            tx_evt
+                                .send(Event::DetailsBatch { items })
+                                .map_err(|e| Error::Internal(e.to_string()))?;
+                            Ok(())
+                        }
+                        JobKind::History => {
+                            if let JobPayload::Package(id) = &job.payload {
+                                let entries = pick(&job.payload)?.history(id, &sink, &cancel)?;
+                                tx_evt
+                                    .send(Event::History {
+                                        id: id.clone(),
+                                        entries,
+                                    })
+                                    .map_err(|e| Error::Internal(e.to_string()))?;
+                            }
+                            Ok(())
+                        }
+                        JobKind::BuildDeps => {
+                            if let JobPayload::Package(id) = &job.payload {
+                                let deps = pick(&job.payload)?.build_deps(id, &sink, &cancel)?;
+                                tx_evt
+                                    .send(Event::BuildDeps {
+                                        id: id.clone(),
+                                        deps,
+                                    })
+                                    .map_err(|e| Error::Internal(e.to_string()))?;
+                            }
+                            Ok(())
+                        }
+                        JobKind::Sibling => {
+                            if let JobPayload::Package(id) = &job.payload {
+                                let sibling = pick(&job.payload)?.sibling(id, &sink, &cancel)?;
+                                tx_evt
+                                    .send(Event::Sibling {
+                                        id: id.clone(),
+                                        sibling: sibling.map(Box::new),
+                                    })
+                                    .map_err(|e| Error::Internal(e.to_string()))?;
+                            }
+                            Ok(())
+                        }
+                        JobKind::ShowBuildFiles => {
+                            if let JobPayload::Package(id) = &job.payload {
+                                let files = pick(&job.payload)?.build_files(id, &sink, &cancel)?;
+                                tx_evt
+                                    .send(Event::BuildFiles {
+                                        id: id.clone(),
+                                        files,
+                                    })
+                                    .map_err(|e| Error::Internal(e.to_string()))?;
+                            }
+                            Ok(())
+                        }
+                        JobKind::WhoRequires => {
+                            if let JobPayload::Package(id) = &job.payload {
+                                let tree = pick(&job.payload)?.who_requires(id, &sink, &cancel)?;
+                                tx_evt
+                                    .send(Event::WhoRequires {
+                                        id: id.clone(),
+                                        tree,
+                                    })
+                                    .map_err(|e| Error::Internal(e.to_string()))?;
+                            }
+                            Ok(())
+                        }
+                        JobKind::CachedVersions => {
+                            if let JobPayload::Package(id) = &job.payload {
+                                let versions =
+                                    pick(&job.payload)?.cached_versions(id, &sink, &cancel)?;
+                                tx_evt
+                                    .send(Event::CachedVersions {
+                                        id: id.clone(),
+                                        versions,
+                                    })
+                                    .map_err(|e| Error::Internal(e.to_string()))?;
+                            }
+                            Ok(())
+                        }
+                        JobKind::Downgrade => {
+                            let _g = TXN_MUTEX.lock();
+                            if let JobPayload::Version { id, version } = &job.payload {
+                                pick(&job.payload)?.downgrade(id, version, &sink, &cancel)
+                            } else {
+                                Ok(())
+                            }
+                        }
+                        JobKind::RemovePreview => {
+                            if let JobPayload::Package(id) = &job.payload {
+                                let preview =
+                                    pick(&job.payload)?.remove_preview(id, &sink, &cancel)?;
+                                for b in &preview.breaks {
                                     let _ = sink.send(Progress {
                                         job_id: job.id,
-                                        stage: Stage::Searching,
+                                        stage: Stage::Resolving,
                                         percent: None,
                                         bytes: None,
-                                        log: Some(format!("AUR search failed: {e}")),
+                                        log: Some(format!("cannot remove {}: {b}", id.name)),
                                         warning: true,
                                     });
                                 }
+                                tx_evt
+                                    .send(Event::RemovePreview {
+                                        id: id.clone(),
+                                        preview,
+                                    })
+                                    .map_err(|e| Error::Internal(e.to_string()))?;
                             }
-
-                            // If both failed, bubble a failure to the final Progress; otherwise continue.
-                            if !any_ok {
-                                return Err(Error::Alpm("all backends failed".into()));
+                            Ok(())
+                        }
+                        JobKind::CheckRebuilds => {
+                            let mut items: Vec<PackageSummary> = Vec::new();
+                            for (source, backend) in backends.iter() {
+                                match backend.rebuild_candidates(&sink, &cancel) {
+                                    Ok(mut v) => items.append(&mut v),
+                                    Err(Error::Cancelled) => return Err(Error::Cancelled),
+                                    Err(e) => {
+                                        let _ = sink.send(Progress {
+                                            job_id: job.id,
+                                            stage: Stage::Verifying,
+                                            percent: None,
+                                            bytes: None,
+                                            log: Some(format!(
+                                                "{} rebuild check failed: {e}",
+                                                source.label()
+                                            )),
+                                            warning: true,
+                                        });
+                                    }
+                                }
                             }
-
-                            items.sort_by(|a, b| a.id.name.cmp(&b.id.name));
                             tx_evt
-                                .send(Event::SearchResults { query: q, items })
+                                .send(Event::RebuildsNeeded { items })
                                 .map_err(|e| Error::Internal(e.to_string()))?;
                             Ok(())
                         }
-                        JobKind::Details => {
+                        JobKind::Files => {
                             if let JobPayload::Package(id) = &job.payload {
-                                let det = pick(&job.payload).details(id, &sink, &cancel)?;
+                                let files = match pick(&job.payload)?.files(id, &sink, &cancel) {
+                                    Err(Error::FilesDbMissing) => {
+                                        let _ = tx_evt.send(Event::FilesDbNeedsSync);
+                                        return Ok(());
+                                    }
+                                    files => files?,
+                                };
                                 tx_evt
-                                    .send(Event::Details { item: det })
+                                    .send(Event::Files {
+                                        id: id.clone(),
+                                        files,
+                                    })
                                     .map_err(|e| Error::Internal(e.to_string()))?;
                             }
                             Ok(())
@@ -298,46 +1832,61 @@ impl Executor {
                         JobKind::Install => {
                             let _g = TXN_MUTEX.lock();
                             if let JobPayload::Package(id) = &job.payload {
-                                pick(&job.payload).install(id, &sink, &cancel)
+                                pick(&job.payload)?.install(id, &sink, &cancel)
                             } else {
                                 Ok(())
                             }
                         }
+                        JobKind::InstallMany | JobKind::RemoveMany => {
+                            let _g = TXN_MUTEX.lock();
+                            let ids = match &job.payload {
+                                JobPayload::Packages(ids) => ids.as_slice(),
+                                _ => &[],
+                            };
+                            for (source, backend) in backends.iter() {
+                                let mine: Vec<PackageId> = ids
+                                    .iter()
+                                    .filter(|id| id.source == source)
+                                    .cloned()
+                                    .collect();
+                                if mine.is_empty() {
+                                    continue;
+                                }
+                                if job.kind == JobKind::InstallMany {
+                                    backend.install_many(&mine, &sink, &cancel)?;
+                                } else {
+                                    backend.remove_many(&mine, &sink, &cancel)?;
+                                }
+                            }
+                            Ok(())
+                        }
                         JobKind::Remove => {
                             let _g = TXN_MUTEX.lock();
                             if let JobPayload::Package(id) = &job.payload {
-                                pick(&job.payload).remove(id, &sink, &cancel)
+                                pick(&job.payload)?.remove(id, &sink, &cancel)
                             } else {
                                 Ok(())
                             }
                         }
                         JobKind::Upgrades => {
-                            // Collect from both repo and AUR, but don’t fail the whole job
+                            // Collect from every backend, but don’t fail the whole job
                             let mut items: Vec<PackageSummary> = Vec::new();
-                            match repo.upgrades(&sink, &cancel) {
-                                Ok(mut v) => items.append(&mut v),
-                                Err(e) => {
-                                    let _ = sink.send(Progress {
-                                        job_id: job.id,
-                                        stage: Stage::Verifying,
-                                        percent: None,
-                                        bytes: None,
-                                        log: Some(format!("repo upgrades failed: {e}")),
-                                        warning: true,
-                                    });
-                                }
-                            }
-                            match aur.upgrades(&sink, &cancel) {
-                                Ok(mut v) => items.append(&mut v),
-                                Err(e) => {
-                                    let _ = sink.send(Progress {
-                                        job_id: job.id,
-                                        stage: Stage::Verifying,
-                                        percent: None,
-                                        bytes: None,
-                                        log: Some(format!("AUR upgrades failed: {e}")),
-                                        warning: true,
-                                    });
+                            for (source, backend) in backends.iter() {
+                                match backend.upgrades(&sink, &cancel) {
+                                    Ok(mut v) => items.append(&mut v),
+                                    Err(e) => {
+                                        let _ = sink.send(Progress {
+                                            job_id: job.id,
+                                            stage: Stage::Verifying,
+                                            percent: None,
+                                            bytes: None,
+                                            log: Some(format!(
+                                                "{} upgrades failed: {e}",
+                                                source.label()
+                                            )),
+                                            warning: true,
+                                        });
+                                    }
                                 }
                             }
                             // Sort A–Z for stability; UI can re-sort
@@ -347,49 +1896,248 @@ impl Executor {
                                 .map_err(|e| Error::Internal(e.to_string()))?;
                             Ok(())
                         }
+                        JobKind::Orphans => {
+                            let mut items: Vec<PackageSummary> = Vec::new();
+                            for (source, backend) in backends.iter() {
+                                match backend.orphans(&sink, &cancel) {
+                                    Ok(mut v) => items.append(&mut v),
+                                    Err(e) => {
+                                        let _ = sink.send(Progress {
+                                            job_id: job.id,
+                                            stage: Stage::Verifying,
+                                            percent: None,
+                                            bytes: None,
+                                            log: Some(format!(
+                                                "{} orphans failed: {e}",
+                                                source.label()
+                                            )),
+                                            warning: true,
+                                        });
+                                    }
+                                }
+                            }
+                            items.sort_by(|a, b| a.id.name.cmp(&b.id.name));
+                            tx_evt
+                                .send(Event::Orphans { items })
+                                .map_err(|e| Error::Internal(e.to_string()))?;
+                            Ok(())
+                        }
                         JobKind::Upgrade => {
                             let _g = TXN_MUTEX.lock();
                             if let JobPayload::Package(id) = &job.payload {
-                                pick(&job.payload).upgrade(id, &sink, &cancel)
+                                pick(&job.payload)?.upgrade(id, &sink, &cancel)
                             } else {
                                 Ok(())
                             }
                         }
                         JobKind::UpgradeAll => {
                             let _g = TXN_MUTEX.lock();
-                            // Minimal: perform repo full system upgrade; AUR can be expanded later.
-                            repo.upgrade_all(&sink, &cancel)?;
-                            // If you want AUR mass-upgrade later, we can iterate aur.upgrades() and call aur.upgrade(..).
-                            Ok(())
+                            backends.each(|source, b| b.upgrade_all(&share(source), &sink, &cancel))
                         }
                     }
                 };
 
-                let res = run_job();
+                // Cancelled while still queued
+                let res = if cancel.is_cancelled() {
+                    Err(Error::Cancelled)
+                } else {
+                    run_job()
+                };
+                // Even a failed transaction may have changed some packages.
+                if matches!(
+                    job.kind,
+                    JobKind::Install
+                        | JobKind::InstallMany
+                        | JobKind::Remove
+                        | JobKind::RemoveMany
+                        | JobKind::Upgrade
+                        | JobKind::UpgradeAll
+                        | JobKind::Downgrade
+                ) {
+                    for (_, b) in backends.iter() {
+                        b.invalidate_installed_cache();
+                    }
+                }
                 if res.is_ok() {
                     match job.kind {
-                        JobKind::Install
+                        JobKind::Refresh
+                        | JobKind::Install
+                        | JobKind::InstallMany
                         | JobKind::Remove
+                        | JobKind::RemoveMany
                         | JobKind::Upgrade
-                        | JobKind::UpgradeAll => {
+                        | JobKind::UpgradeAll
+                        | JobKind::Downgrade => {
                             let _ = tx_evt.send(Event::SystemChanged);
                         }
                         _ => {}
                     }
                 }
-                send(Progress {
+                // A locked db gets its own retry notice in the UI instead
+                match &res {
+                    Ok(()) | Err(Error::Cancelled | Error::AuthCancelled | Error::DbLocked) => {}
+                    Err(e) => {
+                        let _ = tx_evt.send(Event::JobFailed {
+                            job_id: job.id,
+                            kind: job.kind,
+                            message: e.to_string(),
+                        });
+                    }
+                }
+                let (stage, warning) = match &res {
+                    Ok(()) => (Stage::Finished, false),
+                    Err(Error::Cancelled | Error::AuthCancelled) => (Stage::Cancelled, false),
+                    Err(_) => (Stage::Failed, true),
+                };
+                {
+                    let mut state = shared.lock();
+                    state.current = None;
+                    match stage {
+                        Stage::Finished => state.counts.completed += 1,
+                        Stage::Cancelled => state.counts.cancelled += 1,
+                        _ => state.counts.failed += 1,
+                    }
+                    state.publish(self.jobs.len());
+                }
+                if !send(Progress {
                     job_id: job.id,
-                    stage: if res.is_ok() {
-                        Stage::Finished
-                    } else {
-                        Stage::Failed
-                    },
+                    stage,
                     percent: Some(1.0),
                     bytes: None,
                     log: res.as_ref().err().map(|e| e.to_string()),
-                    warning: res.is_err(),
-                });
+                    warning,
+                }) {
+                    break;
+                }
             }
         });
+        ExecutorHandle {
+            jobs,
+            state,
+            thread,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// libalpm's own cases (test/util/vercmptest.sh), each also checked reversed.
+    #[test]
+    fn vercmp_matches_libalpm() {
+        use std::cmp::Ordering::{Equal as E, Greater as G, Less as L};
+        let cases = [
+            ("1.5.0", "1.5.0", E),
+            ("1.5.1", "1.5.0", G),
+            ("1.5.1", "1.5", G),
+            ("1.5.0-1", "1.5.0-1", E),
+            ("1.5.0-1", "1.5.0-2", L),
+            ("1.5.0-1", "1.5.1-1", L),
+            ("1.5.0-2", "1.5.1-1", L),
+            ("1.5-1", "1.5.1-1", L),
+            ("1.5-2", "1.5.1-2", L),
+            // The release counts only when both sides have one
+            ("1.5", "1.5-1", E),
+            ("1.1-1", "1.1", E),
+            ("1.0-1", "1.1", L),
+            ("1.1-1", "1.0", G),
+            // Letters after a version make a pre-release
+            ("1.5b-1", "1.5-1", L),
+            ("1.5b", "1.5", L),
+            ("1.5b", "1.5.1", L),
+            ("1.0a", "1.0", L),
+            ("1.0a", "1.0alpha", L),
+            ("1.0alpha", "1.0b", L),
+            ("1.0b", "1.0beta", L),
+            ("1.0beta", "1.0rc", L),
+            ("1.0rc", "1.0", L),
+            ("1.0rc1", "1.0", L),
+            ("1.5.a", "1.5", G),
+            ("1.5.b", "1.5.a", G),
+            ("1.5.1", "1.5.b", G),
+            ("1.5.b-1", "1.5.b", E),
+            ("1.5-1", "1.5.b", L),
+            // Separators
+            ("2.0", "2_0", E),
+            ("2.0_a", "2_0.a", E),
+            ("2.0a", "2.0.a", L),
+            ("2___a", "2_a", G),
+            ("1..0", "1.0", G),
+            // Leading zeros
+            ("1.01", "1.1", E),
+            ("1.010", "1.9", G),
+            ("1.0001-1", "1.1-1", E),
+            // Epochs
+            ("0:1.0", "0:1.0", E),
+            ("0:1.0", "0:1.1", L),
+            ("1:1.0", "0:1.0", G),
+            ("1:1.0", "0:1.1", G),
+            ("1:1.0", "2:1.1", L),
+            ("1:1.0", "0:1.0-1", G),
+            ("1:1.0-1", "0:1.1-1", G),
+            ("0:1.0", "1.0", E),
+            (":1.0", "1.0", E),
+            ("0:1.0", "1.1", L),
+            ("0:1.1", "1.0", G),
+            ("1:1.0", "1.0", G),
+            ("1:1.0", "1.1", G),
+            ("1:1.1", "1.1", G),
+        ];
+        for (a, b, want) in cases {
+            assert_eq!(vercmp(a, b), want, "{a} vs {b}");
+            assert_eq!(vercmp(b, a), want.reverse(), "{b} vs {a}");
+        }
+    }
+
+    /// Runs `script`, cancelling once it prints "ready".
+    fn cancel_when_ready(script: &str) -> (std::process::ExitStatus, bool, std::time::Duration) {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", script]);
+        let (sink, _rx) = chan::unbounded();
+        let cancel = CancelToken::new();
+        let (tx, ready) = chan::unbounded();
+        let canceller = {
+            let cancel = cancel.clone();
+            std::thread::spawn(move || {
+                ready.recv().ok();
+                cancel.cancel();
+            })
+        };
+        let started = std::time::Instant::now();
+        let on_out = move |l: String| {
+            if l == "ready" {
+                tx.send(()).ok();
+            }
+        };
+        let (status, cancelled) =
+            run_piped(cmd, &sink, &Stage::Installing, &cancel, on_out, |_| {}).unwrap();
+        canceller.join().unwrap();
+        (status, cancelled, started.elapsed())
+    }
+
+    #[test]
+    fn split_lines_breaks_on_carriage_returns() {
+        let mut lines = Vec::new();
+        split_lines(&b"a 10%\ra 50%\ra 100%\r\nb\n\nc"[..], |l| lines.push(l));
+        assert_eq!(lines, ["a 10%", "a 50%", "a 100%", "b", "", "c"]);
+    }
+
+    #[test]
+    fn run_piped_kills_a_command_that_ignores_sigterm() {
+        let (status, cancelled, took) = cancel_when_ready("trap '' TERM; echo ready; sleep 60");
+        assert!(cancelled);
+        assert!(!status.success());
+        assert!(took < STOP_GRACE * 3);
+    }
+
+    #[test]
+    fn run_piped_lets_a_committing_pacman_finish() {
+        let (status, cancelled, took) = cancel_when_ready(
+            "trap '' TERM; echo ':: Processing package changes...'; echo ready; sleep 7",
+        );
+        assert!(cancelled);
+        assert!(status.success());
+        assert!(took > STOP_GRACE);
     }
 }